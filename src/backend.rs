@@ -0,0 +1,693 @@
+// --- 検索の実行・ページ読み込みとバックグラウンド検索の世代管理 ---
+//
+// Everythingへの問い合わせを SearchBackend トレイトの裏に隠し、実際のEverythingなしでも
+// 検索の流れ（古い結果の破棄、ページの読み込みなど）をテストできるようにする。
+
+use crate::groups::FolderGroupList;
+use crate::search::{SearchRequest, SortKey};
+use everything_sdk::ergo::{global, EverythingSearcher, RequestFlags};
+
+use std::cmp::Ordering;
+use std::time::{Duration, Instant};
+
+/// 検索結果のファイル情報を格納する構造体
+#[derive(Debug, Clone)]
+pub struct FileResult {
+    pub name: String,
+    /// 含まれるフォルダ（「フォルダ」列に表示する）
+    pub path: String,
+    /// フルパス（Everythingが返した表記のまま）
+    /// ドライブ直下（`C:\`）やUNCパスで区切りが重ならないよう、`path` と `name` を結合し直さずにこちらを使う
+    pub full_path: String,
+    pub size: u64,
+    pub modified_date: u64,
+    pub created_date: u64,
+    pub highlighted_name: String,
+    pub highlighted_path: String,
+    pub is_folder: bool,
+}
+
+#[cfg(test)]
+impl FileResult {
+    /// テスト用のファイルの結果（フルパスはフォルダと名前をつないで作る）
+    pub fn test_file(path: &str, name: &str, size: u64) -> Self {
+        let full_path = if path.is_empty() || path.ends_with('\\') { format!("{}{}", path, name) } else { format!("{}\\{}", path, name) };
+        Self {
+            name: name.to_string(),
+            path: path.to_string(),
+            full_path,
+            size,
+            modified_date: 0,
+            created_date: 0,
+            highlighted_name: String::new(),
+            highlighted_path: String::new(),
+            is_folder: false,
+        }
+    }
+}
+
+/// 1回の問い合わせで得られた結果
+#[derive(Debug, Clone, Default)]
+pub struct SearchPage {
+    /// offset から最大 max 件の結果
+    pub results: Vec<FileResult>,
+    /// 条件に一致した総件数
+    pub total: u32,
+}
+
+/// 検索の問い合わせ先
+pub trait SearchBackend {
+    /// 条件に一致する結果のうち、offset から最大 max 件を返す
+    fn query(&mut self, request: &SearchRequest, offset: u32, max: u32) -> SearchPage;
+
+    /// インデックスの作成が完了しているか（作成中は結果が不完全）
+    fn is_index_loaded(&mut self) -> bool {
+        true
+    }
+
+    /// 問い合わせ先に接続できるか（Everythingが起動していなければ false）
+    fn is_available(&mut self) -> bool {
+        true
+    }
+}
+
+/// Everything SDK に問い合わせる検索バックエンド
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EverythingBackend;
+
+impl SearchBackend for EverythingBackend {
+    /// Everythingのロックはこの関数内でのみ保持する
+    fn query(&mut self, request: &SearchRequest, offset: u32, max: u32) -> SearchPage {
+        // 同順位を並べ直すときは、範囲を含むページ全体を問い合わせて並べ直してから範囲の分だけを返す
+        let (start, end) = if reorders_ties(request.sort_key, request.secondary_sort) {
+            tie_page_range(offset, max)
+        } else {
+            (offset, offset.saturating_add(max))
+        };
+        let mut guard = global().lock().unwrap();
+        let mut searcher = guard.searcher();
+        configure_searcher(&mut searcher, request, start, end - start);
+
+        let query_results = searcher.query();
+        let mut results = Vec::new();
+        for item in query_results.iter() {
+            results.push(FileResult {
+                name: item.filename().unwrap_or_default().to_string_lossy().to_string(),
+                path: item.path().unwrap_or_default().to_string_lossy().to_string(),
+                full_path: item.filepath().unwrap_or_default().to_string_lossy().to_string(),
+                size: item.size().unwrap_or(0),
+                modified_date: item.date_modified().unwrap_or(0),
+                created_date: item.date_created().unwrap_or(0),
+                highlighted_name: item.highlighted_filename().unwrap_or_default().to_string_lossy().to_string(),
+                highlighted_path: item.highlighted_path().unwrap_or_default().to_string_lossy().to_string(),
+                is_folder: item.is_folder(),
+            });
+        }
+        let total = query_results.total();
+        order_ties(&mut results, start as usize, total as usize, request.sort_key, request.secondary_sort);
+        let results = results.into_iter().skip((offset - start) as usize).take(max as usize).collect();
+        SearchPage { results, total }
+    }
+
+    fn is_index_loaded(&mut self) -> bool {
+        global().lock().unwrap().is_db_loaded().unwrap_or(true)
+    }
+
+    /// IPCで問い合わせられなければEverythingは起動していない
+    fn is_available(&mut self) -> bool {
+        global().lock().unwrap().is_db_loaded().is_ok()
+    }
+}
+
+/// Everythingの検索条件を設定する
+/// 初回検索とページ読み込みで条件が食い違わないよう、検索オプションの設定はすべてここで行う
+fn configure_searcher(searcher: &mut EverythingSearcher<'_>, request: &SearchRequest, offset: u32, max: u32) {
+    searcher.set_search(&request.query.text);
+    searcher.set_regex(request.query.regex);
+    // SDKの設定はグローバルに残るため、使わないオプションも明示的に戻す
+    searcher.set_match_case(false);
+    searcher.set_match_whole_word(false);
+    searcher.set_match_path(false);
+    searcher.set_sort(request.sort_key.sort_type(request.sort_descending));
+    searcher.set_offset(offset);
+    searcher.set_max(max);
+    searcher.set_request_flags(
+        RequestFlags::EVERYTHING_REQUEST_FILE_NAME | RequestFlags::EVERYTHING_REQUEST_PATH |
+        RequestFlags::EVERYTHING_REQUEST_FULL_PATH_AND_FILE_NAME |
+        RequestFlags::EVERYTHING_REQUEST_SIZE | RequestFlags::EVERYTHING_REQUEST_DATE_MODIFIED |
+        RequestFlags::EVERYTHING_REQUEST_DATE_CREATED |
+        RequestFlags::EVERYTHING_REQUEST_ATTRIBUTES |
+        RequestFlags::EVERYTHING_REQUEST_HIGHLIGHTED_FILE_NAME |
+        RequestFlags::EVERYTHING_REQUEST_HIGHLIGHTED_PATH
+    );
+}
+
+/// バックグラウンド検索の世代番号
+/// 入力が変わるたびに進め、完了時点で最新でない検索の結果は捨てる
+#[derive(Debug, Default)]
+pub struct SearchGeneration {
+    latest: u64,
+}
+
+impl SearchGeneration {
+    /// 世代を進め、新しい世代番号を返す
+    /// 実行中の検索はこれ以降すべて古い結果として扱われる
+    pub fn advance(&mut self) -> u64 {
+        self.latest += 1;
+        self.latest
+    }
+
+    /// 指定の世代が最新か
+    pub fn is_current(&self, generation: u64) -> bool {
+        generation == self.latest
+    }
+}
+
+/// バックグラウンドで実行した検索の結果
+#[derive(Debug)]
+pub struct SearchCompletion {
+    /// 検索を開始したときの世代番号
+    pub generation: u64,
+    /// 先頭ページの結果
+    pub page: SearchPage,
+    /// 検索時点でインデックスの作成が完了していたか
+    pub index_loaded: bool,
+    /// 問い合わせにかかった時間（描画は含まない）
+    pub elapsed: Duration,
+    /// フォルダごとにまとめた結果（グループ表示を指定した場合のみ）
+    pub folder_groups: Option<FolderGroupList>,
+}
+
+/// 検索結果をフォルダごとにまとめる指定
+/// まとめるための取得もワーカースレッドで行うよう、検索と一緒に渡す
+#[derive(Debug, Clone, Default)]
+pub struct GroupRequest {
+    /// 先頭からまとめる件数の上限
+    pub limit: usize,
+    /// 折りたたんだ状態にするフォルダ（検索し直しても状態を保つため）
+    pub collapsed_folders: Vec<String>,
+}
+
+/// 先頭ページを検索し、世代番号付きの結果を返す（ワーカースレッドから呼ぶ）
+/// `group` を指定した場合は、先頭ページに収まらない分も取得してフォルダごとにまとめる
+pub fn run_search(backend: &mut impl SearchBackend, generation: u64, request: &SearchRequest, max: u32, group: Option<&GroupRequest>) -> SearchCompletion {
+    let started = Instant::now();
+    let page = backend.query(request, 0, max);
+    let folder_groups = group.map(|group| {
+        let limit = group.limit.min(page.total as usize);
+        let results = if limit <= page.results.len() {
+            page.results[..limit].to_vec()
+        } else {
+            fetch_all(backend, request, (max as usize).max(1), limit, |_| true).unwrap_or_default()
+        };
+        FolderGroupList::new(results, &group.collapsed_folders)
+    });
+    let index_loaded = backend.is_index_loaded();
+    SearchCompletion { generation, page, index_loaded, elapsed: started.elapsed(), folder_groups }
+}
+
+/// 一度に読み込む件数の下限
+pub const MIN_PAGE_SIZE: usize = 100;
+/// 画面に見える行数に上乗せして読み込む行数（スクロールし始めてすぐ読み込みが走らないように）
+const PAGE_BUFFER_ROWS: usize = 50;
+
+/// リストに見えている行数から一度に読み込む件数を決める
+/// 背の高いウィンドウでも最初の画面が初回検索の結果だけで埋まるようにする
+pub fn page_size_for_rows(visible_rows: usize) -> usize {
+    (visible_rows + PAGE_BUFFER_ROWS).max(MIN_PAGE_SIZE)
+}
+
+/// 仮想リストビュー用に、検索結果を1ページ分だけ保持するキャッシュ
+#[derive(Debug)]
+pub struct ResultPages {
+    /// 一度に読み込む件数（初回検索の件数と一致）
+    page_size: usize,
+    /// 保持しているページの開始オフセット
+    offset: usize,
+    results: Vec<FileResult>,
+    /// 読み込む範囲の上限（表示上限。これ以降の行は問い合わせない）
+    limit: usize,
+}
+
+impl ResultPages {
+    pub fn new(page_size: usize) -> Self {
+        Self { page_size, offset: 0, results: Vec::new(), limit: usize::MAX }
+    }
+
+    /// 一度に読み込む件数
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// 一度に読み込む件数を変える（保持しているページはそのまま使い、次の読み込みから反映する）
+    pub fn set_page_size(&mut self, page_size: usize) {
+        self.page_size = page_size.max(1);
+    }
+
+    /// 読み込む範囲の上限を設定する（ページの途中で上限に達する場合は上限までだけを問い合わせる）
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    /// 保持しているページの開始オフセット
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// 保持しているページの結果
+    pub fn results(&self) -> &[FileResult] {
+        &self.results
+    }
+
+    /// 新しい検索の先頭ページで置き換える
+    pub fn reset(&mut self, first_page: Vec<FileResult>) {
+        self.offset = 0;
+        self.results = first_page;
+    }
+
+    /// 保持している結果を破棄する
+    pub fn clear(&mut self) {
+        self.reset(Vec::new());
+    }
+
+    /// 絶対インデックスの行を保持しているページから返せるか（false なら `get` が問い合わせる）
+    pub fn is_loaded(&self, index: usize) -> bool {
+        index >= self.offset && index - self.offset < self.results.len()
+    }
+
+    /// 絶対インデックスの結果を返す（上限以降の行は None）
+    /// 保持しているページになければ、その行を含むページを読み込んで置き換える
+    pub fn get(&mut self, backend: &mut impl SearchBackend, request: &SearchRequest, index: usize) -> Option<&FileResult> {
+        if index >= self.limit { return None; }
+        if !self.is_loaded(index) {
+            let page_start = index / self.page_size * self.page_size;
+            let count = self.page_size.min(self.limit - page_start);
+            self.results = backend.query(request, page_start as u32, count as u32).results;
+            self.offset = page_start;
+        }
+        self.results.get(index - self.offset)
+    }
+
+    /// `from` から `to` まで（両端を含む）の行をまとめて読み込む（上限以降の行は読み込まない）
+    /// 範囲がページの境界をまたぐ場合は `from` から始まる1ページとして問い合わせ、範囲がページより広ければ範囲全体を読み込む
+    pub fn prefetch(&mut self, backend: &mut impl SearchBackend, request: &SearchRequest, from: usize, to: usize) {
+        let to = to.min(self.limit.saturating_sub(1));
+        if from > to || (self.is_loaded(from) && self.is_loaded(to)) { return; }
+        let aligned = from / self.page_size * self.page_size;
+        let page_start = if to < aligned + self.page_size { aligned } else { from };
+        let count = self.page_size.max(to - page_start + 1).min(self.limit - page_start);
+        self.results = backend.query(request, page_start as u32, count as u32).results;
+        self.offset = page_start;
+    }
+}
+
+/// 先頭から最大 `limit` 件をページ単位で取得する（表示中のページは置き換えない）
+/// ページを取得するたびに取得済みの件数で `on_progress` を呼び、false が返ったら中断して None を返す
+pub fn fetch_all(
+    backend: &mut impl SearchBackend,
+    request: &SearchRequest,
+    page_size: usize,
+    limit: usize,
+    mut on_progress: impl FnMut(usize) -> bool,
+) -> Option<Vec<FileResult>> {
+    // `limit` は総件数のこともあるため、先に確保せず取得した分だけ伸ばす
+    let mut results = Vec::new();
+    while results.len() < limit {
+        let page = backend.query(request, results.len() as u32, page_size as u32);
+        if page.results.is_empty() { break; }
+        results.extend(page.results);
+        if !on_progress(results.len().min(limit)) { return None; }
+    }
+    results.truncate(limit);
+    Some(results)
+}
+
+/// 同順位の並べ直しを行うページの件数
+/// 読み込む範囲はリストの高さやキャッシュのヒントで変わるため、並べ直しはこの件数で区切った固定のページの中だけで行う
+pub const TIE_PAGE_SIZE: u32 = 100;
+
+/// 同順位を2番目のキーで並べ直すか
+/// 結果が値を持たないキー（アクセス日時など）が1番目の場合は同順位を判定できないため並べ直さない
+fn reorders_ties(primary: SortKey, secondary: Option<SortKey>) -> bool {
+    secondary.is_some_and(|key| key != primary) && SortKey::SECONDARY.contains(&primary)
+}
+
+/// `offset` から `max` 件を含む、`TIE_PAGE_SIZE` 単位のページの範囲（終わりは含まない）
+fn tie_page_range(offset: u32, max: u32) -> (u32, u32) {
+    let start = offset / TIE_PAGE_SIZE * TIE_PAGE_SIZE;
+    let end = offset.saturating_add(max).div_ceil(TIE_PAGE_SIZE).saturating_mul(TIE_PAGE_SIZE);
+    (start, end)
+}
+
+/// 並べ替えのキーで同じ値になる連続した結果を、2番目のキーの昇順に並べ直す
+/// Everythingの並べ替えはキーを1つしか指定できないため、取得した結果の中で同順位の並びを決める
+/// `results` は絶対インデックス `first_index`（`TIE_PAGE_SIZE` の倍数）から始まり、`total` は総件数
+/// どの範囲を読み込んでも同じ行に同じ結果が来るよう、`TIE_PAGE_SIZE` ごとのページの中に収まる並びだけを並べ直す
+/// ページの境界に接する並びは隣のページへ続いているかもしれないため、Everythingの順のままにする（リストの先頭と末尾は除く）
+pub fn order_ties(results: &mut [FileResult], first_index: usize, total: usize, primary: SortKey, secondary: Option<SortKey>) {
+    let Some(secondary) = secondary.filter(|_| reorders_ties(primary, secondary)) else { return };
+    let page_size = TIE_PAGE_SIZE as usize;
+    for (page_number, page) in results.chunks_mut(page_size).enumerate() {
+        let page_start = first_index + page_number * page_size;
+        let mut start = 0;
+        while start < page.len() {
+            let mut end = start + 1;
+            while end < page.len() && compare(&page[start], &page[end], primary) == Some(Ordering::Equal) {
+                end += 1;
+            }
+            let open_before = start == 0 && page_start > 0;
+            let open_after = end == page.len() && page_start + end < total;
+            if !open_before && !open_after {
+                page[start..end].sort_by(|a, b| compare(a, b, secondary).unwrap_or(Ordering::Equal));
+            }
+            start = end;
+        }
+    }
+}
+
+/// 並べ替えのキーで2件を比べる（結果が値を持たないキーは None）
+/// 名前・フォルダ・拡張子はEverythingと同じく大文字・小文字を区別しない
+fn compare(a: &FileResult, b: &FileResult, key: SortKey) -> Option<Ordering> {
+    let ignore_case = |x: &str, y: &str| x.to_lowercase().cmp(&y.to_lowercase());
+    match key {
+        SortKey::Name => Some(ignore_case(&a.name, &b.name)),
+        SortKey::Path => Some(ignore_case(&a.path, &b.path)),
+        SortKey::Size => Some(a.size.cmp(&b.size)),
+        SortKey::Extension => Some(ignore_case(extension(a), extension(b))),
+        SortKey::DateModified => Some(a.modified_date.cmp(&b.modified_date)),
+        SortKey::DateCreated => Some(a.created_date.cmp(&b.created_date)),
+        SortKey::DateAccessed | SortKey::RunCount | SortKey::Attributes => None,
+    }
+}
+
+/// 拡張子（フォルダや拡張子のない名前は空）
+fn extension(result: &FileResult) -> &str {
+    if result.is_folder { return ""; }
+    result.name.rsplit_once('.').map_or("", |(_, extension)| extension)
+}
+
+/// 先頭から全件をページ単位で取得し、1件ずつ `on_result` に渡す（取得した結果は溜めずに捨てる）
+/// `on_result` が false を返したら中断する。渡した件数を返す
+pub fn stream_all(
+    backend: &mut impl SearchBackend,
+    request: &SearchRequest,
+    page_size: usize,
+    mut on_result: impl FnMut(&FileResult) -> bool,
+) -> usize {
+    let mut count = 0;
+    loop {
+        let page = backend.query(request, count as u32, page_size as u32);
+        if page.results.is_empty() { return count; }
+        for result in &page.results {
+            if !on_result(result) { return count; }
+            count += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::{ComposedQuery, SortKey};
+
+    /// 検索文字列の長さを総件数として返すバックエンド
+    #[derive(Default)]
+    struct MockBackend {
+        /// 受け取った (検索文字列, offset, max)
+        calls: Vec<(String, u32, u32)>,
+    }
+
+    impl SearchBackend for MockBackend {
+        fn query(&mut self, request: &SearchRequest, offset: u32, max: u32) -> SearchPage {
+            self.calls.push((request.query.text.clone(), offset, max));
+            let total = request.query.text.len() as u32;
+            let results = (offset..total.min(offset + max))
+                .map(|i| FileResult::test_file("", &format!("{}-{}", request.query.text, i), 0))
+                .collect();
+            SearchPage { results, total }
+        }
+
+        fn is_index_loaded(&mut self) -> bool {
+            false
+        }
+    }
+
+    fn request(text: &str) -> SearchRequest {
+        SearchRequest {
+            query: ComposedQuery { text: text.to_string(), regex: false },
+            sort_key: SortKey::Name,
+            sort_descending: false,
+            secondary_sort: None,
+        }
+    }
+
+    fn names(results: &[FileResult]) -> Vec<&str> {
+        results.iter().map(|result| result.name.as_str()).collect()
+    }
+
+    #[test]
+    fn run_search_queries_first_page() {
+        let mut backend = MockBackend::default();
+        let completion = run_search(&mut backend, 7, &request("abc"), 2, None);
+        assert_eq!(backend.calls, vec![("abc".to_string(), 0, 2)]);
+        assert_eq!(completion.generation, 7);
+        assert_eq!(completion.page.total, 3);
+        assert_eq!(completion.page.results.len(), 2);
+        assert!(!completion.index_loaded);
+    }
+
+    #[test]
+    fn grouping_fetches_past_the_first_page_on_the_worker() {
+        let mut backend = MockBackend::default();
+        let group = GroupRequest { limit: 4, collapsed_folders: Vec::new() };
+        let completion = run_search(&mut backend, 1, &request("abcde"), 2, Some(&group));
+        assert_eq!(completion.page.results.len(), 2);
+        assert_eq!(completion.folder_groups.unwrap().result_count(), 4);
+
+        // 先頭ページに収まれば問い合わせ直さない
+        backend.calls.clear();
+        let completion = run_search(&mut backend, 2, &request("ab"), 100, Some(&group));
+        assert_eq!(completion.folder_groups.unwrap().result_count(), 2);
+        assert_eq!(backend.calls.len(), 1);
+    }
+
+    #[test]
+    fn newer_input_discards_in_flight_result() {
+        let mut backend = MockBackend::default();
+        let mut generation = SearchGeneration::default();
+
+        // "ab" の検索中に入力が変わった
+        let in_flight = generation.advance();
+        let completion = run_search(&mut backend, in_flight, &request("ab"), 100, None);
+        generation.advance();
+        assert!(!generation.is_current(completion.generation));
+    }
+
+    #[test]
+    fn only_the_latest_of_overlapping_searches_is_applied() {
+        let mut backend = MockBackend::default();
+        let mut generation = SearchGeneration::default();
+
+        let first = generation.advance();
+        let second = generation.advance();
+        // 後から始めた検索が先に終わり、古い検索の結果が後から届く
+        let newer = run_search(&mut backend, second, &request("abcd"), 100, None);
+        let older = run_search(&mut backend, first, &request("ab"), 100, None);
+
+        let applied: Vec<u32> = [newer, older]
+            .into_iter()
+            .filter(|completion| generation.is_current(completion.generation))
+            .map(|completion| completion.page.total)
+            .collect();
+        assert_eq!(applied, vec![4]);
+    }
+
+    #[test]
+    fn pages_are_loaded_only_when_leaving_the_current_page() {
+        let mut backend = MockBackend::default();
+        let request = request("abcdefghij");
+        let mut pages = ResultPages::new(4);
+        pages.reset(run_search(&mut backend, 1, &request, 4, None).page.results);
+        backend.calls.clear();
+
+        assert!(pages.is_loaded(3));
+        assert!(!pages.is_loaded(4));
+        assert_eq!(pages.get(&mut backend, &request, 3).map(|r| r.name.as_str()), Some("abcdefghij-3"));
+        assert!(backend.calls.is_empty());
+
+        assert_eq!(pages.get(&mut backend, &request, 5).map(|r| r.name.as_str()), Some("abcdefghij-5"));
+        assert_eq!(pages.get(&mut backend, &request, 7).map(|r| r.name.as_str()), Some("abcdefghij-7"));
+        assert_eq!(backend.calls, vec![("abcdefghij".to_string(), 4, 4)]);
+        assert_eq!(pages.offset(), 4);
+
+        // 最後のページは件数が足りない
+        assert_eq!(pages.get(&mut backend, &request, 9).map(|r| r.name.as_str()), Some("abcdefghij-9"));
+        assert_eq!(pages.offset(), 8);
+        assert_eq!(names(pages.results()), vec!["abcdefghij-8", "abcdefghij-9"]);
+    }
+
+    #[test]
+    fn index_past_the_end_returns_none() {
+        let mut backend = MockBackend::default();
+        let request = request("abc");
+        let mut pages = ResultPages::new(100);
+        assert!(pages.get(&mut backend, &request, 3).is_none());
+        assert_eq!(pages.get(&mut backend, &request, 2).map(|r| r.name.as_str()), Some("abc-2"));
+    }
+
+    #[test]
+    fn fetch_all_pages_up_to_the_limit() {
+        let mut backend = MockBackend::default();
+        let mut progress = Vec::new();
+        let results = fetch_all(&mut backend, &request("abcdefg"), 3, 5, |fetched| { progress.push(fetched); true }).unwrap();
+        assert_eq!(names(&results), vec!["abcdefg-0", "abcdefg-1", "abcdefg-2", "abcdefg-3", "abcdefg-4"]);
+        assert_eq!(progress, vec![3, 5]);
+        assert_eq!(backend.calls, vec![("abcdefg".to_string(), 0, 3), ("abcdefg".to_string(), 3, 3)]);
+    }
+
+    #[test]
+    fn fetch_all_stops_when_cancelled() {
+        let mut backend = MockBackend::default();
+        let results = fetch_all(&mut backend, &request("abcdefg"), 2, 100, |fetched| fetched < 4);
+        assert!(results.is_none());
+        assert_eq!(backend.calls.len(), 2);
+    }
+
+    #[test]
+    fn stream_all_visits_every_page_in_order() {
+        let mut backend = MockBackend::default();
+        let mut visited = Vec::new();
+        let count = stream_all(&mut backend, &request("abcde"), 2, |result| { visited.push(result.name.clone()); true });
+        assert_eq!(count, 5);
+        assert_eq!(visited, vec!["abcde-0", "abcde-1", "abcde-2", "abcde-3", "abcde-4"]);
+        assert_eq!(backend.calls.iter().map(|call| call.1).collect::<Vec<_>>(), vec![0, 2, 4, 5]);
+
+        // 書き込みに失敗したら残りのページは取得しない
+        backend.calls.clear();
+        assert_eq!(stream_all(&mut backend, &request("abcde"), 2, |result| result.name != "abcde-1"), 1);
+        assert_eq!(backend.calls.len(), 1);
+    }
+
+    fn file(name: &str, size: u64) -> FileResult {
+        FileResult::test_file("", name, size)
+    }
+
+    #[test]
+    fn ties_are_ordered_by_the_secondary_key() {
+        // サイズの降順で、同じサイズの中の順序はばらばら
+        let mut results = vec![file("c.txt", 9), file("b.txt", 5), file("A.txt", 5), file("a2.txt", 5), file("z.txt", 1)];
+        order_ties(&mut results, 0, 5, SortKey::Size, Some(SortKey::Name));
+        assert_eq!(names(&results), vec!["c.txt", "A.txt", "a2.txt", "b.txt", "z.txt"]);
+    }
+
+    #[test]
+    fn ties_crossing_a_page_boundary_keep_the_everything_order() {
+        let page = TIE_PAGE_SIZE as usize;
+        // サイズの降順。ページの境界をまたぐ同順位（page - 2 から page + 2）と、ページ内の同順位（10 から 12）
+        let mut results: Vec<FileResult> = (0..page * 2).map(|i| file(&format!("{:03}", i), (page * 2 - i) as u64)).collect();
+        for (i, name) in (page - 2..page + 3).zip(["e", "d", "c", "b", "a"]) {
+            results[i] = file(name, 7);
+        }
+        for (i, name) in (10..13).zip(["z", "y", "x"]) {
+            results[i] = file(name, 1000);
+        }
+        let mut whole = results.clone();
+        order_ties(&mut whole, 0, page * 2, SortKey::Size, Some(SortKey::Name));
+        assert_eq!(names(&whole[10..13]), vec!["x", "y", "z"]);
+        assert_eq!(names(&whole[page - 2..page + 3]), vec!["e", "d", "c", "b", "a"]);
+
+        // 後ろのページだけを読み込んでも同じ行に同じ結果が来る
+        let mut second = results[page..].to_vec();
+        order_ties(&mut second, page, page * 2, SortKey::Size, Some(SortKey::Name));
+        assert_eq!(names(&second), names(&whole[page..]));
+    }
+
+    #[test]
+    fn tie_pages_cover_the_requested_range() {
+        assert_eq!(tie_page_range(0, 100), (0, 100));
+        assert_eq!(tie_page_range(130, 120), (100, 300));
+        assert_eq!(tie_page_range(250, 1), (200, 300));
+    }
+
+    #[test]
+    fn ties_are_left_alone_without_a_usable_key() {
+        let original = vec![file("b", 5), file("a", 5)];
+        for (primary, secondary) in [(SortKey::Size, None), (SortKey::Name, Some(SortKey::Name)), (SortKey::RunCount, Some(SortKey::Name))] {
+            let mut results = original.clone();
+            order_ties(&mut results, 0, 2, primary, secondary);
+            assert_eq!(names(&results), vec!["b", "a"]);
+        }
+    }
+
+    #[test]
+    fn page_size_covers_the_visible_rows() {
+        assert_eq!(page_size_for_rows(0), MIN_PAGE_SIZE);
+        assert_eq!(page_size_for_rows(40), MIN_PAGE_SIZE);
+        assert_eq!(page_size_for_rows(200), 250);
+    }
+
+    #[test]
+    fn changed_page_size_applies_to_the_next_load() {
+        let mut backend = MockBackend::default();
+        let request = request("abcdefghij");
+        let mut pages = ResultPages::new(2);
+        pages.reset(run_search(&mut backend, 1, &request, 2, None).page.results);
+        backend.calls.clear();
+
+        pages.set_page_size(4);
+        assert_eq!(pages.get(&mut backend, &request, 1).map(|r| r.name.as_str()), Some("abcdefghij-1"));
+        assert!(backend.calls.is_empty());
+        assert_eq!(pages.get(&mut backend, &request, 5).map(|r| r.name.as_str()), Some("abcdefghij-5"));
+        assert_eq!(backend.calls, vec![("abcdefghij".to_string(), 4, 4)]);
+    }
+
+    #[test]
+    fn pages_are_not_loaded_past_the_limit() {
+        let mut backend = MockBackend::default();
+        let request = request("abcdefghij");
+        let mut pages = ResultPages::new(4);
+        pages.set_limit(6);
+
+        assert_eq!(pages.get(&mut backend, &request, 5).map(|r| r.name.as_str()), Some("abcdefghij-5"));
+        assert_eq!(backend.calls, vec![("abcdefghij".to_string(), 4, 2)]);
+        assert!(pages.get(&mut backend, &request, 6).is_none());
+        assert_eq!(backend.calls.len(), 1);
+    }
+
+    #[test]
+    fn prefetch_loads_the_hinted_range_in_one_query() {
+        let mut backend = MockBackend::default();
+        let request = request("abcdefghij");
+        let mut pages = ResultPages::new(4);
+        pages.set_limit(10);
+
+        // ページ内に収まる範囲はページの境界から読み込む
+        pages.prefetch(&mut backend, &request, 5, 6);
+        assert_eq!(backend.calls, vec![("abcdefghij".to_string(), 4, 4)]);
+        // 読み込み済みの範囲は問い合わせない
+        pages.prefetch(&mut backend, &request, 4, 7);
+        assert_eq!(backend.calls.len(), 1);
+        // 境界をまたぐ範囲は先頭から読み込み、上限で切り詰める
+        pages.prefetch(&mut backend, &request, 7, 12);
+        assert_eq!(backend.calls[1], ("abcdefghij".to_string(), 7, 3));
+        assert!(pages.is_loaded(7) && pages.is_loaded(9));
+        assert_eq!(backend.calls.len(), 2);
+        assert_eq!(pages.get(&mut backend, &request, 8).map(|r| r.name.as_str()), Some("abcdefghij-8"));
+        assert_eq!(backend.calls.len(), 2);
+    }
+
+    #[test]
+    fn reset_replaces_the_page_with_a_new_search() {
+        let mut backend = MockBackend::default();
+        let mut pages = ResultPages::new(2);
+        pages.get(&mut backend, &request("abcdef"), 4);
+        assert_eq!(pages.offset(), 4);
+
+        pages.reset(run_search(&mut backend, 2, &request("xy"), 2, None).page.results);
+        assert_eq!(pages.offset(), 0);
+        assert_eq!(names(pages.results()), vec!["xy-0", "xy-1"]);
+
+        pages.clear();
+        assert!(pages.results().is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+// --- 「送る」メニューのユーザー定義コマンド ---
+//
+// 設定ファイルに `send_to=名前|コマンド` の形で書いたコマンドを、選択したアイテムのパスで置き換えて実行する。
+// コマンドに使える置き換え文字列:
+//   {path}  アイテムのフルパス
+//   {dir}   アイテムのあるフォルダ
+//   {name}  アイテムの名前
+//   {paths} 選択したすべてのアイテムのフルパス（それぞれ `"` で囲み、空白で区切る）
+// `{paths}` を含むコマンドは選択全体で1回だけ実行し、それ以外はアイテムごとに実行する。
+
+use std::path::Path;
+
+/// 「送る」メニューに表示できるコマンドの最大数
+pub const SEND_TO_MAX: usize = 32;
+
+/// 「送る」メニューに表示するユーザー定義のコマンド
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendToCommand {
+    /// メニューに表示する名前
+    pub name: String,
+    /// 置き換え前のコマンドライン（例: `code "{path}"`）
+    pub template: String,
+}
+
+impl SendToCommand {
+    /// 設定ファイルの値（`名前|コマンド`）から読み込む
+    /// 名前には `|` を使えないが、コマンドの中の `|` はそのまま残る
+    pub fn from_config_value(value: &str) -> Option<Self> {
+        let (name, template) = value.split_once('|')?;
+        let (name, template) = (name.trim(), template.trim());
+        if name.is_empty() || template.is_empty() { return None; }
+        Some(Self { name: name.to_string(), template: template.to_string() })
+    }
+
+    /// 設定ファイルに書く値
+    pub fn config_value(&self) -> String {
+        format!("{}|{}", self.name, self.template)
+    }
+
+    /// 選択全体で1回だけ実行するか（`{paths}` を含むか）
+    pub fn runs_once(&self) -> bool {
+        self.template.contains("{paths}")
+    }
+
+    /// 選択したアイテムのフルパスから、実行するコマンドラインを作る
+    /// 1回だけ実行するコマンドの `{path}` などは先頭のアイテムで置き換える
+    pub fn command_lines(&self, paths: &[&Path]) -> Vec<String> {
+        if self.runs_once() {
+            let Some(first) = paths.first() else { return Vec::new() };
+            let joined = paths.iter().map(|path| quote_path(path)).collect::<Vec<_>>().join(" ");
+            vec![expand_placeholders(&self.template, first, Some(&joined))]
+        } else {
+            paths.iter().map(|path| expand_placeholders(&self.template, path, None)).collect()
+        }
+    }
+}
+
+/// `{path}` `{dir}` `{name}`（と `{paths}`）をアイテムのパスで置き換える
+/// 引用符はテンプレートの側で付ける（`"{path}"`）。テンプレートを1回だけ走査するので、
+/// パスの中に `{name}` などが含まれていても置き換え直さない。
+/// 置き換えた値が `\` で終わり直後に `"` が続くときは、`"` がエスケープと解釈されないよう `\` を重ねる
+fn expand_placeholders(template: &str, path: &Path, joined_paths: Option<&str>) -> String {
+    let path = path.to_string_lossy();
+    let (dir, name) = path.rsplit_once(['\\', '/']).unwrap_or(("", &path));
+    let placeholders = [("{paths}", joined_paths), ("{path}", Some(&*path)), ("{dir}", Some(dir)), ("{name}", Some(name))];
+
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match placeholders.iter().find(|(key, value)| value.is_some() && rest.starts_with(key)) {
+            Some(&(key, Some(value))) => {
+                rest = &rest[key.len()..];
+                expanded.push_str(value);
+                if value.ends_with('\\') && rest.starts_with('"') { expanded.push('\\'); }
+            }
+            _ => {
+                expanded.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// `{paths}` に並べるパスを `"` で囲む
+/// `C:\` のように `\` で終わると閉じる `"` がエスケープと解釈されるため、`\` を重ねる
+fn quote_path(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if path.ends_with('\\') { format!("\"{}\\\"", path) } else { format!("\"{}\"", path) }
+}
+
+/// コマンドラインを、起動するプログラムと引数に分ける（ShellExecuteW に別々に渡すため）
+/// プログラムが `"` で囲まれていればその中を、なければ最初の空白までをプログラムとする
+pub fn split_command_line(line: &str) -> (String, String) {
+    let line = line.trim();
+    let (program, rest) = match line.strip_prefix('"') {
+        Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+        None => line.split_once(char::is_whitespace).unwrap_or((line, "")),
+    };
+    (program.to_string(), rest.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(template: &str) -> SendToCommand {
+        SendToCommand { name: "test".to_string(), template: template.to_string() }
+    }
+
+    #[test]
+    fn config_value_round_trips() {
+        let parsed = SendToCommand::from_config_value("VS Code | code \"{path}\"").unwrap();
+        assert_eq!(parsed, SendToCommand { name: "VS Code".to_string(), template: "code \"{path}\"".to_string() });
+        assert_eq!(SendToCommand::from_config_value(&parsed.config_value()), Some(parsed));
+
+        let piped = SendToCommand::from_config_value("Count|cmd /c type \"{path}\" | find /c /v \"\"").unwrap();
+        assert_eq!(piped.template, "cmd /c type \"{path}\" | find /c /v \"\"");
+
+        assert_eq!(SendToCommand::from_config_value("no separator"), None);
+        assert_eq!(SendToCommand::from_config_value("|code"), None);
+        assert_eq!(SendToCommand::from_config_value("name|"), None);
+    }
+
+    #[test]
+    fn placeholders_are_expanded_per_item() {
+        let a = Path::new(r"C:\work\a.txt");
+        let b = Path::new(r"D:\b.log");
+        let lines = command(r#"certutil -hashfile "{path}" SHA256 > "{dir}\{name}.sha256""#).command_lines(&[a, b]);
+        assert_eq!(lines, vec![
+            r#"certutil -hashfile "C:\work\a.txt" SHA256 > "C:\work\a.txt.sha256""#.to_string(),
+            r#"certutil -hashfile "D:\b.log" SHA256 > "D:\b.log.sha256""#.to_string(),
+        ]);
+    }
+
+    #[test]
+    fn paths_placeholder_runs_once() {
+        let a = Path::new(r"C:\work\a b.txt");
+        let root = Path::new(r"D:\");
+        let command = command(r#"code -n {paths} --goto "{path}""#);
+        assert!(command.runs_once());
+        assert_eq!(command.command_lines(&[a, root]), vec![r#"code -n "C:\work\a b.txt" "D:\\" --goto "C:\work\a b.txt""#.to_string()]);
+        assert!(command.command_lines(&[]).is_empty());
+    }
+
+    #[test]
+    fn placeholders_in_substituted_paths_are_not_expanded_again() {
+        let odd = Path::new(r"C:\work\{dir}\{name}.txt");
+        let lines = command(r#"tool "{path}" "{name}" {paths}"#).command_lines(&[odd]);
+        assert_eq!(lines, vec![r#"tool "C:\work\{dir}\{name}.txt" "{name}.txt" "C:\work\{dir}\{name}.txt""#.to_string()]);
+        // 置き換え文字列でない `{` はそのまま残す
+        assert_eq!(command(r#"echo {x} "{name}""#).command_lines(&[odd]), vec![r#"echo {x} "{name}.txt""#.to_string()]);
+    }
+
+    #[test]
+    fn trailing_backslash_before_a_quote_is_doubled() {
+        let root = Path::new(r"D:\");
+        assert_eq!(command(r#"explorer "{path}""#).command_lines(&[root]), vec![r#"explorer "D:\\""#.to_string()]);
+        // 引用符が続かなければそのまま
+        assert_eq!(command(r"tool {path}").command_lines(&[root]), vec![r"tool D:\".to_string()]);
+        let share = Path::new(r"\\server\share\\a.txt");
+        assert_eq!(command(r#"tool "{dir}""#).command_lines(&[share]), vec![r#"tool "\\server\share\\""#.to_string()]);
+    }
+
+    #[test]
+    fn command_line_is_split_into_program_and_parameters() {
+        assert_eq!(split_command_line(r#"code "C:\a.txt""#), ("code".to_string(), r#""C:\a.txt""#.to_string()));
+        assert_eq!(
+            split_command_line(r#""C:\Program Files\Tool\tool.exe"  -x "C:\a.txt""#),
+            (r"C:\Program Files\Tool\tool.exe".to_string(), r#"-x "C:\a.txt""#.to_string())
+        );
+        assert_eq!(split_command_line("notepad"), ("notepad".to_string(), String::new()));
+    }
+}
@@ -0,0 +1,445 @@
+// --- 設定ファイルの読み書き ---
+//
+// 設定は `キー=値` 形式のテキストファイルに保存する。
+// 同じキーを複数回書くとリストとして扱う。
+// 保存は一時ファイルに書いてから置き換え、直前の壊れていない内容を `.bak` として残す。
+
+use migemo_everything::commands::{SendToCommand, SEND_TO_MAX};
+use migemo_everything::format::EllipsisPosition;
+use migemo_everything::paths;
+use migemo_everything::search::{ItemKind, SortKey};
+
+use std::fs;
+use std::io::{self, Write};
+use std::os::windows::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use windows::Win32::Storage::FileSystem::{MoveFileExW, MOVEFILE_REPLACE_EXISTING, MOVEFILE_WRITE_THROUGH};
+use windows::core::PCWSTR;
+
+/// 設定ファイル名
+const CONFIG_FILE_NAME: &str = "migemo-everything.ini";
+
+/// リストに表示する最大件数の既定値
+pub const DEFAULT_MAX_DISPLAY_RESULTS: u32 = 100_000;
+
+/// 永続化されるアプリケーション設定
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// 検索から除外するフォルダ（`!path:` 条件として付加される）
+    pub exclude_paths: Vec<String>,
+    /// リストに表示する最大件数（これを超える分は表示しない）
+    pub max_display_results: u32,
+    /// 「作成日時」列を表示するか
+    pub show_created_column: bool,
+    /// 「種類」列を表示するか
+    pub show_type_column: bool,
+    /// 行の背景を1行おきに塗り分けるか
+    pub striped_rows: bool,
+    /// 「フォルダ」列の一致箇所を強調表示するか
+    pub highlight_path: bool,
+    /// 「フォルダ」列に収まらないパスを省略する位置
+    pub path_ellipsis: EllipsisPosition,
+    /// 名前とフォルダを1列にまとめて表示するか
+    pub compact_rows: bool,
+    /// 検索結果をフォルダごとにまとめて表示するか
+    pub group_by_folder: bool,
+    /// 一致箇所をWindowsのアクセントカラーで強調するか（false なら黄色）
+    pub accent_highlight: bool,
+    /// サイズを1024バイト単位（KiB）で表示するか（false なら1000バイト単位の KB）
+    pub binary_units: bool,
+    /// ウィンドウを常に最前面に表示するか
+    pub always_on_top: bool,
+    /// 最小化時に通知領域へ格納するか
+    pub minimize_to_tray: bool,
+    /// リストの下にフォーカス行のプレビュー欄を表示するか
+    pub show_preview: bool,
+    /// 結果内を絞り込む2つ目の入力欄を表示するか
+    pub show_narrow_box: bool,
+    /// プレビュー欄にテキストファイルの先頭部分を表示するか
+    pub preview_content: bool,
+    /// 結果の行をダブルクリックしたときの動作
+    pub double_click_action: DoubleClickAction,
+    /// フォルダの行をダブルクリックしたときの動作
+    pub folder_double_click: FolderDoubleClick,
+    /// 入力中に検索するか（false の場合はEnterでのみ検索する）
+    pub live_search: bool,
+    /// 表示中の検索を自動で再実行する間隔（秒、None なら自動更新しない）
+    pub auto_refresh_secs: Option<u32>,
+    /// シェルのコンテキストメニューのコマンドを実行した後に再検索するか（削除や移動を結果に反映する）
+    pub refresh_after_shell_command: bool,
+    /// 結果に含めるアイテムの種類
+    pub item_kind: ItemKind,
+    /// 並べ替えのキーが同じ値の結果を並べる2番目のキー（None なら Everything の順のまま）
+    pub secondary_sort: Option<SortKey>,
+    /// 通常検索で入力を演算子として解釈せず、そのままの文字列として検索するか
+    pub literal_search: bool,
+    /// アクセント記号（é と e など）を区別するか
+    pub match_diacritics: bool,
+    /// Migemoの展開結果をファイル名ではなくパス全体と照合するか
+    pub migemo_match_path: bool,
+    /// 他のアプリでコピーした文字列を検索ボックスに入れて検索するか
+    pub clipboard_search: bool,
+    /// 最近使った検索範囲のフォルダ（新しい順）
+    pub recent_scopes: Vec<PathBuf>,
+    /// ピン留めした項目のフルパス（ピン留めした順）
+    pub pinned_paths: Vec<PathBuf>,
+    /// コンテキストメニューの「送る」に表示するコマンド（書いた順）
+    pub send_to_commands: Vec<SendToCommand>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            exclude_paths: Vec::new(),
+            max_display_results: DEFAULT_MAX_DISPLAY_RESULTS,
+            show_created_column: false,
+            show_type_column: false,
+            striped_rows: false,
+            highlight_path: true,
+            path_ellipsis: EllipsisPosition::End,
+            compact_rows: false,
+            group_by_folder: false,
+            accent_highlight: false,
+            binary_units: true,
+            always_on_top: false,
+            minimize_to_tray: false,
+            show_preview: false,
+            show_narrow_box: false,
+            preview_content: false,
+            double_click_action: DoubleClickAction::Open,
+            folder_double_click: FolderDoubleClick::ExplorerOpen,
+            live_search: true,
+            auto_refresh_secs: None,
+            refresh_after_shell_command: true,
+            item_kind: ItemKind::All,
+            secondary_sort: Some(SortKey::Name),
+            literal_search: false,
+            match_diacritics: false,
+            migemo_match_path: false,
+            clipboard_search: false,
+            recent_scopes: Vec::new(),
+            pinned_paths: Vec::new(),
+            send_to_commands: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// 設定ファイルを読み込む。存在しない場合は既定値を返す
+    /// 壊れている場合は `.bak` を、データフォルダになければ以前の保存先（実行ファイルと同じフォルダ）を読み込む
+    /// 最近使った検索範囲のうち、既に存在しないフォルダはここで取り除く
+    pub fn load() -> Self {
+        let legacy_path = paths::exe_dir().map(|dir| dir.join(CONFIG_FILE_NAME));
+        let mut config = Self::read_first_valid(load_candidates(config_path(), legacy_path)).unwrap_or_default();
+        config.recent_scopes.retain(|path| Path::new(path).is_dir());
+        config
+    }
+
+    /// 候補のファイルを順に読み、最初に壊れていない内容を読み込めたものを返す
+    fn read_first_valid(paths: impl IntoIterator<Item = PathBuf>) -> Option<Self> {
+        paths.into_iter().find_map(|path| fs::read_to_string(path).ok().and_then(|text| Self::parse_checked(&text)))
+    }
+
+    /// 設定ファイルに書き込む（データフォルダがなければ作成する）
+    /// 書き込み中に終了しても半端な内容にならないよう、一時ファイルに書いてから置き換える
+    pub fn save(&self) -> io::Result<()> {
+        let path = config_path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "config path not found"))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        if fs::read_to_string(&path).is_ok_and(|text| Self::parse_checked(&text).is_some()) {
+            let _ = fs::copy(&path, backup_path(&path));
+        }
+
+        let temp_path = path.with_extension("ini.tmp");
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(self.serialize().as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        replace_file(&temp_path, &path)
+    }
+
+    /// 壊れていなければ `parse` の結果を返す
+    /// `キー=値` の行が1つもない（空のファイルなど）か、コメント以外に `=` を含まない行がある場合は None
+    pub fn parse_checked(text: &str) -> Option<Self> {
+        let mut has_entry = false;
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            if !line.contains('=') { return None; }
+            has_entry = true;
+        }
+        has_entry.then(|| Self::parse(text))
+    }
+
+    /// `キー=値` 形式のテキストから設定を組み立てる。未知のキーは無視する
+    pub fn parse(text: &str) -> Self {
+        let mut config = Self::default();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') { continue; }
+            let Some((key, value)) = line.split_once('=') else { continue };
+            let value = value.trim();
+            match key.trim() {
+                "exclude_path" if !value.is_empty() => config.exclude_paths.push(value.to_string()),
+                "recent_scope" if !value.is_empty() => config.recent_scopes.push(PathBuf::from(value)),
+                "pinned_path" if !value.is_empty() => config.pinned_paths.push(PathBuf::from(value)),
+                "send_to" if config.send_to_commands.len() < SEND_TO_MAX => {
+                    config.send_to_commands.extend(SendToCommand::from_config_value(value));
+                }
+                "max_display_results" => {
+                    if let Ok(max) = value.parse::<u32>() {
+                        config.max_display_results = max.max(1);
+                    }
+                }
+                "show_created_column" => config.show_created_column = value == "true",
+                "show_type_column" => config.show_type_column = value == "true",
+                "striped_rows" => config.striped_rows = value == "true",
+                "path_ellipsis" => config.path_ellipsis = EllipsisPosition::from_config_value(value).unwrap_or_default(),
+                "highlight_path" => config.highlight_path = value != "false",
+                "compact_rows" => config.compact_rows = value == "true",
+                "group_by_folder" => config.group_by_folder = value == "true",
+                "accent_highlight" => config.accent_highlight = value == "true",
+                "binary_units" => config.binary_units = value != "false",
+                "always_on_top" => config.always_on_top = value == "true",
+                "minimize_to_tray" => config.minimize_to_tray = value == "true",
+                "show_preview" => config.show_preview = value == "true",
+                "show_narrow_box" => config.show_narrow_box = value == "true",
+                "preview_content" => config.preview_content = value == "true",
+                "double_click_action" => config.double_click_action = DoubleClickAction::from_config_value(value).unwrap_or_default(),
+                "folder_double_click" => config.folder_double_click = FolderDoubleClick::from_config_value(value).unwrap_or_default(),
+                "live_search" => config.live_search = value != "false",
+                "auto_refresh_secs" => config.auto_refresh_secs = value.parse::<u32>().ok().filter(|&secs| secs > 0),
+                "refresh_after_shell_command" => config.refresh_after_shell_command = value != "false",
+                "literal_search" => config.literal_search = value == "true",
+                "match_diacritics" => config.match_diacritics = value == "true",
+                "migemo_match_path" => config.migemo_match_path = value == "true",
+                "clipboard_search" => config.clipboard_search = value == "true",
+                "item_kind" => config.item_kind = ItemKind::from_config_value(value).unwrap_or_default(),
+                "secondary_sort" if value == "none" => config.secondary_sort = None,
+                "secondary_sort" => {
+                    if let Some(key) = SortKey::from_config_value(value).filter(|key| SortKey::SECONDARY.contains(key)) {
+                        config.secondary_sort = Some(key);
+                    }
+                }
+                _ => {}
+            }
+        }
+        config
+    }
+
+    /// 設定を `キー=値` 形式のテキストに変換する
+    pub fn serialize(&self) -> String {
+        let mut text = String::new();
+        text.push_str(&format!("max_display_results={}\n", self.max_display_results));
+        text.push_str(&format!("show_created_column={}\n", self.show_created_column));
+        text.push_str(&format!("show_type_column={}\n", self.show_type_column));
+        text.push_str(&format!("striped_rows={}\n", self.striped_rows));
+        text.push_str(&format!("highlight_path={}\n", self.highlight_path));
+        text.push_str(&format!("path_ellipsis={}\n", self.path_ellipsis.config_value()));
+        text.push_str(&format!("compact_rows={}\n", self.compact_rows));
+        text.push_str(&format!("group_by_folder={}\n", self.group_by_folder));
+        text.push_str(&format!("accent_highlight={}\n", self.accent_highlight));
+        text.push_str(&format!("binary_units={}\n", self.binary_units));
+        text.push_str(&format!("always_on_top={}\n", self.always_on_top));
+        text.push_str(&format!("minimize_to_tray={}\n", self.minimize_to_tray));
+        text.push_str(&format!("show_preview={}\n", self.show_preview));
+        text.push_str(&format!("show_narrow_box={}\n", self.show_narrow_box));
+        text.push_str(&format!("preview_content={}\n", self.preview_content));
+        text.push_str(&format!("double_click_action={}\n", self.double_click_action.config_value()));
+        text.push_str(&format!("folder_double_click={}\n", self.folder_double_click.config_value()));
+        text.push_str(&format!("live_search={}\n", self.live_search));
+        text.push_str(&format!("auto_refresh_secs={}\n", self.auto_refresh_secs.unwrap_or(0)));
+        text.push_str(&format!("refresh_after_shell_command={}\n", self.refresh_after_shell_command));
+        text.push_str(&format!("item_kind={}\n", self.item_kind.config_value()));
+        text.push_str(&format!("secondary_sort={}\n", self.secondary_sort.map_or("none", SortKey::config_value)));
+        text.push_str(&format!("literal_search={}\n", self.literal_search));
+        text.push_str(&format!("match_diacritics={}\n", self.match_diacritics));
+        text.push_str(&format!("migemo_match_path={}\n", self.migemo_match_path));
+        text.push_str(&format!("clipboard_search={}\n", self.clipboard_search));
+        for path in &self.exclude_paths {
+            text.push_str(&format!("exclude_path={}\n", path));
+        }
+        for path in &self.recent_scopes {
+            text.push_str(&format!("recent_scope={}\n", path.display()));
+        }
+        for path in &self.pinned_paths {
+            text.push_str(&format!("pinned_path={}\n", path.display()));
+        }
+        for command in &self.send_to_commands {
+            text.push_str(&format!("send_to={}\n", command.config_value()));
+        }
+        text
+    }
+}
+
+/// 結果の行をダブルクリックしたときの動作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DoubleClickAction {
+    /// 関連付けで開く（フォルダはエクスプローラで開く）
+    #[default]
+    Open,
+    /// エクスプローラで親フォルダを開いて選択する
+    Reveal,
+    /// プロパティを表示する
+    Properties,
+}
+
+impl DoubleClickAction {
+    /// メニューに並べる順のすべての動作
+    pub const ALL: [DoubleClickAction; 3] = [DoubleClickAction::Open, DoubleClickAction::Reveal, DoubleClickAction::Properties];
+
+    /// メニューに表示する名前
+    pub fn label(self) -> &'static str {
+        match self {
+            DoubleClickAction::Open => "開く(&O)",
+            DoubleClickAction::Reveal => "フォルダを開く(&F)",
+            DoubleClickAction::Properties => "プロパティ(&R)",
+        }
+    }
+
+    /// 設定ファイルに保存する値
+    pub fn config_value(self) -> &'static str {
+        match self {
+            DoubleClickAction::Open => "open",
+            DoubleClickAction::Reveal => "reveal",
+            DoubleClickAction::Properties => "properties",
+        }
+    }
+
+    /// 設定ファイルの値から動作を得る
+    pub fn from_config_value(value: &str) -> Option<Self> {
+        DoubleClickAction::ALL.into_iter().find(|action| action.config_value() == value)
+    }
+}
+
+/// フォルダの行をダブルクリックしたときの動作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FolderDoubleClick {
+    /// 行の動作（`DoubleClickAction`）に従う（「開く」ならエクスプローラで開く）
+    #[default]
+    ExplorerOpen,
+    /// フォルダを検索範囲にして入力を消し、フォルダの中を一覧する
+    NavigateScope,
+}
+
+impl FolderDoubleClick {
+    /// メニューに並べる順のすべての動作
+    pub const ALL: [FolderDoubleClick; 2] = [FolderDoubleClick::ExplorerOpen, FolderDoubleClick::NavigateScope];
+
+    /// メニューに表示する名前
+    pub fn label(self) -> &'static str {
+        match self {
+            FolderDoubleClick::ExplorerOpen => "フォルダもファイルと同じ動作(&X)",
+            FolderDoubleClick::NavigateScope => "フォルダの中へ移動(&N)",
+        }
+    }
+
+    /// 設定ファイルに保存する値
+    pub fn config_value(self) -> &'static str {
+        match self {
+            FolderDoubleClick::ExplorerOpen => "explorer",
+            FolderDoubleClick::NavigateScope => "navigate",
+        }
+    }
+
+    /// 設定ファイルの値から動作を得る
+    pub fn from_config_value(value: &str) -> Option<Self> {
+        FolderDoubleClick::ALL.into_iter().find(|action| action.config_value() == value)
+    }
+}
+
+/// 設定ファイルのパス（データフォルダの中）
+fn config_path() -> Option<PathBuf> {
+    paths::resolve_data_dir().map(|dir| dir.join(CONFIG_FILE_NAME))
+}
+
+/// 読み込む設定ファイルの候補（現在の設定ファイル、その `.bak`、以前の保存先の順）
+fn load_candidates(current_path: Option<PathBuf>, legacy_path: Option<PathBuf>) -> Vec<PathBuf> {
+    let backup_path = current_path.as_deref().map(backup_path);
+    [current_path, backup_path, legacy_path].into_iter().flatten().collect()
+}
+
+/// 直前に保存した壊れていない設定ファイルの控え
+fn backup_path(config_path: &Path) -> PathBuf {
+    config_path.with_extension("ini.bak")
+}
+
+/// `from` のファイルで `to` を置き換える（`to` が既にあれば上書きする）
+fn replace_file(from: &Path, to: &Path) -> io::Result<()> {
+    let wide = |path: &Path| path.as_os_str().encode_wide().chain(std::iter::once(0)).collect::<Vec<u16>>();
+    let (from_w, to_w) = (wide(from), wide(to));
+    unsafe { MoveFileExW(PCWSTR(from_w.as_ptr()), PCWSTR(to_w.as_ptr()), MOVEFILE_REPLACE_EXISTING | MOVEFILE_WRITE_THROUGH) }?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn parse_checked_rejects_empty_and_broken_files() {
+        assert_eq!(Config::parse_checked(""), None);
+        assert_eq!(Config::parse_checked("# comment only\n\n"), None);
+        assert_eq!(Config::parse_checked("max_display_results=5\ntruncated line"), None);
+
+        let config = Config::parse_checked("# comment\nmax_display_results=5\n").unwrap();
+        assert_eq!(config.max_display_results, 5);
+    }
+
+    #[test]
+    fn parse_ignores_unknown_keys_and_invalid_values() {
+        let config = Config::parse("unknown=1\nmax_display_results=0\nitem_kind=dirs\nsecondary_sort=run_count\n");
+        assert_eq!(config, Config { max_display_results: 1, ..Config::default() });
+    }
+
+    #[test]
+    fn serialize_round_trips() {
+        let config = Config {
+            exclude_paths: vec!["C:\\Windows".to_string(), "D:\\tmp".to_string()],
+            max_display_results: 500,
+            show_created_column: true,
+            highlight_path: false,
+            path_ellipsis: EllipsisPosition::Middle,
+            group_by_folder: true,
+            binary_units: false,
+            double_click_action: DoubleClickAction::Reveal,
+            folder_double_click: FolderDoubleClick::NavigateScope,
+            live_search: false,
+            auto_refresh_secs: Some(30),
+            item_kind: ItemKind::Folders,
+            secondary_sort: None,
+            match_diacritics: true,
+            recent_scopes: vec![PathBuf::from("C:\\src")],
+            pinned_paths: vec![PathBuf::from("C:\\src\\a.txt")],
+            send_to_commands: vec![SendToCommand { name: "VS Code".to_string(), template: "code \"{path}\"".to_string() }],
+            ..Config::default()
+        };
+        assert_eq!(Config::parse_checked(&config.serialize()), Some(config));
+        assert_eq!(Config::parse(&Config::default().serialize()), Config::default());
+    }
+
+    #[test]
+    fn broken_config_falls_back_to_backup_then_legacy() {
+        let dir = env::temp_dir().join(format!("migemo-everything-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let current = dir.join(CONFIG_FILE_NAME);
+        let legacy = dir.join("legacy.ini");
+        let candidates = load_candidates(Some(current.clone()), Some(legacy.clone()));
+        assert_eq!(candidates, vec![current.clone(), backup_path(&current), legacy.clone()]);
+
+        fs::write(&current, "").unwrap();
+        fs::write(backup_path(&current), "max_display_results=5\n").unwrap();
+        fs::write(&legacy, "max_display_results=7\n").unwrap();
+        assert_eq!(Config::read_first_valid(candidates.clone()).unwrap().max_display_results, 5);
+
+        fs::remove_file(backup_path(&current)).unwrap();
+        assert_eq!(Config::read_first_valid(candidates.clone()).unwrap().max_display_results, 7);
+
+        fs::write(&current, "max_display_results=3\n").unwrap();
+        assert_eq!(Config::read_first_valid(candidates.clone()).unwrap().max_display_results, 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(Config::read_first_valid(candidates), None);
+    }
+}
@@ -0,0 +1,187 @@
+// --- 重複ファイルの検出 ---
+//
+// 検索結果をサイズ（必要なら名前も）でまとめ、先頭部分の内容が一致するものだけを重複として残す。
+// ファイル全体は比較しないため「重複の候補」であり、削除の前には利用者が確認する前提とする。
+
+use crate::backend::FileResult;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::path::Path;
+
+/// 内容の比較に使う先頭部分の大きさ（バイト）
+pub const FINGERPRINT_PREFIX_BYTES: u64 = 64 * 1024;
+
+/// サイズ（`match_name` なら名前も）が同じファイルをまとめ、2件以上あるグループだけを返す
+/// フォルダと空のファイルは対象外。グループはサイズの大きい順、グループ内はフルパス順に並べる
+pub fn group_by_size(results: impl IntoIterator<Item = FileResult>, match_name: bool) -> Vec<Vec<FileResult>> {
+    let mut buckets: HashMap<(u64, Option<String>), Vec<FileResult>> = HashMap::new();
+    for result in results {
+        if result.is_folder || result.size == 0 { continue; }
+        let name_key = match_name.then(|| result.name.to_lowercase());
+        buckets.entry((result.size, name_key)).or_default().push(result);
+    }
+    sorted_groups(buckets.into_values())
+}
+
+/// 各グループを内容の指紋でさらに分け、2件以上あるグループだけを返す
+/// 指紋を取得できない（読み込めない）ファイルは除く
+pub fn split_by_content(groups: Vec<Vec<FileResult>>, mut fingerprint: impl FnMut(&FileResult) -> Option<u64>) -> Vec<Vec<FileResult>> {
+    let mut split = Vec::new();
+    for group in groups {
+        let mut buckets: HashMap<u64, Vec<FileResult>> = HashMap::new();
+        for result in group {
+            if let Some(hash) = fingerprint(&result) {
+                buckets.entry(hash).or_default().push(result);
+            }
+        }
+        split.extend(buckets.into_values());
+    }
+    sorted_groups(split)
+}
+
+/// ファイルの先頭 `FINGERPRINT_PREFIX_BYTES` バイトのハッシュ値を返す
+pub fn prefix_fingerprint(path: &Path) -> Option<u64> {
+    let mut prefix = Vec::new();
+    File::open(path).ok()?.take(FINGERPRINT_PREFIX_BYTES).read_to_end(&mut prefix).ok()?;
+    let mut hasher = DefaultHasher::new();
+    prefix.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// 2件以上のグループだけを残して並べ替える
+fn sorted_groups(groups: impl IntoIterator<Item = Vec<FileResult>>) -> Vec<Vec<FileResult>> {
+    let mut groups: Vec<Vec<FileResult>> = groups.into_iter().filter(|group| group.len() > 1).collect();
+    for group in &mut groups {
+        group.sort_by(|a, b| (&a.path, &a.name).cmp(&(&b.path, &b.name)));
+    }
+    groups.sort_by(|a, b| b[0].size.cmp(&a[0].size).then_with(|| (&a[0].path, &a[0].name).cmp(&(&b[0].path, &b[0].name))));
+    groups
+}
+
+/// 重複グループをリストの行として並べたもの
+#[derive(Debug, Default)]
+pub struct DuplicateList {
+    rows: Vec<FileResult>,
+    /// 各グループの先頭行のインデックス
+    group_starts: Vec<usize>,
+}
+
+impl DuplicateList {
+    pub fn new(groups: Vec<Vec<FileResult>>) -> Self {
+        let mut list = Self::default();
+        for group in groups {
+            list.group_starts.push(list.rows.len());
+            list.rows.extend(group);
+        }
+        list
+    }
+
+    /// すべての行
+    pub fn rows(&self) -> &[FileResult] {
+        &self.rows
+    }
+
+    /// グループの数
+    pub fn group_count(&self) -> usize {
+        self.group_starts.len()
+    }
+
+    /// 指定行がグループの先頭か（区切り線を引く位置）
+    pub fn is_group_start(&self, index: usize) -> bool {
+        self.group_starts.binary_search(&index).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn file(path: &str, name: &str, size: u64) -> FileResult {
+        FileResult::test_file(path, name, size)
+    }
+
+    fn paths(groups: &[Vec<FileResult>]) -> Vec<Vec<String>> {
+        groups.iter().map(|group| group.iter().map(|r| r.full_path.clone()).collect()).collect()
+    }
+
+    #[test]
+    fn groups_files_of_the_same_size() {
+        let mut folder = file("C:\\a", "x", 10);
+        folder.is_folder = true;
+        let results = vec![
+            file("C:\\b", "one.txt", 10),
+            file("C:\\a", "two.txt", 10),
+            file("C:\\a", "big.bin", 99),
+            file("C:\\b", "big.dat", 99),
+            file("C:\\a", "unique.txt", 5),
+            file("C:\\a", "empty1", 0),
+            file("C:\\b", "empty2", 0),
+            folder,
+        ];
+        assert_eq!(
+            paths(&group_by_size(results, false)),
+            vec![
+                vec!["C:\\a\\big.bin".to_string(), "C:\\b\\big.dat".to_string()],
+                vec!["C:\\a\\two.txt".to_string(), "C:\\b\\one.txt".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn matching_names_ignores_case() {
+        let results = vec![file("C:\\a", "Memo.txt", 10), file("C:\\b", "memo.TXT", 10), file("C:\\c", "other.txt", 10)];
+        assert_eq!(
+            paths(&group_by_size(results, true)),
+            vec![vec!["C:\\a\\Memo.txt".to_string(), "C:\\b\\memo.TXT".to_string()]]
+        );
+    }
+
+    #[test]
+    fn content_splits_groups_and_drops_unreadable_files() {
+        let groups = vec![vec![
+            file("C:\\a", "1", 10),
+            file("C:\\a", "2", 10),
+            file("C:\\a", "3", 10),
+            file("C:\\a", "4", 10),
+            file("C:\\a", "locked", 10),
+        ]];
+        let fingerprints = HashMap::from([("1", 7), ("2", 8), ("3", 7), ("4", 9)]);
+        let split = split_by_content(groups, |r| fingerprints.get(r.name.as_str()).copied());
+        assert_eq!(paths(&split), vec![vec!["C:\\a\\1".to_string(), "C:\\a\\3".to_string()]]);
+    }
+
+    #[test]
+    fn prefix_fingerprint_compares_file_contents() {
+        let dir = env::temp_dir().join(format!("migemo-everything-dup-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a"), b"same").unwrap();
+        fs::write(dir.join("b"), b"same").unwrap();
+        fs::write(dir.join("c"), b"diff").unwrap();
+
+        let a = prefix_fingerprint(&dir.join("a"));
+        assert!(a.is_some());
+        assert_eq!(a, prefix_fingerprint(&dir.join("b")));
+        assert_ne!(a, prefix_fingerprint(&dir.join("c")));
+        assert_eq!(prefix_fingerprint(&dir.join("missing")), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn list_marks_group_starts() {
+        let list = DuplicateList::new(vec![
+            vec![file("C:\\a", "1", 9), file("C:\\b", "1", 9)],
+            vec![file("C:\\a", "2", 5), file("C:\\b", "2", 5), file("C:\\c", "2", 5)],
+        ]);
+        assert_eq!(list.rows().len(), 5);
+        assert_eq!(list.group_count(), 2);
+        let starts: Vec<usize> = (0..5).filter(|&i| list.is_group_start(i)).collect();
+        assert_eq!(starts, vec![0, 2]);
+    }
+}
@@ -0,0 +1,496 @@
+// --- 表示用の文字列変換 ---
+//
+// リストやエクスポートで使う純粋な変換関数をまとめる。Win32 APIには依存しない。
+
+use crate::backend::FileResult;
+
+/// FILETIMEの起点（1601-01-01）からUNIXエポック（1970-01-01）までの日数
+const DAYS_FROM_1601_TO_1970: i64 = 134_774;
+/// FILETIMEの1秒あたりの単位数（100ナノ秒単位）
+const FILETIME_TICKS_PER_SECOND: u64 = 10_000_000;
+
+/// Everythingのハイライト付き文字列（`*` で囲まれた部分が一致箇所）を解析する
+/// `*` を取り除いた文字列と、一致箇所の (開始, 終了) を文字単位で返す
+pub fn parse_highlight_text(highlighted_text: &str) -> (String, Vec<(usize, usize)>) {
+    let mut plain_text = String::new();
+    let mut ranges = Vec::new();
+    let mut highlight_start = 0;
+    let mut in_highlight = false;
+
+    let chars: Vec<char> = highlighted_text.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '*' {
+            if in_highlight {
+                ranges.push((highlight_start, plain_text.chars().count()));
+                in_highlight = false;
+            } else {
+                highlight_start = plain_text.chars().count();
+                in_highlight = true;
+            }
+            i += 1;
+        } else {
+            plain_text.push(chars[i]);
+            i += 1;
+        }
+    }
+    (plain_text, ranges)
+}
+
+/// 1列表示で名前とフォルダの間に挟む区切り
+pub const NAME_FOLDER_SEPARATOR: &str = " — ";
+
+/// 名前とフォルダを1つの文字列につなぐ（`名前 — フォルダ`）
+/// それぞれの一致箇所（文字単位）をつないだ文字列での位置に直し、区切りが始まる文字位置とともに返す
+pub fn join_name_and_folder(name: &str, name_ranges: &[(usize, usize)], folder: &str, folder_ranges: &[(usize, usize)]) -> (String, Vec<(usize, usize)>, usize) {
+    let name_len = name.chars().count();
+    let folder_start = name_len + NAME_FOLDER_SEPARATOR.chars().count();
+    let text = format!("{}{}{}", name, NAME_FOLDER_SEPARATOR, folder);
+    let ranges = name_ranges.iter().copied()
+        .chain(folder_ranges.iter().map(|&(start, end)| (start + folder_start, end + folder_start)))
+        .collect();
+    (text, ranges, name_len)
+}
+
+/// 数値を3桁ごとにカンマで区切る
+pub fn format_with_commas(n: u64) -> String {
+    let s = n.to_string();
+    let bytes = s.as_bytes();
+    let mut result = Vec::new();
+    let len = bytes.len();
+    let first = len % 3;
+
+    if first > 0 {
+        result.extend_from_slice(&bytes[..first]);
+        if len > first { result.push(b','); }
+    }
+
+    for (i, chunk) in bytes[first..].chunks(3).enumerate() {
+        result.extend_from_slice(chunk);
+        if i < (len - first) / 3 - 1 { result.push(b','); }
+    }
+    String::from_utf8(result).unwrap_or_default()
+}
+
+/// ファイルサイズをキロ単位（切り上げ）の文字列にフォーマットする
+/// `binary_units` なら 1024 バイト単位の `KiB`、そうでなければ 1000 バイト単位の `KB`
+/// フォルダはサイズを表示せず空文字列にする（空のファイルは `0 KiB` / `0 KB`）
+pub fn format_size(bytes: u64, is_folder: bool, binary_units: bool) -> String {
+    if is_folder { return "".to_string(); }
+    let (divisor, suffix) = if binary_units { (1024, "KiB") } else { (1000, "KB") };
+    format!("{} {}", format_with_commas(bytes.div_ceil(divisor)), suffix)
+}
+
+/// FILETIME(u64)を"YYYY-MM-DD HH:MM"形式の文字列に変換する（UTC）
+/// FileTimeToSystemTime と同様に、負の値として扱われる範囲は変換しない
+pub fn format_date(filetime: u64) -> String {
+    if filetime == 0 || filetime > i64::MAX as u64 { return String::new(); }
+    let seconds = filetime / FILETIME_TICKS_PER_SECOND;
+    let days = (seconds / 86_400) as i64;
+    let seconds_of_day = seconds % 86_400;
+    let (year, month, day) = civil_from_days(days - DAYS_FROM_1601_TO_1970);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, seconds_of_day / 3600, seconds_of_day % 3600 / 60)
+}
+
+/// FILETIME(u64)をISO 8601形式（`YYYY-MM-DDTHH:MM:SSZ`、UTC）の文字列に変換する
+/// 日時が不明（0）または変換できない値なら None
+pub fn format_iso8601(filetime: u64) -> Option<String> {
+    if filetime == 0 || filetime > i64::MAX as u64 { return None; }
+    let seconds = filetime / FILETIME_TICKS_PER_SECOND;
+    let days = (seconds / 86_400) as i64;
+    let seconds_of_day = seconds % 86_400;
+    let (year, month, day) = civil_from_days(days - DAYS_FROM_1601_TO_1970);
+    Some(format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, seconds_of_day / 3600, seconds_of_day % 3600 / 60, seconds_of_day % 60))
+}
+
+/// JSONの文字列リテラルにする（前後の `"` を含む）
+pub fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// 検索結果1件をJSONのオブジェクトにする（`path` はフォルダではなくフルパス）
+/// 日時が不明な場合の `modified` は null
+pub fn result_json(result: &FileResult) -> String {
+    let modified = format_iso8601(result.modified_date).map_or("null".to_string(), |date| json_string(&date));
+    format!(
+        "{{\"name\":{},\"path\":{},\"size\":{},\"modified\":{},\"is_folder\":{}}}",
+        json_string(&result.name), json_string(&result.full_path), result.size, modified, result.is_folder
+    )
+}
+
+/// フルパスを検索範囲のフォルダからの相対パスにする（大文字・小文字は区別しない）
+/// 検索範囲の外にある項目や検索範囲のフォルダそのものは、フルパスのまま返す
+pub fn relative_to_scope(full_path: &str, scope: &str) -> String {
+    let scope = scope.trim_end_matches('\\');
+    match (full_path.get(..scope.len()), full_path.get(scope.len()..)) {
+        (Some(prefix), Some(rest)) if prefix.eq_ignore_ascii_case(scope) && rest.len() > 1 && rest.starts_with('\\') => rest[1..].to_string(),
+        _ => full_path.to_string(),
+    }
+}
+
+/// リストの型付き検索で、名前が入力した文字で始まるか（大文字・小文字は区別しない）
+pub fn starts_with_ignore_case(name: &str, prefix: &str) -> bool {
+    let mut name_chars = name.chars().flat_map(char::to_lowercase);
+    prefix.chars().flat_map(char::to_lowercase).all(|c| name_chars.next() == Some(c))
+}
+
+/// UTF-16の文字列を `max_units` 単位以内に切り詰める（サロゲートペアの途中では切らない）
+pub fn truncate_utf16(wide: &mut Vec<u16>, max_units: usize) {
+    if wide.len() <= max_units { return; }
+    wide.truncate(max_units);
+    if wide.last().is_some_and(|unit| (0xD800..0xDC00).contains(unit)) {
+        wide.pop();
+    }
+}
+
+/// Markdownの表のセル用に `|` と改行をエスケープする
+pub fn escape_markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace(['\r', '\n'], " ")
+}
+
+/// UTF-16単位ごとの部分幅（GetTextExtentExPointW の結果）を、文字（`char`）ごとの部分幅に変換する
+/// サロゲートペアの文字は2単位を占めるため、その文字の末尾の単位の幅を使う
+/// 部分幅が足りない場合は取得できた最後の幅で埋める
+pub fn char_extents(text: &str, unit_extents: &[i32]) -> Vec<i32> {
+    let mut units = 0;
+    text.chars()
+        .map(|c| {
+            units += c.len_utf16();
+            unit_extents.get(units - 1).or(unit_extents.last()).copied().unwrap_or(0)
+        })
+        .collect()
+}
+
+/// 幅 `width` のセルに表示する先頭の文字数と、末尾に省略記号を付けるかを返す
+/// `extents[i]` は先頭から i + 1 文字までの描画幅（GetTextExtentExPointW が返す部分幅と同じ形）
+/// 省略記号も収まらないほど狭い場合は、省略記号を付けずに収まるだけの文字を表示する
+pub fn fit_text(extents: &[i32], width: i32, ellipsis_width: i32) -> (usize, bool) {
+    let fits_within = |limit: i32| extents.partition_point(|&extent| extent <= limit);
+    let fitting = fits_within(width);
+    if fitting == extents.len() { return (fitting, false); }
+    if ellipsis_width > width { return (fitting, false); }
+    (fits_within(width - ellipsis_width), true)
+}
+
+/// 列に収まらない文字列を省略する位置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EllipsisPosition {
+    #[default]
+    End,
+    Middle,
+    Start,
+}
+
+impl EllipsisPosition {
+    /// メニューに並べる順のすべての位置
+    pub const ALL: [EllipsisPosition; 3] = [EllipsisPosition::End, EllipsisPosition::Middle, EllipsisPosition::Start];
+
+    /// メニューに表示する名前
+    pub fn label(self) -> &'static str {
+        match self {
+            EllipsisPosition::End => "末尾を省略",
+            EllipsisPosition::Middle => "中央を省略",
+            EllipsisPosition::Start => "先頭を省略",
+        }
+    }
+
+    /// 設定ファイルに保存する値
+    pub fn config_value(self) -> &'static str {
+        match self {
+            EllipsisPosition::End => "end",
+            EllipsisPosition::Middle => "middle",
+            EllipsisPosition::Start => "start",
+        }
+    }
+
+    /// 設定ファイルの値から位置を得る
+    pub fn from_config_value(value: &str) -> Option<Self> {
+        EllipsisPosition::ALL.into_iter().find(|position| position.config_value() == value)
+    }
+}
+
+/// `elide_text` の結果
+/// 先頭の `head` 文字と、`tail` 文字目から末尾までを表示し、`ellipsis` なら間に省略記号を挟む
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elision {
+    pub head: usize,
+    pub tail: usize,
+    pub ellipsis: bool,
+}
+
+/// 幅 `width` のセルにパスを表示するとき、省略記号で省く範囲を決める
+/// 末尾の省略は `fit_text` と同じ。中央の省略はドライブ（`C:\`）を残し、先頭の省略は何も残さずに、
+/// 末尾側をフォルダの区切りから始まるよう切り詰める（`C:\...\project\src`）
+/// 末尾のフォルダ名だけでも収まらない場合は文字の途中から表示する
+pub fn elide_text(text: &str, extents: &[i32], width: i32, ellipsis_width: i32, position: EllipsisPosition) -> Elision {
+    let len = extents.len();
+    let (fitting, ellipsis) = fit_text(extents, width, ellipsis_width);
+    if !ellipsis || position == EllipsisPosition::End {
+        return Elision { head: fitting, tail: len, ellipsis };
+    }
+
+    let extent_at = |index: usize| index.checked_sub(1).map_or(0, |i| extents[i]);
+    let total = extent_at(len);
+    let available = width - ellipsis_width;
+    let chars: Vec<char> = text.chars().take(len).collect();
+
+    let mut head = if position == EllipsisPosition::Middle { path_root_len(&chars) } else { 0 };
+    // ドライブが長いネットワークパスなどでは、末尾の表示を優先する
+    if extent_at(head) > available / 2 { head = 0; }
+    let tail_limit = available - extent_at(head);
+    let mut tail = (head..=len).find(|&i| total - extent_at(i) <= tail_limit).unwrap_or(len);
+    if let Some(separator) = (tail..len).find(|&i| chars.get(i) == Some(&'\\') && i + 1 < len) {
+        tail = separator;
+    }
+    Elision { head, tail, ellipsis: true }
+}
+
+/// パスの先頭のドライブ（`C:\`）やサーバー（`\\server\`）の部分の文字数（区切りを含む）
+fn path_root_len(chars: &[char]) -> usize {
+    let name_start = chars.iter().position(|&c| c != '\\').unwrap_or(chars.len());
+    chars[name_start..].iter().position(|&c| c == '\\').map_or(0, |i| name_start + i + 1)
+}
+
+/// UNIXエポックからの日数をグレゴリオ暦の (年, 月, 日) に変換する
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// UNIX時刻（秒）をFILETIMEに変換する
+    fn filetime_from_unix(seconds: u64) -> u64 {
+        (seconds + DAYS_FROM_1601_TO_1970 as u64 * 86_400) * FILETIME_TICKS_PER_SECOND
+    }
+
+    #[test]
+    fn commas_at_group_boundaries() {
+        assert_eq!(format_with_commas(0), "0");
+        assert_eq!(format_with_commas(999), "999");
+        assert_eq!(format_with_commas(1000), "1,000");
+        assert_eq!(format_with_commas(999_999), "999,999");
+        assert_eq!(format_with_commas(1_000_000), "1,000,000");
+        assert_eq!(format_with_commas(12_345_678), "12,345,678");
+        assert_eq!(format_with_commas(u64::MAX), "18,446,744,073,709,551,615");
+    }
+
+    #[test]
+    fn size_rounds_up_to_kilobytes() {
+        assert_eq!(format_size(1, false, true), "1 KiB");
+        assert_eq!(format_size(1024, false, true), "1 KiB");
+        assert_eq!(format_size(1025, false, true), "2 KiB");
+        assert_eq!(format_size(1024 * 1000, false, true), "1,000 KiB");
+    }
+
+    #[test]
+    fn decimal_units_divide_by_1000() {
+        assert_eq!(format_size(1000, false, false), "1 KB");
+        assert_eq!(format_size(1001, false, false), "2 KB");
+        assert_eq!(format_size(1024, false, false), "2 KB");
+        assert_eq!(format_size(1_000_000, false, false), "1,000 KB");
+    }
+
+    #[test]
+    fn empty_file_has_a_size_but_folder_does_not() {
+        assert_eq!(format_size(0, false, true), "0 KiB");
+        assert_eq!(format_size(0, false, false), "0 KB");
+        assert_eq!(format_size(0, true, true), "");
+        assert_eq!(format_size(4096, true, false), "");
+    }
+
+    #[test]
+    fn date_is_formatted_in_utc() {
+        assert_eq!(format_date(0), "");
+        assert_eq!(format_date(1), "1601-01-01 00:00");
+        assert_eq!(format_date(filetime_from_unix(0)), "1970-01-01 00:00");
+        // 2024-01-02 03:04:59 UTC
+        assert_eq!(format_date(filetime_from_unix(1_704_164_699)), "2024-01-02 03:04");
+        // うるう日
+        assert_eq!(format_date(filetime_from_unix(1_709_164_800)), "2024-02-29 00:00");
+        assert_eq!(format_date(u64::MAX), "");
+    }
+
+    #[test]
+    fn highlight_without_markers() {
+        assert_eq!(parse_highlight_text(""), (String::new(), vec![]));
+        assert_eq!(parse_highlight_text("plain.txt"), ("plain.txt".to_string(), vec![]));
+    }
+
+    #[test]
+    fn highlight_with_multiple_ranges() {
+        let (text, ranges) = parse_highlight_text("*re*port_*2024*.pdf");
+        assert_eq!(text, "report_2024.pdf");
+        assert_eq!(ranges, vec![(0, 2), (7, 11)]);
+    }
+
+    #[test]
+    fn highlight_ranges_count_characters_not_bytes() {
+        let (text, ranges) = parse_highlight_text("議事録_*検索*.docx");
+        assert_eq!(text, "議事録_検索.docx");
+        assert_eq!(ranges, vec![(4, 6)]);
+    }
+
+    #[test]
+    fn unterminated_highlight_is_dropped() {
+        assert_eq!(parse_highlight_text("ab*cd"), ("abcd".to_string(), vec![]));
+    }
+
+    #[test]
+    fn char_extents_follow_utf16_units() {
+        assert_eq!(char_extents("abc", &[3, 6, 9]), vec![3, 6, 9]);
+        // 「𠮷」はサロゲートペア（2単位）
+        assert_eq!(char_extents("a𠮷b", &[3, 5, 12, 15]), vec![3, 12, 15]);
+        assert_eq!(char_extents("", &[]), Vec::<i32>::new());
+    }
+
+    #[test]
+    fn char_extents_pad_missing_units() {
+        assert_eq!(char_extents("abc", &[3]), vec![3, 3, 3]);
+        assert_eq!(char_extents("ab", &[]), vec![0, 0]);
+    }
+
+    #[test]
+    fn text_that_fits_is_not_truncated() {
+        assert_eq!(fit_text(&[5, 10, 15], 15, 9), (3, false));
+        assert_eq!(fit_text(&[], 0, 9), (0, false));
+    }
+
+    #[test]
+    fn truncated_text_leaves_room_for_ellipsis() {
+        assert_eq!(fit_text(&[5, 10, 15, 20], 18, 9), (1, true));
+        // 省略記号しか入らない幅では文字を表示せず省略記号だけを付ける
+        assert_eq!(fit_text(&[5, 10, 15, 20], 12, 9), (0, true));
+    }
+
+    #[test]
+    fn column_narrower_than_ellipsis_shows_leading_characters() {
+        // 5px 幅の列: 省略記号 (9px) は入らないので、収まる1文字だけを表示する
+        assert_eq!(fit_text(&[3, 6, 9], 5, 9), (1, false));
+        assert_eq!(fit_text(&[6, 12], 5, 9), (0, false));
+        assert_eq!(fit_text(&[3, 6], -4, 9), (0, false));
+    }
+
+    /// 1文字1pxとした部分幅
+    fn unit_extents(text: &str) -> Vec<i32> {
+        (1..=text.chars().count() as i32).collect()
+    }
+
+    #[test]
+    fn path_is_elided_at_folder_boundaries() {
+        let path = r"C:\Users\me\project\src";
+        let extents = unit_extents(path);
+        let elide = |width, position| elide_text(path, &extents, width, 3, position);
+        assert_eq!(elide(20, EllipsisPosition::Middle), Elision { head: 3, tail: 11, ellipsis: true });
+        assert_eq!(elide(16, EllipsisPosition::Middle), Elision { head: 3, tail: 19, ellipsis: true });
+        assert_eq!(elide(16, EllipsisPosition::Start), Elision { head: 0, tail: 11, ellipsis: true });
+        assert_eq!(elide(16, EllipsisPosition::End), Elision { head: 13, tail: 23, ellipsis: true });
+        assert_eq!(elide(23, EllipsisPosition::Middle), Elision { head: 23, tail: 23, ellipsis: false });
+    }
+
+    #[test]
+    fn long_last_folder_is_cut_mid_name() {
+        let path = r"C:\averyveryverylongfolder";
+        let extents = unit_extents(path);
+        // ドライブを残すと末尾がほとんど見えない幅では、ドライブも省く
+        assert_eq!(elide_text(path, &extents, 8, 3, EllipsisPosition::Middle), Elision { head: 0, tail: 21, ellipsis: true });
+        assert_eq!(elide_text(path, &extents, 13, 3, EllipsisPosition::Middle), Elision { head: 3, tail: 19, ellipsis: true });
+        let unc = r"\\server\share\folder\file";
+        assert_eq!(path_root_len(&unc.chars().collect::<Vec<_>>()), 9);
+    }
+
+    #[test]
+    fn markdown_cell_escapes_pipes_and_newlines() {
+        assert_eq!(escape_markdown_cell("a|b\r\nc"), "a\\|b  c");
+    }
+
+    #[test]
+    fn name_and_folder_ranges_are_shifted_past_the_separator() {
+        let (text, ranges, separator_from) = join_name_and_folder("報告.txt", &[(0, 2)], "C:\\報告", &[(3, 5)]);
+        assert_eq!(text, "報告.txt — C:\\報告");
+        assert_eq!(ranges, vec![(0, 2), (12, 14)]);
+        assert_eq!(separator_from, 6);
+        let chars: Vec<char> = text.chars().collect();
+        assert_eq!(chars[12..14].iter().collect::<String>(), "報告");
+    }
+
+    #[test]
+    fn paths_inside_the_scope_become_relative() {
+        assert_eq!(relative_to_scope(r"C:\work\proj\src\main.rs", r"C:\work\proj"), r"src\main.rs");
+        assert_eq!(relative_to_scope(r"C:\Work\Proj\a.txt", r"c:\work\proj\"), "a.txt");
+        assert_eq!(relative_to_scope(r"D:\a.txt", r"D:\"), "a.txt");
+    }
+
+    #[test]
+    fn paths_outside_the_scope_stay_absolute() {
+        assert_eq!(relative_to_scope(r"C:\work\project2\a.txt", r"C:\work\proj"), r"C:\work\project2\a.txt");
+        assert_eq!(relative_to_scope(r"C:\work\proj", r"C:\work\proj"), r"C:\work\proj");
+        assert_eq!(relative_to_scope(r"C:\a.txt", r"C:\work\proj"), r"C:\a.txt");
+    }
+
+    #[test]
+    fn json_escapes_quotes_backslashes_and_controls() {
+        assert_eq!(json_string("C:\\a \"b\"\n\u{1}"), "\"C:\\\\a \\\"b\\\"\\n\\u0001\"");
+        assert_eq!(json_string("報告"), "\"報告\"");
+    }
+
+    #[test]
+    fn result_json_uses_full_path_and_utc_date() {
+        let result = FileResult { modified_date: filetime_from_unix(1_704_164_699), ..FileResult::test_file("C:\\docs", "a.txt", 42) };
+        assert_eq!(
+            result_json(&result),
+            r#"{"name":"a.txt","path":"C:\\docs\\a.txt","size":42,"modified":"2024-01-02T03:04:59Z","is_folder":false}"#
+        );
+        let folder = FileResult { name: "docs".to_string(), path: "C:\\".to_string(), full_path: "C:\\docs".to_string(), modified_date: 0, is_folder: true, ..result };
+        assert_eq!(result_json(&folder), r#"{"name":"docs","path":"C:\\docs","size":42,"modified":null,"is_folder":true}"#);
+    }
+
+    #[test]
+    fn type_ahead_prefix_ignores_case() {
+        assert!(starts_with_ignore_case("Readme.md", "read"));
+        assert!(starts_with_ignore_case("ÄBC.txt", "äb"));
+        assert!(starts_with_ignore_case("メモ.txt", "メモ"));
+        assert!(starts_with_ignore_case("a", ""));
+        assert!(!starts_with_ignore_case("ab", "abc"));
+        assert!(!starts_with_ignore_case("notes.txt", "te"));
+    }
+
+    #[test]
+    fn truncate_utf16_keeps_surrogate_pairs() {
+        let mut wide: Vec<u16> = "ab😀c".encode_utf16().collect();
+        truncate_utf16(&mut wide, 3);
+        assert_eq!(String::from_utf16(&wide).unwrap(), "ab");
+
+        let mut wide: Vec<u16> = "ab😀c".encode_utf16().collect();
+        truncate_utf16(&mut wide, 4);
+        assert_eq!(String::from_utf16(&wide).unwrap(), "ab😀");
+
+        let mut wide: Vec<u16> = "abc".encode_utf16().collect();
+        truncate_utf16(&mut wide, 10);
+        assert_eq!(String::from_utf16(&wide).unwrap(), "abc");
+    }
+}
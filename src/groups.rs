@@ -0,0 +1,177 @@
+// --- フォルダごとのグループ表示 ---
+//
+// 検索結果を含まれるフォルダごとにまとめ、各グループの前に見出しの行を差し込んだ行の並びを作る。
+// グループは結果の並び順で最初に現れた順に、グループ内は元の並び順のまま並べる。
+// 見出しの行は検索結果ではないため、行から結果を引くときは `result` が None を返す。
+
+use crate::backend::FileResult;
+
+use std::collections::HashMap;
+
+/// 1つのフォルダのグループ
+#[derive(Debug)]
+pub struct FolderGroup {
+    /// フォルダのパス（最初に現れた結果の表記）
+    pub folder: String,
+    pub results: Vec<FileResult>,
+    /// 折りたたまれているか（見出しの行だけを表示する）
+    pub collapsed: bool,
+}
+
+impl FolderGroup {
+    /// 見出しの行に表示する文字列（折りたたみの状態・フォルダ・件数）
+    pub fn label(&self) -> String {
+        format!("{} {} ({}件)", if self.collapsed { '▶' } else { '▼' }, self.folder, self.results.len())
+    }
+}
+
+/// リストの1行の中身
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Row {
+    /// グループの見出し（グループの番号）
+    Header(usize),
+    /// 検索結果（グループの番号とグループ内の番号）
+    Item(usize, usize),
+}
+
+/// フォルダごとのグループをリストの行として並べたもの
+#[derive(Debug, Default)]
+pub struct FolderGroupList {
+    groups: Vec<FolderGroup>,
+    /// 表示中の行（折りたたまれたグループの結果は含まない）
+    rows: Vec<Row>,
+}
+
+impl FolderGroupList {
+    /// 検索結果をフォルダごとにまとめる。フォルダは大文字と小文字を区別せずに比べる
+    /// `collapsed_folders` に含まれるフォルダのグループは折りたたんだ状態にする（検索し直しても状態を保つため）
+    pub fn new(results: impl IntoIterator<Item = FileResult>, collapsed_folders: &[String]) -> Self {
+        let mut groups: Vec<FolderGroup> = Vec::new();
+        let mut group_index: HashMap<String, usize> = HashMap::new();
+        for result in results {
+            let key = result.path.to_lowercase();
+            let index = *group_index.entry(key.clone()).or_insert_with(|| {
+                let collapsed = collapsed_folders.iter().any(|folder| folder.to_lowercase() == key);
+                groups.push(FolderGroup { folder: result.path.clone(), results: Vec::new(), collapsed });
+                groups.len() - 1
+            });
+            groups[index].results.push(result);
+        }
+        let mut list = Self { groups, rows: Vec::new() };
+        list.rebuild_rows();
+        list
+    }
+
+    /// 折りたたみの状態から表示する行を並べ直す
+    fn rebuild_rows(&mut self) {
+        self.rows.clear();
+        for (index, group) in self.groups.iter().enumerate() {
+            self.rows.push(Row::Header(index));
+            if !group.collapsed {
+                self.rows.extend((0..group.results.len()).map(|item| Row::Item(index, item)));
+            }
+        }
+    }
+
+    /// 表示中の行数（見出しの行を含む）
+    pub fn row_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// グループの数
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// まとめた検索結果の件数（折りたたまれたグループの結果も含む）
+    pub fn result_count(&self) -> usize {
+        self.groups.iter().map(|group| group.results.len()).sum()
+    }
+
+    /// 指定行の検索結果（見出しの行や範囲外なら None）
+    pub fn result(&self, index: usize) -> Option<&FileResult> {
+        match self.rows.get(index)? {
+            Row::Item(group, item) => self.groups[*group].results.get(*item),
+            Row::Header(_) => None,
+        }
+    }
+
+    /// 指定行が見出しならそのグループ
+    pub fn header(&self, index: usize) -> Option<&FolderGroup> {
+        match self.rows.get(index)? {
+            Row::Header(group) => self.groups.get(*group),
+            Row::Item(..) => None,
+        }
+    }
+
+    /// 指定行の見出しのグループを折りたたむ（展開する）。見出しの行でなければ何もせず false を返す
+    pub fn toggle(&mut self, index: usize) -> bool {
+        let Some(Row::Header(group)) = self.rows.get(index).copied() else { return false };
+        self.groups[group].collapsed = !self.groups[group].collapsed;
+        self.rebuild_rows();
+        true
+    }
+
+    /// 折りたたまれているグループのフォルダ
+    pub fn collapsed_folders(&self) -> Vec<String> {
+        self.groups.iter().filter(|group| group.collapsed).map(|group| group.folder.clone()).collect()
+    }
+
+    /// 条件に合う検索結果の行を探す（折りたたまれたグループの結果は探さない）
+    pub fn position(&self, mut predicate: impl FnMut(&FileResult) -> bool) -> Option<usize> {
+        (0..self.rows.len()).find(|&index| self.result(index).is_some_and(&mut predicate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, name: &str) -> FileResult {
+        FileResult::test_file(path, name, 0)
+    }
+
+    fn row_labels(list: &FolderGroupList) -> Vec<String> {
+        (0..list.row_count())
+            .map(|index| match (list.header(index), list.result(index)) {
+                (Some(group), _) => format!("[{}]", group.folder),
+                (_, Some(result)) => result.name.clone(),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn groups_in_order_of_first_appearance() {
+        let list = FolderGroupList::new(
+            vec![file("C:\\b", "1"), file("C:\\a", "2"), file("c:\\B", "3"), file("C:\\a", "4")],
+            &[],
+        );
+        assert_eq!(list.group_count(), 2);
+        assert_eq!(list.result_count(), 4);
+        assert_eq!(row_labels(&list), vec!["[C:\\b]", "1", "3", "[C:\\a]", "2", "4"]);
+    }
+
+    #[test]
+    fn collapsing_hides_the_group_results() {
+        let mut list = FolderGroupList::new(vec![file("C:\\a", "1"), file("C:\\a", "2"), file("C:\\b", "3")], &[]);
+        assert!(!list.toggle(1));
+        assert!(list.toggle(0));
+        assert_eq!(row_labels(&list), vec!["[C:\\a]", "[C:\\b]", "3"]);
+        assert_eq!(list.header(0).unwrap().label(), "▶ C:\\a (2件)");
+        assert_eq!(list.header(1).unwrap().label(), "▼ C:\\b (1件)");
+        assert_eq!(list.collapsed_folders(), vec!["C:\\a".to_string()]);
+        assert_eq!(list.position(|result| result.name == "3"), Some(2));
+        assert_eq!(list.position(|result| result.name == "1"), None);
+
+        assert!(list.toggle(0));
+        assert_eq!(list.row_count(), 5);
+    }
+
+    #[test]
+    fn collapsed_folders_are_kept_across_searches() {
+        let list = FolderGroupList::new(vec![file("C:\\a", "1"), file("C:\\b", "2")], &["c:\\A".to_string()]);
+        assert_eq!(row_labels(&list), vec!["[C:\\a]", "[C:\\b]", "2"]);
+        assert!(list.header(0).unwrap().collapsed);
+    }
+}
@@ -0,0 +1,35 @@
+// --- 最近使った項目の一覧 ---
+
+/// 項目を一覧の先頭に追加する
+/// 既に含まれていれば先頭へ移し、`max` 件を超えた古い項目は捨てる
+pub fn push_recent<T: PartialEq>(list: &mut Vec<T>, item: T, max: usize) {
+    list.retain(|existing| *existing != item);
+    list.insert(0, item);
+    list.truncate(max);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn newest_item_comes_first() {
+        let mut list = vec!["b", "a"];
+        push_recent(&mut list, "c", 10);
+        assert_eq!(list, vec!["c", "b", "a"]);
+    }
+
+    #[test]
+    fn existing_item_moves_to_front() {
+        let mut list = vec!["a", "b", "c"];
+        push_recent(&mut list, "c", 10);
+        assert_eq!(list, vec!["c", "a", "b"]);
+    }
+
+    #[test]
+    fn oldest_items_are_dropped_over_the_limit() {
+        let mut list = vec!["a", "b", "c"];
+        push_recent(&mut list, "d", 3);
+        assert_eq!(list, vec!["d", "a", "b"]);
+    }
+}
@@ -0,0 +1,201 @@
+// --- シェルから取得するファイル情報（アイコン・種類名）のキャッシュ ---
+//
+// 同じ種類のファイルはほぼ同じアイコンと種類名を共有するため、拡張子ごとにキャッシュする。
+// exe や lnk のようにファイルごとにアイコンが異なるものは、ワーカースレッドで
+// 実ファイルから取得し、完了したら WM_ICON_LOADED でウィンドウに通知する。
+
+use windows::{
+    core::*,
+    Win32::Foundation::*,
+    Win32::Storage::FileSystem::{FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_NORMAL, FILE_FLAGS_AND_ATTRIBUTES},
+    Win32::System::Com::{CoInitializeEx, CoUninitialize, COINIT_APARTMENTTHREADED},
+    Win32::UI::Shell::{SHGetFileInfoW, SHFILEINFOW, SHGFI_FLAGS, SHGFI_SYSICONINDEX, SHGFI_TYPENAME, SHGFI_USEFILEATTRIBUTES},
+    Win32::UI::WindowsAndMessaging::{PostMessageW, WM_APP},
+};
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::c_void;
+use std::path::Path;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+/// ワーカースレッドがファイル固有のアイコンを取得し終えたときの通知メッセージ
+/// WPARAM: アイコンインデックス、LPARAM: フルパスを格納した `Box<String>` のポインタ
+pub const WM_ICON_LOADED: u32 = WM_APP + 1;
+
+/// ファイルごとにアイコンが異なる拡張子
+const PER_FILE_ICON_EXTENSIONS: [&str; 7] = ["exe", "lnk", "ico", "url", "cpl", "scr", "appref-ms"];
+
+/// アイコンインデックスのキャッシュ
+#[derive(Default)]
+pub struct IconCache {
+    /// 拡張子（小文字）ごとのアイコンインデックス
+    by_extension: HashMap<String, i32>,
+    /// フォルダのアイコンインデックス（すべてのフォルダで共通）
+    folder: Option<i32>,
+    /// ファイル固有のアイコンインデックス（フルパスごと）
+    by_path: HashMap<String, i32>,
+    /// ワーカースレッドで取得中のフルパス
+    pending: HashSet<String>,
+    /// ワーカースレッドへの要求の送り口
+    request_tx: Option<Sender<String>>,
+}
+
+impl IconCache {
+    /// アイテムのアイコンインデックスを返す
+    /// ファイル固有のアイコンが未取得の場合はワーカーに要求し、それまでは拡張子のアイコンを返す
+    pub fn get(&mut self, name: &str, full_path: &Path, is_folder: bool, notify_hwnd: HWND) -> i32 {
+        if !is_folder && has_per_file_icon(name) {
+            let path_key = full_path.to_string_lossy().into_owned();
+            if let Some(&index) = self.by_path.get(&path_key) {
+                return index;
+            }
+            self.request(path_key, notify_hwnd);
+        }
+
+        self.generic(name, is_folder, || {
+            let attributes = if is_folder { FILE_ATTRIBUTE_DIRECTORY } else { FILE_ATTRIBUTE_NORMAL };
+            shell_icon_index(name, attributes, SHGFI_USEFILEATTRIBUTES)
+        })
+    }
+
+    /// フォルダまたは拡張子ごとの共通アイコンを返す
+    /// キャッシュにない場合だけ `load` を呼び出して結果を保存する
+    fn generic(&mut self, name: &str, is_folder: bool, load: impl FnOnce() -> i32) -> i32 {
+        if is_folder {
+            *self.folder.get_or_insert_with(load)
+        } else {
+            *self.by_extension.entry(extension_of(name)).or_insert_with(load)
+        }
+    }
+
+    /// ワーカーから届いたファイル固有のアイコンを登録する
+    pub fn complete(&mut self, full_path: String, index: i32) {
+        self.pending.remove(&full_path);
+        self.by_path.insert(full_path, index);
+    }
+
+    /// ファイル固有のアイコンの取得をワーカーに要求する（取得中なら何もしない）
+    fn request(&mut self, path_key: String, notify_hwnd: HWND) {
+        if !self.pending.insert(path_key.clone()) { return; }
+        let tx = self.request_tx.get_or_insert_with(|| spawn_worker(notify_hwnd));
+        if tx.send(path_key.clone()).is_err() {
+            self.pending.remove(&path_key);
+        }
+    }
+}
+
+/// ファイルの種類名（エクスプローラの「種類」列と同じ表記）のキャッシュ
+#[derive(Default)]
+pub struct TypeNameCache {
+    /// 拡張子（小文字）ごとの種類名
+    by_extension: HashMap<String, String>,
+    /// フォルダの種類名
+    folder: Option<String>,
+}
+
+impl TypeNameCache {
+    /// アイテムの種類名を返す
+    pub fn get(&mut self, name: &str, is_folder: bool) -> String {
+        if is_folder {
+            self.folder.get_or_insert_with(|| shell_type_name(name, FILE_ATTRIBUTE_DIRECTORY)).clone()
+        } else {
+            self.by_extension
+                .entry(extension_of(name))
+                .or_insert_with(|| shell_type_name(name, FILE_ATTRIBUTE_NORMAL))
+                .clone()
+        }
+    }
+}
+
+/// ファイル固有のアイコンを取得するワーカースレッドを起動する
+fn spawn_worker(notify_hwnd: HWND) -> Sender<String> {
+    let (tx, rx) = mpsc::channel::<String>();
+    // HWNDはスレッド間で送れないため数値として渡す
+    let hwnd_raw = notify_hwnd.0 as isize;
+    thread::spawn(move || {
+        let com_initialized = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) }.is_ok();
+        for full_path in rx {
+            let index = shell_icon_index(&full_path, FILE_FLAGS_AND_ATTRIBUTES(0), SHGFI_FLAGS(0));
+            let path_ptr = Box::into_raw(Box::new(full_path));
+            let posted = unsafe {
+                PostMessageW(Some(HWND(hwnd_raw as *mut c_void)), WM_ICON_LOADED, WPARAM(index as usize), LPARAM(path_ptr as isize))
+            };
+            if posted.is_err() {
+                // ウィンドウが既に破棄されている
+                drop(unsafe { Box::from_raw(path_ptr) });
+                break;
+            }
+        }
+        if com_initialized { unsafe { CoUninitialize() }; }
+    });
+    tx
+}
+
+/// シェルからシステムイメージリストのアイコンインデックスを取得する
+fn shell_icon_index(path: &str, attributes: FILE_FLAGS_AND_ATTRIBUTES, flags: SHGFI_FLAGS) -> i32 {
+    let mut shfi: SHFILEINFOW = unsafe { std::mem::zeroed() };
+    let path_w: Vec<u16> = path.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        SHGetFileInfoW(PCWSTR(path_w.as_ptr()), attributes, Some(&mut shfi as *mut _), std::mem::size_of::<SHFILEINFOW>() as u32, flags | SHGFI_SYSICONINDEX);
+    }
+    shfi.iIcon
+}
+
+/// シェルからファイルの種類名を取得する
+fn shell_type_name(name: &str, attributes: FILE_FLAGS_AND_ATTRIBUTES) -> String {
+    let mut shfi: SHFILEINFOW = unsafe { std::mem::zeroed() };
+    let name_w: Vec<u16> = name.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        SHGetFileInfoW(PCWSTR(name_w.as_ptr()), attributes, Some(&mut shfi as *mut _), std::mem::size_of::<SHFILEINFOW>() as u32, SHGFI_TYPENAME | SHGFI_USEFILEATTRIBUTES);
+    }
+    let len = shfi.szTypeName.iter().position(|&c| c == 0).unwrap_or(shfi.szTypeName.len());
+    String::from_utf16_lossy(&shfi.szTypeName[..len])
+}
+
+/// ファイルごとにアイコンが異なる種類のファイルか
+fn has_per_file_icon(name: &str) -> bool {
+    PER_FILE_ICON_EXTENSIONS.contains(&extension_of(name).as_str())
+}
+
+/// ファイル名の拡張子を小文字で返す（拡張子がなければ空文字列）
+fn extension_of(name: &str) -> String {
+    Path::new(name).extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn generic_icon_is_cached_per_extension() {
+        let mut cache = IconCache::default();
+        let calls = Cell::new(0);
+        let load = |index: i32| { calls.set(calls.get() + 1); index };
+
+        assert_eq!(cache.generic("a.txt", false, || load(3)), 3);
+        assert_eq!(cache.generic("B.TXT", false, || load(99)), 3);
+        assert_eq!(calls.get(), 1);
+
+        assert_eq!(cache.generic("c.pdf", false, || load(5)), 5);
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn folders_share_a_single_icon() {
+        let mut cache = IconCache::default();
+        assert_eq!(cache.generic("Documents", true, || 7), 7);
+        assert_eq!(cache.generic("src.old", true, || 99), 7);
+        // フォルダ名の「拡張子」はファイルのキャッシュに影響しない
+        assert_eq!(cache.generic("x.old", false, || 8), 8);
+    }
+
+    #[test]
+    fn per_file_icon_extensions() {
+        assert!(has_per_file_icon("setup.EXE"));
+        assert!(has_per_file_icon("shortcut.lnk"));
+        assert!(!has_per_file_icon("readme.txt"));
+        assert!(!has_per_file_icon("exe"));
+    }
+}
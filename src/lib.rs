@@ -0,0 +1,13 @@
+//! Migemo Everything のウィンドウに依存しない部分
+//!
+//! CLIなど別のフロントエンドからも、GUIと同じ規則で検索クエリを組み立てたり結果を整形したりできるようにする。
+
+pub mod backend;
+pub mod commands;
+pub mod duplicates;
+pub mod format;
+pub mod groups;
+pub mod history;
+pub mod paths;
+pub mod preview;
+pub mod search;
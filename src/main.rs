@@ -1,48 +1,73 @@
 // リリースビルド時にコンソールウィンドウを非表示にする
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod config;
+mod icon;
+
 // --- 依存クレート ---
 use windows::{
     core::*,
     Win32::Foundation::*,
+    Win32::Graphics::Dwm::DwmGetColorizationColor,
     Win32::Graphics::Gdi::*,
+    Win32::Globalization::{MultiByteToWideChar, MULTI_BYTE_TO_WIDE_CHAR_FLAGS},
     Win32::System::Com::{
-        CoInitializeEx, CoTaskMemFree, CoUninitialize, COINIT_APARTMENTTHREADED,
+        CoCreateInstance, CoInitializeEx, CoTaskMemFree, CoUninitialize, CLSCTX_ALL, CLSCTX_INPROC_SERVER,
+        COINIT_APARTMENTTHREADED,
+    },
+    Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS},
+    Win32::System::DataExchange::{
+        AddClipboardFormatListener, CloseClipboard, EmptyClipboard, GetClipboardData, GetClipboardOwner, IsClipboardFormatAvailable,
+        OpenClipboard, RemoveClipboardFormatListener, SetClipboardData,
     },
-    Win32::System::DataExchange::{CloseClipboard, EmptyClipboard, OpenClipboard, SetClipboardData},
-    Win32::System::LibraryLoader::GetModuleHandleA,
+    Win32::System::LibraryLoader::{GetModuleHandleA, GetModuleHandleW, GetProcAddress, LoadLibraryW},
     Win32::System::Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE},
     Win32::System::Ole::CF_UNICODETEXT,
-    Win32::System::SystemServices::SFGAO_FILESYSTEM,
-    Win32::System::Time::FileTimeToSystemTime,
-    Win32::Storage::FileSystem::{FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_NORMAL},
+    Win32::System::Registry::{RegGetValueW, HKEY_LOCAL_MACHINE, RRF_RT_REG_SZ},
+    Win32::System::SystemServices::{MK_SHIFT, SFGAO_FILESYSTEM},
+    Win32::System::Variant::VARIANT,
+    Win32::UI::Accessibility::{HCF_HIGHCONTRASTON, HIGHCONTRASTW},
     Win32::UI::Controls::*,
     Win32::UI::HiDpi::{
-        DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
-        GetDpiForWindow,
-        SetProcessDpiAwarenessContext,
+        DPI_AWARENESS_CONTEXT, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+        PROCESS_DPI_AWARENESS, PROCESS_PER_MONITOR_DPI_AWARE,
     },
-    Win32::UI::Input::KeyboardAndMouse::SetFocus,
+    Win32::UI::Input::KeyboardAndMouse::{EnableWindow, GetFocus, GetKeyState, SetFocus, VIRTUAL_KEY, VK_BACK, VK_CONTROL, VK_DELETE, VK_ESCAPE, VK_RETURN, VK_SHIFT, VK_TAB, VK_UP},
     Win32::UI::Shell::{
-        Common::ITEMIDLIST, ShellExecuteW, SHFILEINFOW, SHGFI_SMALLICON,
-        SHGFI_SYSICONINDEX, SHGFI_USEFILEATTRIBUTES, SHGetFileInfoW, SHBindToParent,
-        SHParseDisplayName, CMINVOKECOMMANDINFO, CMF_NORMAL, IContextMenu, IShellFolder,
+        Common::ITEMIDLIST, ShellExecuteW, SHBindToParent,
+        SHParseDisplayName, SHOpenFolderAndSelectItems, SHOpenWithDialog, OPENASINFO, OAIF_ALLOW_REGISTRATION, OAIF_EXEC, SHGetImageList, SHIL_LARGE, SHIL_SMALL, DefSubclassProc, SetWindowSubclass, CMINVOKECOMMANDINFO, CMF_NORMAL, IContextMenu, IShellFolder,
+        FileOpenDialog, IFileOpenDialog, FOS_FORCEFILESYSTEM, FOS_PICKFOLDERS, SIGDN_FILESYSPATH,
+        FileOperation, IFileOperation, IShellItem, SHCreateItemFromParsingName, FOF_ALLOWUNDO, FOF_NOCONFIRMMKDIR,
+        ITaskbarList3, TaskbarList, TBPF_INDETERMINATE, TBPF_NOPROGRESS,
+        IShellWindows, IWebBrowserApp, PathCreateFromUrlW, ShellWindows,
+        SHObjectProperties, SHOP_FILEPATH, Shell_NotifyIconW, NOTIFYICONDATAW, NIF_ICON, NIF_MESSAGE, NIF_TIP, NIM_ADD, NIM_DELETE,
     },
     Win32::UI::WindowsAndMessaging::*,
 };
 
-use everything_sdk::ergo::{global, RequestFlags};
-use rustmigemo::migemo::{
-    compact_dictionary::CompactDictionary, query::query, regex_generator::RegexOperator,
-};
-
+use config::{Config, DoubleClickAction, FolderDoubleClick};
+use icon::{IconCache, TypeNameCache, WM_ICON_LOADED};
+use migemo_everything::commands::{split_command_line, SendToCommand, SEND_TO_MAX};
+use migemo_everything::duplicates::{self, DuplicateList};
+use migemo_everything::groups::{FolderGroup, FolderGroupList};
+use migemo_everything::history::push_recent;
+use migemo_everything::paths::{explorer_select_parameter, normalize_windows_path};
+use migemo_everything::preview::{decode_text, is_text_file_name, to_crlf, DecodedText, MAX_PREVIEW_FILE_SIZE, PREVIEW_BYTES};
+use migemo_everything::format::{char_extents, elide_text, escape_markdown_cell, format_date, format_size, format_with_commas, join_name_and_folder, parse_highlight_text, relative_to_scope, result_json, starts_with_ignore_case, truncate_utf16, EllipsisPosition, NAME_FOLDER_SEPARATOR};
+use migemo_everything::backend::{fetch_all, page_size_for_rows, run_search, stream_all, MIN_PAGE_SIZE, EverythingBackend, FileResult, GroupRequest, ResultPages, SearchBackend, SearchCompletion, SearchGeneration};
+use migemo_everything::search::{self, ColumnFilters, ComposedQuery, ItemKind, SearchRequest, SortKey};
+use rustmigemo::migemo::compact_dictionary::CompactDictionary;
+
+use std::any::Any;
 use std::ffi::c_void;
-use std::fs::File;
-use std::io::Read;
 use std::os::windows::ffi::OsStrExt;
+use std::os::windows::fs::MetadataExt;
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 
 // --- 定数 ---
 
@@ -54,16 +79,213 @@ const RE_BUTTON_ID: u16 = 1001;
 const MI_BUTTON_ID: u16 = 1002;
 /// コントロールID: シェルコンテキストメニュー切り替えトグル
 const SHELL_CONTEXT_TOGGLE_ID: u16 = 1003;
+/// コントロールID: 検索範囲フォルダの選択ボタン
+const SCOPE_BUTTON_ID: u16 = 1004;
+/// コントロールID: ワイルドカードボタン
+const WC_BUTTON_ID: u16 = 1005;
+/// コントロールID: 入力中に検索するかの切り替えトグル
+const LIVE_SEARCH_TOGGLE_ID: u16 = 1006;
+/// コントロールID: 結果に含めるアイテムの種類の切り替えボタン
+const ITEM_KIND_BUTTON_ID: u16 = 1007;
+/// コントロールID: 最近使った検索範囲のドロップダウンボタン
+const RECENT_SCOPES_BUTTON_ID: u16 = 1008;
+/// コントロールID: 結果内を絞り込む入力
+const NARROW_EDIT_ID: u16 = 1009;
+/// コントロールID: 検索範囲を親フォルダへ移すボタン
+const UP_BUTTON_ID: u16 = 1010;
 
 /// タイマーID
 const TIMER_ID: usize = 1;
 
+/// タイマーID: ステータスバーの一時メッセージを消す
+const STATUS_FLASH_TIMER_ID: usize = 2;
+
+/// タイマーID: 表示中の検索を一定間隔で再実行する（入力の遅延検索とは別のタイマー）
+const AUTO_REFRESH_TIMER_ID: usize = 3;
+/// 「自動更新」メニューに並べる間隔（秒、None はオフ）
+const AUTO_REFRESH_CHOICES: [Option<u32>; 5] = [None, Some(5), Some(15), Some(30), Some(60)];
+
+/// エディットボックスのサブクラスID
+const EDIT_SUBCLASS_ID: usize = 1;
+/// リストビューのサブクラスID
+const LISTVIEW_SUBCLASS_ID: usize = 2;
+
+/// 最小クライアント領域の幅（96DPI基準）
+const MIN_CLIENT_WIDTH: i32 = 500;
+/// 最小クライアント領域の高さ（96DPI基準、リスト数行分を含む）
+const MIN_CLIENT_HEIGHT: i32 = 160;
+/// プレビュー欄の高さ（96DPI基準、3行分）
+const PREVIEW_HEIGHT: i32 = 54;
+/// ファイルの内容も表示するときのプレビュー欄の高さ（96DPI基準）
+const PREVIEW_CONTENT_HEIGHT: i32 = 200;
+
+/// バックグラウンド検索が完了したときの通知メッセージ
+/// LPARAM: `Box<SearchCompletion>` のポインタ
+const WM_SEARCH_COMPLETE: u32 = WM_APP + 2;
+/// 時間のかかる処理の進捗通知（進捗ダイアログ宛て）
+/// WPARAM: 済んだ件数、LPARAM: 全体の件数
+const WM_FETCH_PROGRESS: u32 = WM_APP + 3;
+/// 時間のかかる処理の完了通知（進捗ダイアログ宛て）
+/// LPARAM: `Box<Option<Box<dyn Any + Send>>>` のポインタ（中断した場合は None）
+const WM_FETCH_DONE: u32 = WM_APP + 4;
+/// 通知領域のアイコンからのコールバックメッセージ
+/// LPARAM: アイコン上で発生したマウスメッセージ（WM_LBUTTONUP など）
+const WM_TRAY_ICON: u32 = WM_APP + 5;
+/// 通知領域のアイコンのID
+const TRAY_ICON_ID: u32 = 1;
+/// 検索ボックスでIMEの変換が始まった（または終わった）ことの通知（サブクラスから親ウィンドウ宛て）
+/// WPARAM: 変換中なら1、確定・取り消しで終わったら0
+const WM_EDIT_COMPOSITION: u32 = WM_APP + 6;
+/// プレビュー欄に表示するファイル内容の読み込みが完了したことの通知（ワーカースレッドから）
+/// LPARAM: Box<PreviewLoaded> のポインタ
+const WM_PREVIEW_LOADED: u32 = WM_APP + 7;
+
+/// これより多い件数を取得するときは進捗ダイアログを表示する
+const FETCH_PROGRESS_THRESHOLD: usize = 1_000;
+
+/// リストの型付き検索で調べる行数の上限（ページを同期的に読み込むため、全件はたどらない）
+const TYPE_AHEAD_MAX_ROWS: usize = 5_000;
+
+/// ステータスバーのペイン: 件数
+const STATUS_PANE_COUNT: usize = 0;
+/// ステータスバーのペイン: 選択状況
+const STATUS_PANE_SELECTION: usize = 1;
+/// ステータスバーのペイン: 検索モード
+const STATUS_PANE_MODE: usize = 2;
+/// ステータスバーのペイン: 適用中の絞り込み条件
+const STATUS_PANE_FILTERS: usize = 3;
+
+/// 再検索後に選択行を探す最大ページ数
+const RESTORE_SELECTION_MAX_PAGES: usize = 10;
+
+/// リストのサブアイテム: 名前
+const COLUMN_NAME: usize = 0;
+/// リストのサブアイテム: フォルダ
+const COLUMN_PATH: usize = 1;
+/// リストのサブアイテム: サイズ
+const COLUMN_SIZE: usize = 2;
+/// リストのサブアイテム: 更新日時
+const COLUMN_MODIFIED: usize = 3;
+/// リストのサブアイテム: 作成日時（表示は任意）
+const COLUMN_CREATED: usize = 4;
+/// リストのサブアイテム: 種類（表示は任意）
+const COLUMN_TYPE: usize = 5;
+/// リストのサブアイテム数
+const COLUMN_COUNT: usize = 6;
+
+/// カラムの見出しと既定の幅（96DPI基準）。添字がサブアイテム番号
+/// 列の表示/非表示で位置がずれても、サブアイテム番号は変わらない
+const COLUMNS: [(&str, f32); COLUMN_COUNT] = [
+    ("名前", 300.0),
+    ("フォルダ", 300.0),
+    ("サイズ", 80.0),
+    ("更新日時", 150.0),
+    ("作成日時", 150.0),
+    ("種類", 150.0),
+];
+
 /// メニューID: 終了
 const IDM_FILE_EXIT: u16 = 2001;
+/// メニューID: 設定ダイアログ
+const IDM_FILE_SETTINGS: u16 = 2002;
 /// メニューID: 正規表現検索
 const IDM_SEARCH_REGEX: u16 = 3001;
 /// メニューID: Migemo検索
 const IDM_SEARCH_MIGEMO: u16 = 3002;
+/// メニューID: 検索範囲フォルダの選択
+const IDM_SEARCH_SCOPE: u16 = 3003;
+/// メニューID: 検索範囲のクリア
+const IDM_SEARCH_SCOPE_CLEAR: u16 = 3004;
+/// メニューID: 除外フォルダの編集
+const IDM_SEARCH_EXCLUDES: u16 = 3005;
+/// メニューID: Everythingに渡すクエリの表示
+const IDM_SEARCH_SHOW_QUERY: u16 = 3006;
+/// メニューID: 今すぐ検索（デバウンスを待たない）
+const IDM_SEARCH_NOW: u16 = 3007;
+/// メニューID: ワイルドカード検索
+const IDM_SEARCH_WILDCARD: u16 = 3008;
+/// メニューID: 検索モードの切り替え
+const IDM_SEARCH_CYCLE_MODE: u16 = 3009;
+/// メニューID: 入力中に検索するかの切り替え
+const IDM_SEARCH_LIVE: u16 = 3010;
+/// メニューID: 通常検索で記号をそのまま検索するかの切り替え
+const IDM_SEARCH_LITERAL: u16 = 3014;
+/// メニューID: 入力したパスのファイルへ移動
+const IDM_SEARCH_GOTO: u16 = 3011;
+/// メニューID: 重複ファイルを検索（サイズと内容）
+const IDM_SEARCH_DUPLICATES: u16 = 3012;
+/// メニューID: 重複ファイルを検索（名前・サイズと内容）
+const IDM_SEARCH_DUPLICATES_BY_NAME: u16 = 3013;
+/// メニューID: 現在のクエリをEverythingで開く
+const IDM_SEARCH_OPEN_IN_EVERYTHING: u16 = 3015;
+/// メニューID: アクセント記号を区別するかの切り替え
+const IDM_SEARCH_DIACRITICS: u16 = 3016;
+/// メニューID: 検索ボックスへ移動
+const IDM_SEARCH_FOCUS_EDIT: u16 = 3017;
+/// メニューID: エクスプローラで開いているフォルダを検索範囲にする
+const IDM_SEARCH_SCOPE_EXPLORER: u16 = 3018;
+/// メニューID: 直前に検索した語に戻す
+const IDM_SEARCH_RESTORE_QUERY: u16 = 3019;
+/// メニューID: Everythingに渡すクエリのコピー
+const IDM_SEARCH_COPY_QUERY: u16 = 3020;
+/// メニューID: 最近使った検索範囲の先頭（新しい順に連番）
+const IDM_SCOPE_RECENT_FIRST: u16 = 7001;
+/// 最近使った検索範囲を覚えておく件数
+const RECENT_SCOPES_MAX: usize = 10;
+/// メニューID: 通知領域のアイコンのメニューから「表示」
+const IDM_TRAY_SHOW: u16 = 8001;
+/// メニューID: 結果に含めるアイテムの種類の先頭（`ItemKind::ALL` の順に連番）
+const IDM_SEARCH_KIND_FIRST: u16 = 3021;
+/// メニューID: 自動更新の間隔の先頭（`AUTO_REFRESH_CHOICES` の順に連番）
+const IDM_SEARCH_AUTO_REFRESH_FIRST: u16 = 3031;
+/// メニューID: シェルのメニューのコマンドを実行した後に再検索するかの切り替え
+const IDM_SEARCH_REFRESH_AFTER_SHELL: u16 = 3040;
+/// メニューID: Migemoの展開結果をパス全体と照合するか切り替え
+const IDM_SEARCH_MIGEMO_MATCH_PATH: u16 = 3041;
+/// メニューID: 結果内の絞り込み欄の表示/非表示
+const IDM_SEARCH_NARROW: u16 = 3042;
+/// メニューID: コピーした文字列で検索するか切り替え
+const IDM_SEARCH_CLIPBOARD: u16 = 3043;
+/// メニューID: 検索範囲を親フォルダへ移す
+const IDM_SEARCH_SCOPE_UP: u16 = 3044;
+/// メニューID: 並べ替えキーの先頭（`SortKey::ALL` の順に連番）
+const IDM_VIEW_SORT_FIRST: u16 = 6001;
+/// メニューID: 降順で並べ替え
+const IDM_VIEW_SORT_DESCENDING: u16 = 6050;
+/// メニューID: 2番目の並べ替えキーの先頭（「なし」、`SortKey::SECONDARY` の順に連番）
+const IDM_VIEW_SECONDARY_SORT_FIRST: u16 = 6060;
+/// メニューID: 「作成日時」列の表示切り替え
+const IDM_VIEW_COLUMN_CREATED: u16 = 6101;
+/// メニューID: 「種類」列の表示切り替え
+const IDM_VIEW_COLUMN_TYPE: u16 = 6102;
+/// メニューID: 行の背景を1行おきに塗り分けるかの切り替え
+const IDM_VIEW_STRIPED_ROWS: u16 = 6201;
+/// メニューID: 「フォルダ」列の一致箇所を強調表示するかの切り替え
+const IDM_VIEW_HIGHLIGHT_PATH: u16 = 6202;
+/// メニューID: 最前面に表示するかの切り替え
+const IDM_VIEW_ALWAYS_ON_TOP: u16 = 6203;
+/// メニューID: 最小化時に通知領域へ格納するかの切り替え
+const IDM_VIEW_MINIMIZE_TO_TRAY: u16 = 6204;
+/// メニューID: プレビュー欄を表示するかの切り替え
+const IDM_VIEW_PREVIEW: u16 = 6205;
+/// メニューID: プレビュー欄にファイルの内容を表示するかの切り替え
+const IDM_VIEW_PREVIEW_CONTENT: u16 = 6206;
+/// メニューID: 名前とフォルダを1列にまとめて表示するかの切り替え
+const IDM_VIEW_COMPACT_ROWS: u16 = 6207;
+/// メニューID: 一致箇所をアクセントカラーで強調するかの切り替え
+const IDM_VIEW_ACCENT_HIGHLIGHT: u16 = 6208;
+/// メニューID: サイズを1024バイト単位（KiB）で表示
+const IDM_VIEW_UNITS_BINARY: u16 = 6209;
+/// メニューID: サイズを1000バイト単位（KB）で表示
+const IDM_VIEW_UNITS_DECIMAL: u16 = 6210;
+/// メニューID: 検索結果をフォルダごとにまとめて表示するかの切り替え
+const IDM_VIEW_GROUP_BY_FOLDER: u16 = 6211;
+/// メニューID: ダブルクリックの動作の先頭（`DoubleClickAction::ALL` の順に連番）
+const IDM_VIEW_DOUBLE_CLICK_FIRST: u16 = 6301;
+/// メニューID: フォルダ列の省略位置の先頭（`EllipsisPosition::ALL` の順に連番）
+const IDM_VIEW_PATH_ELLIPSIS_FIRST: u16 = 6311;
+/// メニューID: フォルダをダブルクリックしたときの動作の先頭（`FolderDoubleClick::ALL` の順に連番）
+const IDM_VIEW_FOLDER_DOUBLE_CLICK_FIRST: u16 = 6321;
 
 /// アクセラレータID: 終了
 const IDA_EXIT: u16 = 5001;
@@ -71,6 +293,18 @@ const IDA_EXIT: u16 = 5001;
 const IDA_REGEX: u16 = 5002;
 /// アクセラレータID: Migemo検索
 const IDA_MIGEMO: u16 = 5003;
+/// アクセラレータID: 検索モードの切り替え
+const IDA_CYCLE_MODE: u16 = 5004;
+/// アクセラレータID: 入力したパスのファイルへ移動
+const IDA_GOTO: u16 = 5005;
+/// アクセラレータID: 検索ボックスへ移動
+const IDA_FOCUS_EDIT: u16 = 5006;
+/// アクセラレータID: エクスプローラで開いているフォルダを検索範囲にする
+const IDA_SCOPE_EXPLORER: u16 = 5007;
+/// アクセラレータID: 結果内の絞り込み欄へ移動
+const IDA_NARROW: u16 = 5008;
+/// アクセラレータID: 検索範囲を親フォルダへ移す
+const IDA_SCOPE_UP: u16 = 5009;
 
 /// コンテキストメニューID: 開く
 const IDM_CONTEXT_OPEN: u16 = 4001;
@@ -78,20 +312,125 @@ const IDM_CONTEXT_OPEN: u16 = 4001;
 const IDM_CONTEXT_OPEN_FOLDER: u16 = 4002;
 /// コンテキストメニューID: フルパスをコピー
 const IDM_CONTEXT_COPY_PATH: u16 = 4003;
+/// コンテキストメニューID: Markdownの表としてコピー
+const IDM_CONTEXT_COPY_MARKDOWN: u16 = 4004;
+/// コンテキストメニューID: プログラムから開く
+const IDM_CONTEXT_OPEN_WITH: u16 = 4005;
+/// コンテキストメニューID: フォルダへコピー
+const IDM_CONTEXT_COPY_TO: u16 = 4006;
+/// コンテキストメニューID: フォルダへ移動
+const IDM_CONTEXT_MOVE_TO: u16 = 4007;
+/// コンテキストメニューID: ターミナルで開く
+const IDM_CONTEXT_OPEN_TERMINAL: u16 = 4008;
+/// コンテキストメニューID: プロパティ
+const IDM_CONTEXT_PROPERTIES: u16 = 4009;
+/// コンテキストメニューID: ピン留め/ピン留めを外す
+const IDM_CONTEXT_PIN: u16 = 4010;
+/// コンテキストメニューID: 検索範囲からの相対パスをコピー
+const IDM_CONTEXT_COPY_RELATIVE_PATH: u16 = 4011;
+/// コンテキストメニューID: 管理者として実行
+const IDM_CONTEXT_RUN_AS_ADMIN: u16 = 4012;
+/// コンテキストメニューID: 「送る」のコマンドの先頭（設定ファイルに書いた順に連番）
+const IDM_CONTEXT_SEND_TO_FIRST: u16 = 4101;
+
+/// 「管理者として実行」を表示する拡張子（小文字）
+const RUN_AS_ADMIN_EXTENSIONS: &[&str] = &["exe", "msi", "bat"];
+
+/// 一致箇所の背景色（COLORREF、黄色）
+const HIGHLIGHT_BACK_COLOR: u32 = 0x00FFFF;
+/// アクセントカラーで強調する場合に、行の背景色をアクセントカラーへ近づける割合（1/16単位）
+const ACCENT_HIGHLIGHT_TINT: i32 = 6;
+
+/// コピー/移動の前に確認を求める件数
+const FILE_OPERATION_CONFIRM_COUNT: usize = 20;
+/// プレビュー欄でUTF-8として読めないファイルに使うコードページ（Shift_JIS）
+const SHIFT_JIS_CODE_PAGE: u32 = 932;
+/// 複数選択で「フォルダを開く」ときに確認を求めるウィンドウ数
+const OPEN_FOLDERS_CONFIRM_COUNT: usize = 5;
+/// 「送る」のコマンドをアイテムごとに実行するときに確認を求める実行回数
+const SEND_TO_CONFIRM_COUNT: usize = 5;
 
 
 // --- アプリケーションの状態管理 ---
 
-/// 検索結果のファイル情報を格納する構造体
-#[derive(Debug, Clone)]
-pub struct FileResult {
-    pub name: String,
-    pub path: String,
-    pub size: u64,
-    pub modified_date: u64,
-    pub highlighted_name: String,
-    pub highlighted_path: String,
-    pub is_folder: bool,
+/// 結果が0件のときにリスト領域に表示する状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListStatus {
+    /// 何も表示しない（未入力、または結果あり）
+    Idle,
+    /// 検索中
+    Searching,
+    /// 検索したが該当なし
+    NoResults,
+}
+
+/// 検索モード（いずれか1つだけが有効になる。MigemoとRegexだけは組み合わせて `MigemoRegex` にできる）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchMode {
+    Plain,
+    Migemo,
+    Regex,
+    /// 入力を正規表現とし、その中の英字の並びだけをMigemoで展開する
+    MigemoRegex,
+    Wildcard,
+}
+
+impl SearchMode {
+    /// 切り替えキーで次に選ばれるモード
+    pub fn next(self) -> Self {
+        match self {
+            SearchMode::Plain => SearchMode::Migemo,
+            SearchMode::Migemo => SearchMode::Regex,
+            SearchMode::Regex | SearchMode::MigemoRegex => SearchMode::Wildcard,
+            SearchMode::Wildcard => SearchMode::Plain,
+        }
+    }
+
+    /// 正規表現のON/OFFを切り替えたモード（Migemoの状態は保つ）
+    pub fn toggle_regex(self) -> Self {
+        match self {
+            SearchMode::Regex => SearchMode::Plain,
+            SearchMode::MigemoRegex => SearchMode::Migemo,
+            SearchMode::Migemo => SearchMode::MigemoRegex,
+            SearchMode::Plain | SearchMode::Wildcard => SearchMode::Regex,
+        }
+    }
+
+    /// MigemoのON/OFFを切り替えたモード（正規表現の状態は保つ）
+    pub fn toggle_migemo(self) -> Self {
+        match self {
+            SearchMode::Migemo => SearchMode::Plain,
+            SearchMode::MigemoRegex => SearchMode::Regex,
+            SearchMode::Regex => SearchMode::MigemoRegex,
+            SearchMode::Plain | SearchMode::Wildcard => SearchMode::Migemo,
+        }
+    }
+
+    /// 入力を正規表現として扱うか（RE ボタンがONか）
+    pub fn regex_input(self) -> bool {
+        matches!(self, SearchMode::Regex | SearchMode::MigemoRegex)
+    }
+
+    /// Migemoで展開するか（Mi ボタンがONか）
+    pub fn uses_migemo(self) -> bool {
+        matches!(self, SearchMode::Migemo | SearchMode::MigemoRegex)
+    }
+
+    /// Everythingの正規表現検索を使うか（Migemoの展開結果も正規表現）
+    pub fn uses_regex(self) -> bool {
+        matches!(self, SearchMode::Regex | SearchMode::Migemo | SearchMode::MigemoRegex)
+    }
+
+    /// ステータスバーに表示する名前
+    pub fn label(self) -> &'static str {
+        match self {
+            SearchMode::Plain => "通常検索",
+            SearchMode::Migemo => "Migemo",
+            SearchMode::Regex => "正規表現",
+            SearchMode::MigemoRegex => "Migemo+正規表現",
+            SearchMode::Wildcard => "ワイルドカード",
+        }
+    }
 }
 
 /// アプリケーションの状態をすべて保持する構造体
@@ -100,61 +439,415 @@ pub struct AppState {
     pub main_hwnd: HWND,
     pub status_hwnd: HWND,
     pub edit_hwnd: HWND,
+    /// 結果内を絞り込む2つ目の入力欄
+    pub narrow_hwnd: HWND,
     pub listview_hwnd: HWND,
+    pub preview_hwnd: HWND,
     pub re_button_hwnd: HWND,
     pub mi_button_hwnd: HWND,
+    pub wc_button_hwnd: HWND,
     pub shell_context_toggle_hwnd: HWND,
+    pub live_search_toggle_hwnd: HWND,
+    pub scope_button_hwnd: HWND,
+    pub recent_scopes_button_hwnd: HWND,
+    pub up_button_hwnd: HWND,
+    pub item_kind_button_hwnd: HWND,
+    /// リストビューのアイコンに使うシステムイメージリスト
+    /// システムが所有する共有のものなので破棄しない（リストビューにも LVS_SHAREIMAGELISTS で渡す）
     pub himagelist: HIMAGELIST,
+    /// コントロールに設定しているUIフォント（DPIの変更時に作り直し、AppStateの破棄時に削除する）
+    pub h_font: HFONT,
+    /// イメージリストのアイコンの大きさ（ピクセル）
+    pub icon_size: i32,
+    /// アイコンインデックスのキャッシュ
+    pub icon_cache: IconCache,
+    /// 種類名のキャッシュ
+    pub type_name_cache: TypeNameCache,
+    /// 「作成日時」列を表示するか（永続化される）
+    pub show_created_column: bool,
+    /// 「種類」列を表示するか（永続化される）
+    pub show_type_column: bool,
+    /// 行の背景を1行おきに塗り分けるか（永続化される）
+    pub striped_rows: bool,
+    /// 「フォルダ」列の一致箇所を強調表示するか（永続化される）
+    pub highlight_path: bool,
+    /// 「フォルダ」列に収まらないパスを省略する位置（永続化される）
+    pub path_ellipsis: EllipsisPosition,
+    /// 名前とフォルダを1列にまとめて表示するか（永続化される）
+    pub compact_rows: bool,
+    /// 検索結果をフォルダごとにまとめて表示するか（永続化される）
+    pub group_by_folder: bool,
+    /// 一致箇所をアクセントカラーで強調するか（永続化される）
+    pub accent_highlight: bool,
+    /// サイズを1024バイト単位（KiB）で表示するか（永続化される）
+    pub binary_units: bool,
+    /// 強調に使うアクセントカラー（COLORREF）。オフの場合や読み取れない場合は None で、黄色で強調する
+    pub accent_color: Option<u32>,
+    /// ハイコントラストが有効か（有効な間はシステムの配色だけで描画する）
+    pub high_contrast: bool,
+    /// ウィンドウを常に最前面に表示するか（永続化される）
+    pub always_on_top: bool,
+    /// 最小化時にタスクバーではなく通知領域へ格納するか（永続化される）
+    pub minimize_to_tray: bool,
+    /// リストの下にフォーカス行のプレビュー欄を表示するか（永続化される）
+    pub show_preview: bool,
+    /// 検索ボックスの下に結果内を絞り込む入力欄を表示するか（永続化される）
+    pub show_narrow_box: bool,
+    /// 結果内を絞り込む入力欄の文字列（検索のたびに入力欄から読み直す）
+    pub narrow_term: String,
+    /// プレビュー欄にテキストファイルの先頭部分を表示するか（永続化される）
+    pub preview_content: bool,
+    /// 結果の行をダブルクリックしたときの動作（永続化される）
+    pub double_click_action: DoubleClickAction,
+    /// フォルダの行をダブルクリックしたときの動作（永続化される）
+    pub folder_double_click: FolderDoubleClick,
 
     // --- DPI関連 ---
     pub current_dpi: u32,
     pub scale_factor: f32,
 
     // --- 検索オプション ---
-    pub regex_enabled: bool,
-    pub migemo_enabled: bool,
+    pub search_mode: SearchMode,
     pub shell_context_enabled: bool,
+    /// 入力中に検索するか（OFFの場合はEnterでのみ検索する、永続化される）
+    pub live_search: bool,
+    /// 表示中の検索を自動で再実行する間隔（秒、永続化される）
+    pub auto_refresh_secs: Option<u32>,
+    /// シェルのコンテキストメニューのコマンドを実行した後に再検索するか（永続化される）
+    pub refresh_after_shell_command: bool,
+    /// 検索ボックスでIMEの変換中か（変換中は入力中の検索を予約しない）
+    pub ime_composing: bool,
+    /// 通常検索で入力をそのままの文字列として検索するか（永続化される）
+    pub literal_search: bool,
+    /// アクセント記号（é と e など）を区別するか（永続化される）
+    pub match_diacritics: bool,
+    /// Migemoの展開結果をファイル名ではなくパス全体と照合するか（永続化される）
+    pub migemo_match_path: bool,
+    /// 他のアプリでコピーした文字列を検索ボックスに入れて検索するか（永続化される）
+    pub clipboard_search: bool,
+    /// 検索範囲のフォルダ（設定時はその配下のみを検索する）
+    pub scope_path: Option<PathBuf>,
+    /// 最近使った検索範囲のフォルダ（新しい順、永続化される）
+    pub recent_scopes: Vec<PathBuf>,
+    /// ピン留めした項目のフルパス（永続化される）
+    pub pinned_paths: Vec<PathBuf>,
+    /// コンテキストメニューの「送る」に表示するコマンド（設定ファイルで編集し、そのまま書き戻す）
+    pub send_to_commands: Vec<SendToCommand>,
+    /// リストの先頭に表示しているピン留め項目（検索のたびに作り直す）
+    pub pinned_rows: Vec<FileResult>,
+    /// 検索から除外するフォルダ（永続化される）
+    pub exclude_paths: Vec<String>,
+    /// 結果に含めるアイテムの種類（永続化される）
+    pub item_kind: ItemKind,
+    /// 並べ替えのキー
+    pub sort_key: SortKey,
+    /// 降順で並べ替えるか
+    pub sort_descending: bool,
+    /// 並べ替えのキーが同じ値の結果を並べる2番目のキー（永続化される）
+    pub secondary_sort: Option<SortKey>,
 
     // --- データ ---
     pub migemo_dict: Option<CompactDictionary>,
-    pub search_results: Mutex<Vec<FileResult>>,
+    /// 検索の問い合わせ先
+    pub backend: EverythingBackend,
 
     // --- 仮想リストビュー関連 ---
     pub total_results: u32,
+    /// リストに表示する最大件数（永続化される）
+    pub max_display_results: u32,
+    /// Everythingのインデックスの読み込みが完了しているか
+    pub index_loaded: bool,
     pub current_search_term: String,
-    pub page_size: usize,
-    pub current_page_offset: usize, // 現在ロードされているページの開始オフセット
+    /// 直前に検索した空でない入力（Migemo変換前）。入力が空のときのEscで元に戻す
+    pub previous_query: String,
+    /// 読み込み済みの検索結果（1ページ分）
+    pub pages: ResultPages,
+    /// 重複ファイルの一覧（表示中は検索結果の代わりにリストに表示する）
+    pub duplicates: Option<DuplicateList>,
+    /// フォルダごとにまとめた検索結果（グループ表示では、先頭から `FETCH_PROGRESS_THRESHOLD` 件までを見出しの行と共に表示する）
+    pub folder_groups: Option<FolderGroupList>,
+    pub list_status: ListStatus,
+    /// 表示中の結果の問い合わせにかかった時間
+    pub search_elapsed: Option<Duration>,
+    /// 表示中の結果の検索条件（ページの読み込みにも使う）
+    last_search: Option<SearchRequest>,
+    /// バックグラウンド検索の世代番号
+    search_generation: SearchGeneration,
+    /// プレビュー欄の内容の読み込みの世代番号（フォーカス行が変わるたびに進め、古い読み込みを打ち切る）
+    preview_generation: Arc<AtomicU64>,
+    /// 実行中のバックグラウンド検索
+    pending_search: Option<PendingSearch>,
+    /// 表示中の結果の検索語で使われている列の絞り込み（該当する列見出しを強調する）
+    pub column_filters: ColumnFilters,
+    /// タスクバーボタンの進捗表示（作成できなかった場合はNone、AppStateの破棄時に解放される）
+    taskbar: Option<ITaskbarList3>,
 
     // --- その他 ---
-    // LVN_GETDISPINFOで使うための静的バッファ
-    pub item_wide_buffer: [Vec<u16>; 4],
+    /// LVN_GETDISPINFOで返す文字列のバッファ（列ごと）
+    /// コントロールは返したポインタを通知の後で読むため、次に同じ列を問い合わせられるまで内容を変えない
+    pub item_wide_buffer: [Vec<u16>; COLUMN_COUNT],
 }
 
 impl AppState {
+    /// 指定モードのON/OFFを切り替える（OFFにすると通常検索に戻る）
+    pub fn toggle_search_mode(&mut self, mode: SearchMode) {
+        self.search_mode = if self.search_mode == mode { SearchMode::Plain } else { mode };
+    }
+
+    /// リストに表示する件数（総件数を表示上限で切り詰めたもの）
+    pub fn display_count(&self) -> u32 {
+        self.total_results.min(self.max_display_results)
+    }
+
+    /// リストの行数（先頭のピン留め項目と、グループ表示の見出しの行を含む）
+    pub fn row_count(&self) -> usize {
+        match &self.folder_groups {
+            Some(groups) => self.pinned_rows.len() + groups.row_count(),
+            None => self.pinned_rows.len() + self.display_count() as usize,
+        }
+    }
+
+    /// 絶対インデックスの検索結果を取得する（必要ならページを読み込む）
+    /// ページング・範囲チェック・オフセット計算はすべてここで行う
+    /// 先頭の行はピン留め項目で、検索結果はその後ろから始まる
+    pub fn result_at(&mut self, index: usize) -> Option<FileResult> {
+        if let Some(duplicates) = &self.duplicates {
+            return duplicates.rows().get(index).cloned();
+        }
+        if let Some(pinned) = self.pinned_rows.get(index) {
+            return Some(pinned.clone());
+        }
+        let index = index - self.pinned_rows.len();
+        if let Some(groups) = &self.folder_groups {
+            return groups.result(index).cloned();
+        }
+        if index >= self.display_count() as usize { return None; }
+
+        let request = self.last_search.as_ref()?;
+        // ページの読み込みはUIスレッドで同期的に問い合わせるため、その間は待機カーソルにする
+        let _wait_cursor = (!self.pages.is_loaded(index)).then(WaitCursor::show);
+        self.pages.get(&mut self.backend, request, index).cloned()
+    }
+
+    /// 指定行がグループ表示の見出しならそのグループ
+    pub fn group_header_at(&self, index: usize) -> Option<&FolderGroup> {
+        let index = index.checked_sub(self.pinned_rows.len())?;
+        self.folder_groups.as_ref()?.header(index)
+    }
+
+    /// リストの `from` から `to` までの行（LVN_ODCACHEHINT の範囲）をまとめて読み込んでおく
+    /// 続く LVN_GETDISPINFOW が1行ずつ問い合わせずに済む
+    pub fn prefetch_rows(&mut self, from: usize, to: usize) {
+        if self.duplicates.is_some() || self.folder_groups.is_some() { return; }
+        let pinned = self.pinned_rows.len();
+        if to < pinned || self.display_count() == 0 { return; }
+        let from = from.saturating_sub(pinned);
+        let to = (to - pinned).min(self.display_count() as usize - 1);
+        let Some(request) = self.last_search.as_ref() else { return };
+        let _wait_cursor = (!self.pages.is_loaded(from) || !self.pages.is_loaded(to)).then(WaitCursor::show);
+        self.pages.prefetch(&mut self.backend, request, from, to);
+    }
+
     /// AppStateの新しいインスタンスを作成する
     pub fn new() -> Self {
-        let migemo_dict = init_migemo_dict();
+        let migemo_dict = search::init_migemo_dict();
+        let config = Config::load();
         Self {
             main_hwnd: HWND::default(),
             status_hwnd: HWND::default(),
             edit_hwnd: HWND::default(),
+            narrow_hwnd: HWND::default(),
             listview_hwnd: HWND::default(),
+            preview_hwnd: HWND::default(),
             re_button_hwnd: HWND::default(),
             mi_button_hwnd: HWND::default(),
+            wc_button_hwnd: HWND::default(),
             shell_context_toggle_hwnd: HWND::default(),
+            live_search_toggle_hwnd: HWND::default(),
+            scope_button_hwnd: HWND::default(),
+            recent_scopes_button_hwnd: HWND::default(),
+            up_button_hwnd: HWND::default(),
+            item_kind_button_hwnd: HWND::default(),
             himagelist: HIMAGELIST::default(),
+            h_font: HFONT::default(),
+            icon_size: 16,
+            icon_cache: IconCache::default(),
+            type_name_cache: TypeNameCache::default(),
+            show_created_column: config.show_created_column,
+            show_type_column: config.show_type_column,
+            striped_rows: config.striped_rows,
+            highlight_path: config.highlight_path,
+            path_ellipsis: config.path_ellipsis,
+            compact_rows: config.compact_rows,
+            group_by_folder: config.group_by_folder,
+            accent_highlight: config.accent_highlight,
+            binary_units: config.binary_units,
+            accent_color: if config.accent_highlight { accent_color() } else { None },
+            high_contrast: is_high_contrast(),
+            always_on_top: config.always_on_top,
+            minimize_to_tray: config.minimize_to_tray,
+            show_preview: config.show_preview,
+            show_narrow_box: config.show_narrow_box,
+            narrow_term: String::new(),
+            preview_content: config.preview_content,
+            double_click_action: config.double_click_action,
+            folder_double_click: config.folder_double_click,
             current_dpi: 96,  // デフォルトDPI
             scale_factor: 1.0,  // デフォルトスケール
-            regex_enabled: false,
-            migemo_enabled: true,
+            search_mode: SearchMode::Migemo,
             shell_context_enabled: false,
+            live_search: config.live_search,
+            auto_refresh_secs: config.auto_refresh_secs,
+            refresh_after_shell_command: config.refresh_after_shell_command,
+            ime_composing: false,
+            literal_search: config.literal_search,
+            match_diacritics: config.match_diacritics,
+            migemo_match_path: config.migemo_match_path,
+            clipboard_search: config.clipboard_search,
+            scope_path: None,
+            recent_scopes: config.recent_scopes,
+            pinned_paths: config.pinned_paths,
+            send_to_commands: config.send_to_commands,
+            pinned_rows: Vec::new(),
+            exclude_paths: config.exclude_paths,
+            item_kind: config.item_kind,
+            sort_key: SortKey::Name,
+            sort_descending: false,
+            secondary_sort: config.secondary_sort,
             migemo_dict,
-            search_results: Mutex::new(Vec::new()),
+            backend: EverythingBackend,
             total_results: 0,
+            max_display_results: config.max_display_results,
+            index_loaded: true,
             current_search_term: String::new(),
-            page_size: 100,  // 一度に読み込む件数（初回検索の件数と一致）
-            current_page_offset: 0,
-            item_wide_buffer: [Vec::new(), Vec::new(), Vec::new(), Vec::new()],
+            previous_query: String::new(),
+            pages: ResultPages::new(MIN_PAGE_SIZE),  // 一度に読み込む件数（初回検索の件数と一致、リストの高さに合わせて増やす）
+            duplicates: None,
+            folder_groups: None,
+            list_status: ListStatus::Idle,
+            search_elapsed: None,
+            last_search: None,
+            search_generation: SearchGeneration::default(),
+            preview_generation: Arc::new(AtomicU64::new(0)),
+            pending_search: None,
+            column_filters: ColumnFilters::default(),
+            taskbar: None,
+            item_wide_buffer: Default::default(),
+        }
+    }
+}
+
+/// 自分で作成したGDIオブジェクトを解放する
+/// WM_DESTROY で破棄されるため、コントロールはまだ残っているがこの後は描画されない
+impl Drop for AppState {
+    fn drop(&mut self) {
+        if !self.h_font.is_invalid() {
+            let _ = unsafe { DeleteObject(self.h_font.into()) };
+        }
+    }
+}
+
+/// 現在の状態を設定ファイルに保存する
+fn save_config(state: &AppState) {
+    if let Err(e) = current_config(state).save() {
+        eprintln!("Failed to save config: {:?}", e);
+    }
+}
+
+/// 現在の状態から永続化する設定を組み立てる
+fn current_config(state: &AppState) -> Config {
+    Config {
+        exclude_paths: state.exclude_paths.clone(),
+        max_display_results: state.max_display_results,
+        show_created_column: state.show_created_column,
+        show_type_column: state.show_type_column,
+        striped_rows: state.striped_rows,
+        highlight_path: state.highlight_path,
+        path_ellipsis: state.path_ellipsis,
+        compact_rows: state.compact_rows,
+        group_by_folder: state.group_by_folder,
+        accent_highlight: state.accent_highlight,
+        binary_units: state.binary_units,
+        always_on_top: state.always_on_top,
+        minimize_to_tray: state.minimize_to_tray,
+        show_preview: state.show_preview,
+        show_narrow_box: state.show_narrow_box,
+        preview_content: state.preview_content,
+        double_click_action: state.double_click_action,
+        folder_double_click: state.folder_double_click,
+        live_search: state.live_search,
+        auto_refresh_secs: state.auto_refresh_secs,
+        refresh_after_shell_command: state.refresh_after_shell_command,
+        item_kind: state.item_kind,
+        secondary_sort: state.secondary_sort,
+        literal_search: state.literal_search,
+        match_diacritics: state.match_diacritics,
+        migemo_match_path: state.migemo_match_path,
+        clipboard_search: state.clipboard_search,
+        recent_scopes: state.recent_scopes.clone(),
+        pinned_paths: state.pinned_paths.clone(),
+        send_to_commands: state.send_to_commands.clone(),
+    }
+}
+
+// --- JSON出力（ウィンドウを作らずに検索する） ---
+
+/// JSON出力で1回に問い合わせる件数
+const JSON_PAGE_SIZE: usize = 1000;
+
+/// `--json <検索語>` で起動された場合の検索語（`--json` より後の引数を空白でつないだもの）
+fn json_query_arg(args: &[String]) -> Option<String> {
+    let position = args.iter().position(|arg| arg == "--json")?;
+    Some(args[position + 1..].join(" "))
+}
+
+/// ウィンドウを作らずに検索し、結果をJSONの配列として標準出力に書き出す。終了コードを返す
+/// 検索モードに合わせた展開・除外フォルダ・種類の絞り込み・並べ替えは、GUIの起動直後と同じ設定でクエリを組み立てる
+/// 結果はページごとに書き出して捨てるため、件数が多くてもメモリに溜めない
+/// 検索語が空（空白だけ）なら 2、Everythingに接続できなければ 1
+fn run_json_search(query: &str) -> i32 {
+    // リリースビルドはGUIサブシステムのため、コマンドプロンプトから起動したときはその画面に出力する
+    // パイプやリダイレクトで標準出力が渡されている場合はそちらに書く
+    let _ = unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
+
+    // 空の検索語ではすべてのファイルを書き出してしまうため、使い方の誤りとして扱う
+    if query.trim().is_empty() {
+        eprintln!("検索語を指定してください: migemo-everything.exe --json <検索語>");
+        return 2;
+    }
+
+    let mut backend = EverythingBackend;
+    if !backend.is_available() {
+        eprintln!("Everything に接続できません。Everything が起動しているか確認してください。");
+        return 1;
+    }
+    let state = AppState::new();
+    let term = expand_term(&state, query.to_string());
+    let request = search_request(&state, &term);
+
+    let mut out = std::io::BufWriter::new(std::io::stdout().lock());
+    let mut written = 0;
+    let mut write_error = None;
+    stream_all(&mut backend, &request, JSON_PAGE_SIZE, |result| {
+        let separator = if written == 0 { "[\n" } else { ",\n" };
+        written += 1;
+        match write!(out, "{}{}", separator, result_json(result)) {
+            Ok(()) => true,
+            Err(e) => { write_error = Some(e); false }
+        }
+    });
+    let closing = if written == 0 { "[]\n" } else { "\n]\n" };
+    let finished = match write_error {
+        Some(e) => Err(e),
+        None => out.write_all(closing.as_bytes()).and_then(|_| out.flush()),
+    };
+    match finished {
+        Ok(()) => 0,
+        // `| head` などで読み手が先に終了した
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => 0,
+        Err(e) => {
+            eprintln!("{}", e);
+            1
         }
     }
 }
@@ -163,17 +856,23 @@ impl AppState {
 
 /// アプリケーションのエントリポイント
 fn main() -> Result<()> {
-    // COMライブラリの初期化
-    unsafe {
-        CoInitializeEx(None, COINIT_APARTMENTTHREADED).ok()?;
+    // `--json <検索語>` ならウィンドウを作らずに結果を出力して終了する
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(query) = json_query_arg(&args) {
+        std::process::exit(run_json_search(&query));
     }
 
-    // DPI対応を有効にする
-    unsafe {
-        // プロセス全体でDPI認識を設定
-        let _ = SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2);
+    // COMライブラリの初期化
+    // 失敗するとシェル連携が使えないため、理由を表示して終了する
+    if let Err(e) = unsafe { CoInitializeEx(None, COINIT_APARTMENTTHREADED) }.ok() {
+        let text_w = str_to_wide(&format!("COMの初期化に失敗したため起動できません。\n\n{}", e.message()));
+        unsafe { MessageBoxW(None, PCWSTR(text_w.as_ptr()), w!("Migemo Everything"), MB_OK | MB_ICONERROR) };
+        return Err(e);
     }
 
+    // DPI対応を有効にする（古いWindowsでは使える範囲の方式に落とす）
+    enable_dpi_awareness(detect_dpi_support());
+
     // アプリケーションの状態を初期化
     let app_state = AppState::new();
 
@@ -217,16 +916,31 @@ fn main() -> Result<()> {
             ACCEL { fVirt: FCONTROL | FVIRTKEY, key: b'Q' as u16, cmd: IDA_EXIT },
             ACCEL { fVirt: FCONTROL | FVIRTKEY, key: b'R' as u16, cmd: IDA_REGEX },
             ACCEL { fVirt: FCONTROL | FSHIFT | FVIRTKEY, key: b'R' as u16, cmd: IDA_MIGEMO },
+            ACCEL { fVirt: FCONTROL | FVIRTKEY, key: b'M' as u16, cmd: IDA_CYCLE_MODE },
+            ACCEL { fVirt: FCONTROL | FVIRTKEY, key: b'G' as u16, cmd: IDA_GOTO },
+            ACCEL { fVirt: FCONTROL | FVIRTKEY, key: b'L' as u16, cmd: IDA_FOCUS_EDIT },
+            ACCEL { fVirt: FCONTROL | FVIRTKEY, key: b'E' as u16, cmd: IDA_SCOPE_EXPLORER },
+            ACCEL { fVirt: FCONTROL | FVIRTKEY, key: b'F' as u16, cmd: IDA_NARROW },
+            ACCEL { fVirt: FALT | FVIRTKEY, key: VK_UP.0, cmd: IDA_SCOPE_UP },
         ];
         let haccel = CreateAcceleratorTableW(&accelerators)?;
 
         // メッセージループ
         let mut message = MSG::default();
         while GetMessageW(&mut message, None, 0, 0).into() {
-            if TranslateAcceleratorW(hwnd, haccel, &message) == 0 {
-                let _ = TranslateMessage(&message);
-                DispatchMessageW(&message);
+            if TranslateAcceleratorW(hwnd, haccel, &message) != 0 { continue; }
+
+            // Tab / Shift+Tab でコントロール間のフォーカスを移動する
+            if message.message == WM_KEYDOWN && message.wParam.0 == VK_TAB.0 as usize && IsChild(hwnd, message.hwnd).as_bool() {
+                let backward = GetKeyState(VK_SHIFT.0 as i32) < 0;
+                if let Ok(next) = GetNextDlgTabItem(hwnd, Some(GetFocus()), backward) {
+                    let _ = SetFocus(Some(next));
+                }
+                continue;
             }
+
+            let _ = TranslateMessage(&message);
+            DispatchMessageW(&message);
         }
     }
 
@@ -278,9 +992,18 @@ pub extern "system" fn wndproc(
         WM_COMMAND => handle_command(window, wparam, lparam, state),
         WM_TIMER => handle_timer(window, wparam, state),
         WM_NOTIFY => handle_notify(window, lparam, state),
-        WM_SIZE => handle_size(window, lparam, state),
+        WM_SIZE => handle_size(window, wparam, lparam, state),
         WM_SETFOCUS => handle_setfocus(state),
         WM_DPICHANGED => handle_dpi_changed(window, wparam, lparam, state),
+        WM_GETMINMAXINFO => handle_get_min_max_info(window, lparam, state),
+        WM_ICON_LOADED => handle_icon_loaded(wparam, lparam, state),
+        WM_SEARCH_COMPLETE => handle_search_complete(lparam, state),
+        WM_TRAY_ICON => handle_tray_icon(window, lparam),
+        WM_EDIT_COMPOSITION => handle_edit_composition(window, wparam, state),
+        WM_PREVIEW_LOADED => handle_preview_loaded(lparam, state),
+        WM_CLIPBOARDUPDATE => handle_clipboard_update(window, state),
+        WM_CONTEXTMENU if HWND(wparam.0 as *mut c_void) == state.listview_hwnd => handle_context_menu(window, lparam, state),
+        WM_SETTINGCHANGE | WM_SYSCOLORCHANGE | WM_THEMECHANGED | WM_DWMCOLORIZATIONCOLORCHANGED => handle_setting_change(window, message, state),
         WM_PAINT => {
             let _ = unsafe { ValidateRect(Some(window), None) };
             LRESULT(0)
@@ -291,6 +1014,169 @@ pub extern "system" fn wndproc(
 
 // --- イベントハンドラ ---
 
+/// エディットボックスのサブクラスプロシージャ
+/// Enterキーでデバウンスを待たずに検索する
+/// Ctrl+Backspace / Ctrl+Delete で前後の単語を削除する
+/// Escで入力をクリアし、空のときのEscで直前に検索した語に戻す
+extern "system" fn edit_subclass_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+    _subclass_id: usize,
+    _ref_data: usize,
+) -> LRESULT {
+    match message {
+        WM_KEYDOWN if wparam.0 == VK_RETURN.0 as usize => {
+            if let Ok(parent) = unsafe { GetParent(window) } {
+                unsafe { SendMessageW(parent, WM_COMMAND, Some(WPARAM(IDM_SEARCH_NOW as usize)), Some(LPARAM(0))) };
+            }
+            LRESULT(0)
+        }
+        WM_KEYDOWN if wparam.0 == VK_ESCAPE.0 as usize => {
+            if unsafe { GetWindowTextLengthW(window) } > 0 {
+                // 元に戻せるよう全体を選択して置き換える。EN_CHANGE が届くので空の検索になる
+                let empty = [0u16];
+                unsafe {
+                    SendMessageW(window, EM_SETSEL, Some(WPARAM(0)), Some(LPARAM(-1)));
+                    SendMessageW(window, EM_REPLACESEL, Some(WPARAM(1)), Some(LPARAM(empty.as_ptr() as isize)));
+                }
+            } else if let Ok(parent) = unsafe { GetParent(window) } {
+                // 空の絞り込み欄では欄を閉じ、検索ボックスでは直前の検索語に戻す
+                let command = if unsafe { GetDlgCtrlID(window) } == NARROW_EDIT_ID as i32 { IDM_SEARCH_NARROW } else { IDM_SEARCH_RESTORE_QUERY };
+                unsafe { SendMessageW(parent, WM_COMMAND, Some(WPARAM(command as usize)), Some(LPARAM(0))) };
+            }
+            LRESULT(0)
+        }
+        WM_PASTE => {
+            // 単一行エディットは最初の行しか貼り付けないため、改行を空白にまとめて全体を入れる
+            // EM_REPLACESEL は元に戻せ、EN_CHANGE も届くので、メニューからの貼り付けでも入力と同様に検索が予約される
+            match read_clipboard_text(window) {
+                Some(text) => {
+                    let text_w = str_to_wide(&search::collapse_newlines(&text));
+                    unsafe { SendMessageW(window, EM_REPLACESEL, Some(WPARAM(1)), Some(LPARAM(text_w.as_ptr() as isize))) };
+                    LRESULT(0)
+                }
+                None => unsafe { DefSubclassProc(window, message, wparam, lparam) },
+            }
+        }
+        WM_KEYDOWN if (wparam.0 == VK_BACK.0 as usize || wparam.0 == VK_DELETE.0 as usize) && is_key_down(VK_CONTROL) => {
+            delete_word(window, wparam.0 == VK_BACK.0 as usize);
+            LRESULT(0)
+        }
+        WM_IME_STARTCOMPOSITION | WM_IME_ENDCOMPOSITION => {
+            if let Ok(parent) = unsafe { GetParent(window) } {
+                let composing = message == WM_IME_STARTCOMPOSITION;
+                unsafe { SendMessageW(parent, WM_EDIT_COMPOSITION, Some(WPARAM(composing as usize)), Some(LPARAM(0))) };
+            }
+            unsafe { DefSubclassProc(window, message, wparam, lparam) }
+        }
+        // 単一行エディットにEnterの文字が届くと警告音が鳴るため捨てる
+        WM_CHAR if wparam.0 == '\r' as usize => LRESULT(0),
+        // Escの文字も同様に捨てる
+        WM_CHAR if wparam.0 == 0x1B => LRESULT(0),
+        // Ctrl+Backspaceで届くDEL文字（0x7F）は四角形として挿入されてしまうため捨てる
+        WM_CHAR if wparam.0 == 0x7F => LRESULT(0),
+        _ => unsafe { DefSubclassProc(window, message, wparam, lparam) },
+    }
+}
+
+/// リストビューのサブクラスプロシージャ
+/// ヘッダーの通知はリストビュー宛てに届くため、カスタムドローだけをメインウィンドウへ転送する
+/// Shift+ホイールは横スクロールに変換する（Shiftなしの縦スクロールは既定の動作のまま）
+extern "system" fn listview_subclass_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+    _subclass_id: usize,
+    _ref_data: usize,
+) -> LRESULT {
+    if message == WM_NOTIFY {
+        let nmhdr = unsafe { &*(lparam.0 as *const NMHDR) };
+        if nmhdr.code == NM_CUSTOMDRAW && nmhdr.hwndFrom == listview_header(window) {
+            if let Ok(parent) = unsafe { GetParent(window) } {
+                return unsafe { SendMessageW(parent, WM_NOTIFY, Some(wparam), Some(lparam)) };
+            }
+        }
+    }
+    if message == WM_MOUSEWHEEL && (loword(wparam.0 as u32) as u32 & MK_SHIFT.0) != 0 {
+        let delta = hiword(wparam.0 as u32) as i16 as i32;
+        let dx = -delta * wheel_scroll_width(window) / WHEEL_DELTA as i32;
+        unsafe { SendMessageW(window, LVM_SCROLL, Some(WPARAM(dx as isize as usize)), Some(LPARAM(0))) };
+        return LRESULT(0);
+    }
+    unsafe { DefSubclassProc(window, message, wparam, lparam) }
+}
+
+/// ホイール1ノッチ分の横スクロール量（ピクセル）
+/// システム設定の文字数（既定は3文字）にコントロールのフォントの平均文字幅を掛ける
+fn wheel_scroll_width(control: HWND) -> i32 {
+    let mut chars: u32 = 3;
+    let _ = unsafe {
+        SystemParametersInfoW(SPI_GETWHEELSCROLLCHARS, 0, Some(&mut chars as *mut _ as *mut c_void), SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0))
+    };
+    unsafe {
+        let hdc = GetDC(Some(control));
+        let font = HFONT(SendMessageW(control, WM_GETFONT, None, None).0 as *mut c_void);
+        let previous = (!font.is_invalid()).then(|| SelectObject(hdc, font.into()));
+        let mut metrics = TEXTMETRICW::default();
+        let _ = GetTextMetricsW(hdc, &mut metrics);
+        if let Some(previous) = previous { SelectObject(hdc, previous); }
+        ReleaseDC(Some(control), hdc);
+        chars as i32 * metrics.tmAveCharWidth.max(1)
+    }
+}
+
+/// キーが押されているか
+fn is_key_down(key: VIRTUAL_KEY) -> bool {
+    unsafe { GetKeyState(key.0 as i32) < 0 }
+}
+
+/// エディットボックスのキャレットの前（または後ろ）の単語を削除する
+/// 選択範囲がある場合は選択範囲だけを削除する
+fn delete_word(edit_hwnd: HWND, backward: bool) {
+    let mut start: u32 = 0;
+    let mut end: u32 = 0;
+    unsafe { SendMessageW(edit_hwnd, EM_GETSEL, Some(WPARAM(&mut start as *mut u32 as usize)), Some(LPARAM(&mut end as *mut u32 as isize))) };
+
+    if start == end {
+        let len = unsafe { GetWindowTextLengthW(edit_hwnd) } as usize;
+        let mut buffer = vec![0u16; len + 1];
+        let copied = unsafe { GetWindowTextW(edit_hwnd, &mut buffer) } as usize;
+        let text = &buffer[..copied];
+        let caret = (start as usize).min(text.len());
+        let (from, to) = if backward { (previous_word_start(text, caret), caret) } else { (caret, next_word_end(text, caret)) };
+        if from == to { return; }
+        unsafe { SendMessageW(edit_hwnd, EM_SETSEL, Some(WPARAM(from)), Some(LPARAM(to as isize))) };
+    }
+
+    // 元に戻せるよう wParam に TRUE を渡す。EN_CHANGE が届くので通常の入力と同様に検索される
+    let empty = [0u16];
+    unsafe { SendMessageW(edit_hwnd, EM_REPLACESEL, Some(WPARAM(1)), Some(LPARAM(empty.as_ptr() as isize))) };
+}
+
+/// 単語の区切りとなる文字（空白とパス区切り）か
+fn is_word_separator(c: u16) -> bool {
+    matches!(char::from_u32(c as u32), Some(' ' | '\t' | '\u{3000}' | '\\' | '/'))
+}
+
+/// キャレットの直前の単語の先頭位置（直前の区切り文字は単語に含める）
+fn previous_word_start(text: &[u16], caret: usize) -> usize {
+    let mut pos = caret;
+    while pos > 0 && is_word_separator(text[pos - 1]) { pos -= 1; }
+    while pos > 0 && !is_word_separator(text[pos - 1]) { pos -= 1; }
+    pos
+}
+
+/// キャレットの直後の単語の末尾位置（後続の区切り文字も含める）
+fn next_word_end(text: &[u16], caret: usize) -> usize {
+    let mut pos = caret;
+    while pos < text.len() && !is_word_separator(text[pos]) { pos += 1; }
+    while pos < text.len() && is_word_separator(text[pos]) { pos += 1; }
+    pos
+}
+
 /// WM_CREATE メッセージのハンドラ
 fn handle_create(window: HWND, lparam: LPARAM) -> LRESULT {
     // CreateWindowExWから渡されたポインタを取得
@@ -308,7 +1194,7 @@ fn handle_create(window: HWND, lparam: LPARAM) -> LRESULT {
 
     // DPIを初期化
     unsafe {
-        state.current_dpi = GetDpiForWindow(window);
+        state.current_dpi = window_dpi(window);
         state.scale_factor = state.current_dpi as f32 / 96.0;
     }
 
@@ -317,12 +1203,29 @@ fn handle_create(window: HWND, lparam: LPARAM) -> LRESULT {
     create_controls(window, create_struct.hInstance, state);
     setup_listview(state);
     update_ui_states(state);
+    apply_always_on_top(state);
+    apply_auto_refresh(state);
+    if state.minimize_to_tray {
+        add_tray_icon(window);
+    }
+    if state.clipboard_search {
+        let _ = unsafe { AddClipboardFormatListener(window) };
+    }
+
+    // 検索中であることをタスクバーボタンに表示するため
+    state.taskbar = unsafe { CoCreateInstance::<_, ITaskbarList3>(&TaskbarList, None, CLSCTX_INPROC_SERVER) }
+        .ok()
+        .filter(|taskbar| unsafe { taskbar.HrInit() }.is_ok());
 
     LRESULT(0)
 }
 
 /// WM_DESTROY メッセージのハンドラ
 fn handle_destroy(window: HWND) -> LRESULT {
+    // 追加していなければ何も起きない
+    remove_tray_icon(window);
+    let _ = unsafe { RemoveClipboardFormatListener(window) };
+
     // ウィンドウのユーザーデータからポインタを取得
     let app_state_ptr =
         unsafe { GetWindowLongPtrW(window, GWLP_USERDATA) as *mut AppState };
@@ -347,92 +1250,395 @@ fn handle_command(window: HWND, wparam: WPARAM, lparam: LPARAM, state: &mut AppS
     match control_id {
         // --- アクセラレータ ---
         IDA_EXIT => { let _ = unsafe { DestroyWindow(window) }; }
-        IDA_REGEX => {
-            state.regex_enabled = !state.regex_enabled;
-            if state.regex_enabled { state.migemo_enabled = false; }
+        IDA_REGEX | IDM_SEARCH_REGEX | RE_BUTTON_ID => {
+            state.search_mode = state.search_mode.toggle_regex();
+            update_ui_states(state);
+            trigger_search(window);
+        }
+        IDA_MIGEMO | IDM_SEARCH_MIGEMO | MI_BUTTON_ID => {
+            state.search_mode = state.search_mode.toggle_migemo();
+            update_ui_states(state);
+            trigger_search(window);
+        }
+        IDM_SEARCH_WILDCARD | WC_BUTTON_ID => {
+            state.toggle_search_mode(SearchMode::Wildcard);
             update_ui_states(state);
             trigger_search(window);
         }
-        IDA_MIGEMO => {
-            state.migemo_enabled = !state.migemo_enabled;
-            if state.migemo_enabled { state.regex_enabled = false; }
+        IDA_CYCLE_MODE | IDM_SEARCH_CYCLE_MODE => {
+            state.search_mode = state.search_mode.next();
             update_ui_states(state);
+            flash_status(window, state, &format!("検索モード: {}", state.search_mode.label()));
             trigger_search(window);
         }
         // --- メニュー項目 ---
         IDM_FILE_EXIT => { let _ = unsafe { DestroyWindow(window) }; }
-        IDM_SEARCH_REGEX => {
-            state.regex_enabled = !state.regex_enabled;
-            if state.regex_enabled { state.migemo_enabled = false; }
+        IDM_FILE_SETTINGS => {
+            if let Some(config) = settings_dialog(window, &current_config(state)) {
+                apply_settings(window, state, config);
+            }
+        }
+        id if SortKey::ALL.iter().any(|key| sort_menu_id(*key) == id) => {
+            state.sort_key = SortKey::ALL[(id - IDM_VIEW_SORT_FIRST) as usize];
+            update_ui_states(state);
+            trigger_search(window);
+        }
+        IDM_VIEW_SORT_DESCENDING => {
+            state.sort_descending = !state.sort_descending;
+            update_ui_states(state);
+            trigger_search(window);
+        }
+        id if (IDM_VIEW_SECONDARY_SORT_FIRST..=IDM_VIEW_SECONDARY_SORT_FIRST + SortKey::SECONDARY.len() as u16).contains(&id) => {
+            state.secondary_sort = match (id - IDM_VIEW_SECONDARY_SORT_FIRST) as usize {
+                0 => None,
+                i => Some(SortKey::SECONDARY[i - 1]),
+            };
+            save_config(state);
             update_ui_states(state);
             trigger_search(window);
         }
-        IDM_SEARCH_MIGEMO => {
-            state.migemo_enabled = !state.migemo_enabled;
-            if state.migemo_enabled { state.regex_enabled = false; }
+        IDM_VIEW_COLUMN_CREATED => {
+            state.show_created_column = !state.show_created_column;
+            set_column_visible(state, COLUMN_CREATED, state.show_created_column);
+            save_config(state);
+            update_ui_states(state);
+        }
+        IDM_VIEW_COLUMN_TYPE => {
+            state.show_type_column = !state.show_type_column;
+            set_column_visible(state, COLUMN_TYPE, state.show_type_column);
+            save_config(state);
+            update_ui_states(state);
+        }
+        id if (IDM_VIEW_PATH_ELLIPSIS_FIRST..IDM_VIEW_PATH_ELLIPSIS_FIRST + EllipsisPosition::ALL.len() as u16).contains(&id) => {
+            state.path_ellipsis = EllipsisPosition::ALL[(id - IDM_VIEW_PATH_ELLIPSIS_FIRST) as usize];
+            save_config(state);
+            update_ui_states(state);
+            let _ = unsafe { InvalidateRect(Some(state.listview_hwnd), None, true) };
+        }
+        id if (IDM_VIEW_DOUBLE_CLICK_FIRST..IDM_VIEW_DOUBLE_CLICK_FIRST + DoubleClickAction::ALL.len() as u16).contains(&id) => {
+            state.double_click_action = DoubleClickAction::ALL[(id - IDM_VIEW_DOUBLE_CLICK_FIRST) as usize];
+            save_config(state);
             update_ui_states(state);
+        }
+        id if (IDM_VIEW_FOLDER_DOUBLE_CLICK_FIRST..IDM_VIEW_FOLDER_DOUBLE_CLICK_FIRST + FolderDoubleClick::ALL.len() as u16).contains(&id) => {
+            state.folder_double_click = FolderDoubleClick::ALL[(id - IDM_VIEW_FOLDER_DOUBLE_CLICK_FIRST) as usize];
+            save_config(state);
+            update_ui_states(state);
+            // 入力が空のときにフォルダの中を一覧するかが変わるため検索し直す
             trigger_search(window);
         }
-        // --- ボタン ---
-        RE_BUTTON_ID => {
-            state.regex_enabled = !state.regex_enabled;
-            if state.regex_enabled { state.migemo_enabled = false; }
+        IDM_VIEW_MINIMIZE_TO_TRAY => {
+            state.minimize_to_tray = !state.minimize_to_tray;
+            if state.minimize_to_tray { add_tray_icon(window); } else { remove_tray_icon(window); }
+            save_config(state);
+            update_ui_states(state);
+        }
+        IDM_TRAY_SHOW => restore_from_tray(window),
+        IDM_VIEW_PREVIEW | IDM_VIEW_PREVIEW_CONTENT => {
+            if control_id == IDM_VIEW_PREVIEW {
+                state.show_preview = !state.show_preview;
+            } else {
+                state.preview_content = !state.preview_content;
+            }
+            save_config(state);
+            update_ui_states(state);
+            let mut client = RECT::default();
+            let _ = unsafe { GetClientRect(window, &mut client) };
+            layout_controls(client.right, client.bottom, state);
+            update_preview(state);
+        }
+        IDM_VIEW_ALWAYS_ON_TOP => {
+            state.always_on_top = !state.always_on_top;
+            apply_always_on_top(state);
+            save_config(state);
+            update_ui_states(state);
+        }
+        IDM_VIEW_HIGHLIGHT_PATH => {
+            state.highlight_path = !state.highlight_path;
+            save_config(state);
+            update_ui_states(state);
+            let _ = unsafe { InvalidateRect(Some(state.listview_hwnd), None, true) };
+        }
+        IDM_VIEW_UNITS_BINARY | IDM_VIEW_UNITS_DECIMAL => {
+            state.binary_units = control_id == IDM_VIEW_UNITS_BINARY;
+            save_config(state);
+            update_ui_states(state);
+            let _ = unsafe { InvalidateRect(Some(state.listview_hwnd), None, true) };
+        }
+        IDM_VIEW_ACCENT_HIGHLIGHT => {
+            state.accent_highlight = !state.accent_highlight;
+            refresh_accent_color(state);
+            save_config(state);
+            update_ui_states(state);
+            let _ = unsafe { InvalidateRect(Some(state.listview_hwnd), None, true) };
+        }
+        IDM_VIEW_COMPACT_ROWS => {
+            state.compact_rows = !state.compact_rows;
+            apply_compact_rows(state);
+            save_config(state);
+            update_ui_states(state);
+            let _ = unsafe { InvalidateRect(Some(state.listview_hwnd), None, true) };
+        }
+        IDM_VIEW_GROUP_BY_FOLDER => {
+            state.group_by_folder = !state.group_by_folder;
+            save_config(state);
+            update_ui_states(state);
+            if !state.group_by_folder {
+                state.folder_groups = None;
+                refresh_list_rows(state);
+            } else if let (None, Some(request)) = (&state.duplicates, state.last_search.clone()) {
+                // まとめるための取得はワーカースレッドで行うため、表示中の検索をやり直す
+                let focused_path = focused_item_path(state);
+                let search = PendingSearch { term: state.current_search_term.clone(), request, focused_path, column_filters: state.column_filters };
+                dispatch_search(state, search);
+            }
+        }
+        IDM_VIEW_STRIPED_ROWS => {
+            state.striped_rows = !state.striped_rows;
+            apply_row_style(state);
+            save_config(state);
+            update_ui_states(state);
+        }
+        SCOPE_BUTTON_ID | IDM_SEARCH_SCOPE => {
+            if let Some(folder) = pick_folder(window) {
+                set_scope(window, state, folder);
+            }
+            update_status_text(state);
+        }
+        IDA_SCOPE_EXPLORER | IDM_SEARCH_SCOPE_EXPLORER => {
+            // エクスプローラのウィンドウがなければ検索範囲は変えない
+            if let Some(folder) = explorer_folder(window) {
+                set_scope(window, state, folder);
+            }
+        }
+        RECENT_SCOPES_BUTTON_ID => show_recent_scopes_menu(window, state),
+        id if (IDM_SCOPE_RECENT_FIRST..IDM_SCOPE_RECENT_FIRST + RECENT_SCOPES_MAX as u16).contains(&id) => {
+            if let Some(folder) = state.recent_scopes.get((id - IDM_SCOPE_RECENT_FIRST) as usize).cloned() {
+                set_scope(window, state, folder);
+            }
+        }
+        UP_BUTTON_ID | IDA_SCOPE_UP | IDM_SEARCH_SCOPE_UP => scope_up(window, state),
+        IDM_SEARCH_SCOPE_CLEAR => {
+            if state.scope_path.take().is_some() {
+                trigger_search(window);
+            }
+            update_status_text(state);
+        }
+        IDM_SEARCH_EXCLUDES => {
+            if let Some(paths) = edit_list_dialog(window, "除外フォルダ (1行に1つ)", &state.exclude_paths) {
+                state.exclude_paths = paths;
+                save_config(state);
+                update_status_text(state);
+                trigger_search(window);
+            }
+        }
+        IDM_SEARCH_NOW => {
+            // 明示的な検索は、条件が同じでもインデックスの更新を反映するためやり直す
+            let _ = unsafe { KillTimer(Some(window), TIMER_ID) };
+            perform_search(state, true);
+        }
+        IDM_SEARCH_RESTORE_QUERY => restore_previous_query(window, state),
+        ITEM_KIND_BUTTON_ID => set_item_kind(window, state, state.item_kind.next()),
+        id if ItemKind::ALL.iter().any(|kind| item_kind_menu_id(*kind) == id) => {
+            set_item_kind(window, state, ItemKind::ALL[(id - IDM_SEARCH_KIND_FIRST) as usize]);
+        }
+        IDM_SEARCH_DUPLICATES | IDM_SEARCH_DUPLICATES_BY_NAME => {
+            show_duplicates(window, state, control_id == IDM_SEARCH_DUPLICATES_BY_NAME);
+        }
+        IDA_NARROW => show_narrow_box(window, state, true),
+        IDM_SEARCH_NARROW => show_narrow_box(window, state, !state.show_narrow_box),
+        IDA_FOCUS_EDIT | IDM_SEARCH_FOCUS_EDIT => {
+            // ブラウザのアドレスバーと同様に、入力済みの文字を全選択して上書きしやすくする
+            unsafe {
+                let _ = SetFocus(Some(state.edit_hwnd));
+                SendMessageW(state.edit_hwnd, EM_SETSEL, Some(WPARAM(0)), Some(LPARAM(-1)));
+            }
+        }
+        IDA_GOTO | IDM_SEARCH_GOTO => {
+            let _ = unsafe { KillTimer(Some(window), TIMER_ID) };
+            go_to_entered_path(window, state);
+        }
+        IDM_SEARCH_SHOW_QUERY => {
+            let text = if state.last_search.is_none() {
+                "検索語が入力されていません".to_string()
+            } else {
+                let query = compose_query(state, &state.current_search_term);
+                format!("{}\n\n正規表現: {}", query.text, if query.regex { "ON" } else { "OFF" })
+            };
+            let text_w = str_to_wide(&text);
+            unsafe { MessageBoxW(Some(window), PCWSTR(text_w.as_ptr()), w!("Everythingに渡すクエリ"), MB_OK | MB_ICONINFORMATION) };
+        }
+        IDM_SEARCH_COPY_QUERY => {
+            if state.last_search.is_none() {
+                flash_status(window, state, "検索語が入力されていません");
+            } else {
+                let query = compose_query(state, &state.current_search_term);
+                copy_text_to_clipboard(window, &query.text);
+                flash_status(window, state, "クエリをコピーしました");
+            }
+        }
+        IDM_SEARCH_OPEN_IN_EVERYTHING => {
+            if state.last_search.is_none() {
+                flash_status(window, state, "検索語が入力されていません");
+            } else {
+                let query = compose_query(state, &state.current_search_term);
+                if !open_in_everything(&query) {
+                    unsafe { MessageBoxW(Some(window), w!("Everything.exe が見つかりませんでした。"), w!("Everythingで開く"), MB_OK | MB_ICONWARNING) };
+                }
+            }
+        }
+        id if (IDM_SEARCH_AUTO_REFRESH_FIRST..IDM_SEARCH_AUTO_REFRESH_FIRST + AUTO_REFRESH_CHOICES.len() as u16).contains(&id) => {
+            state.auto_refresh_secs = AUTO_REFRESH_CHOICES[(id - IDM_SEARCH_AUTO_REFRESH_FIRST) as usize];
+            apply_auto_refresh(state);
+            save_config(state);
+            update_ui_states(state);
+            update_status_text(state);
+        }
+        IDM_SEARCH_REFRESH_AFTER_SHELL => {
+            state.refresh_after_shell_command = !state.refresh_after_shell_command;
+            save_config(state);
             update_ui_states(state);
+        }
+        IDM_SEARCH_DIACRITICS => {
+            state.match_diacritics = !state.match_diacritics;
+            save_config(state);
+            update_ui_states(state);
+            update_status_text(state);
             trigger_search(window);
         }
-        MI_BUTTON_ID => {
-            state.migemo_enabled = !state.migemo_enabled;
-            if state.migemo_enabled { state.regex_enabled = false; }
+        IDM_SEARCH_MIGEMO_MATCH_PATH => {
+            state.migemo_match_path = !state.migemo_match_path;
+            save_config(state);
             update_ui_states(state);
+            update_status_text(state);
             trigger_search(window);
         }
+        IDM_SEARCH_CLIPBOARD => {
+            state.clipboard_search = !state.clipboard_search;
+            apply_clipboard_listener(window, state.clipboard_search);
+            save_config(state);
+            update_ui_states(state);
+        }
+        IDM_SEARCH_LITERAL => {
+            state.literal_search = !state.literal_search;
+            save_config(state);
+            update_ui_states(state);
+            if state.search_mode == SearchMode::Plain {
+                trigger_search(window);
+            }
+        }
+        LIVE_SEARCH_TOGGLE_ID | IDM_SEARCH_LIVE => {
+            state.live_search = !state.live_search;
+            if !state.live_search {
+                let _ = unsafe { KillTimer(Some(window), TIMER_ID) };
+            }
+            save_config(state);
+            update_ui_states(state);
+        }
         SHELL_CONTEXT_TOGGLE_ID => {
             let is_checked = unsafe { SendMessageW(state.shell_context_toggle_hwnd, BM_GETCHECK, None, None) } == LRESULT(BST_CHECKED.0 as isize);
             state.shell_context_enabled = is_checked;
         }
         // --- エディットボックス ---
-        EDIT_ID if notification_code as u32 == EN_CHANGE => {
-            // 500ミリ秒後に検索タイマーをセット
-            unsafe { SetTimer(Some(window), TIMER_ID, 500, None) };
+        EDIT_ID | NARROW_EDIT_ID if notification_code as u32 == EN_CHANGE => {
+            // 500ミリ秒後に検索タイマーをセット（ライブ検索OFF時はEnterを待つ）
+            // 実行中の検索があれば、その結果は古くなるため世代を進めて捨てる
+            // IMEの変換中は未確定の文字で検索しないよう、変換の終了時に予約する
+            if state.live_search {
+                state.search_generation.advance();
+                if !state.ime_composing {
+                    unsafe { SetTimer(Some(window), TIMER_ID, 500, None) };
+                }
+            }
         }
         // --- コンテキストメニュー ---
         IDM_CONTEXT_OPEN => {
-            let item_index = lparam.0 as usize;
-            ensure_data_available(state, item_index);
-            let results = state.search_results.lock().unwrap();
-            let local_index = item_index - state.current_page_offset;
-            if let Some(result) = results.get(local_index) {
-                let full_path = Path::new(&result.path).join(&result.name);
-                let path_w = str_to_wide(full_path.to_str().unwrap_or(""));
+            if let Some(result) = state.result_at(lparam.0 as usize) {
+                let path_w = str_to_wide(&result.full_path);
                 thread::spawn(move || unsafe {
                     ShellExecuteW(None, w!("open"), PCWSTR(path_w.as_ptr()), None, None, SW_SHOW);
                 });
             }
         }
-        IDM_CONTEXT_OPEN_FOLDER => {
-            let item_index = lparam.0 as usize;
-            ensure_data_available(state, item_index);
-            let results = state.search_results.lock().unwrap();
-            let local_index = item_index - state.current_page_offset;
-            if let Some(result) = results.get(local_index) {
-                let full_path = Path::new(&result.path).join(&result.name);
-                let params = format!("/select,\"{}\"", full_path.display());
-                let params_w = str_to_wide(&params);
+        IDM_CONTEXT_OPEN_WITH => {
+            if let Some(result) = state.result_at(lparam.0 as usize).filter(|result| !result.is_folder) {
+                let path_w = str_to_wide(&result.full_path);
+                // HWNDはスレッド間で送れないため数値として渡す
+                let owner_raw = window.0 as isize;
                 thread::spawn(move || unsafe {
-                    ShellExecuteW(None, w!("open"), w!("explorer.exe"), PCWSTR(params_w.as_ptr()), None, SW_SHOW);
+                    // シェルのダイアログを開くスレッドでもCOMの初期化が必要
+                    let com_initialized = CoInitializeEx(None, COINIT_APARTMENTTHREADED).is_ok();
+                    let info = OPENASINFO {
+                        pcszFile: PCWSTR(path_w.as_ptr()),
+                        pcszClass: PCWSTR::null(),
+                        oaifInFlags: OAIF_EXEC | OAIF_ALLOW_REGISTRATION,
+                    };
+                    // メインウィンドウの後ろに隠れないよう、オーナーを指定する
+                    if let Err(e) = SHOpenWithDialog(Some(HWND(owner_raw as *mut c_void)), &info) {
+                        // ダイアログを閉じた場合はERROR_CANCELLEDになるため何もしない
+                        if e.code() != ERROR_CANCELLED.to_hresult() {
+                            eprintln!("SHOpenWithDialog failed: {:?}", e);
+                        }
+                    }
+                    if com_initialized { CoUninitialize(); }
                 });
             }
         }
+        IDM_CONTEXT_RUN_AS_ADMIN => {
+            if let Some(result) = state.result_at(lparam.0 as usize).filter(|result| !result.is_folder) {
+                if can_run_as_admin(Path::new(&result.full_path)) {
+                    let path_w = str_to_wide(&result.full_path);
+                    thread::spawn(move || unsafe {
+                        let instance = ShellExecuteW(None, w!("runas"), PCWSTR(path_w.as_ptr()), None, None, SW_SHOW);
+                        // UACの確認で「いいえ」を選んだ場合はERROR_CANCELLEDになるため何もしない
+                        if instance.0 as isize <= 32 {
+                            let error = GetLastError();
+                            if error != ERROR_CANCELLED {
+                                eprintln!("ShellExecuteW(runas) failed: {:?}", error);
+                            }
+                        }
+                    });
+                }
+            }
+        }
+        IDM_CONTEXT_OPEN_FOLDER => open_selected_folders(window, state, lparam.0 as usize),
+        id if (IDM_CONTEXT_SEND_TO_FIRST..IDM_CONTEXT_SEND_TO_FIRST + SEND_TO_MAX as u16).contains(&id) => {
+            run_send_to(window, state, (id - IDM_CONTEXT_SEND_TO_FIRST) as usize, lparam.0 as usize);
+        }
+        IDM_CONTEXT_PROPERTIES => {
+            if let Some(result) = state.result_at(lparam.0 as usize) {
+                let path_w = str_to_wide(&result.full_path);
+                // プロパティシートはモードレスで表示されるため、UIスレッドから呼んでも待たされない
+                let _ = unsafe { SHObjectProperties(Some(window), SHOP_FILEPATH, PCWSTR(path_w.as_ptr()), None) };
+            }
+        }
+        IDM_CONTEXT_OPEN_TERMINAL => {
+            if let Some(result) = state.result_at(lparam.0 as usize) {
+                // フォルダはその中、ファイルは親フォルダで開く
+                let directory = if result.is_folder { PathBuf::from(&result.full_path) } else { PathBuf::from(&result.path) };
+                thread::spawn(move || open_terminal(&directory));
+            }
+        }
+        IDM_CONTEXT_COPY_MARKDOWN => {
+            let results = collect_results_for_export(window, state).unwrap_or_default();
+            if !results.is_empty() {
+                copy_text_to_clipboard(window, &format_markdown_table(&results, state.binary_units));
+            }
+        }
         IDM_CONTEXT_COPY_PATH => {
-            let item_index = lparam.0 as usize;
-            ensure_data_available(state, item_index);
-            let results = state.search_results.lock().unwrap();
-            let local_index = item_index - state.current_page_offset;
-            if let Some(result) = results.get(local_index) {
-                let full_path_str = Path::new(&result.path).join(&result.name).to_str().unwrap_or("").to_string();
-                copy_text_to_clipboard(window, &full_path_str);
+            if let Some(result) = state.result_at(lparam.0 as usize) {
+                copy_text_to_clipboard(window, &result.full_path);
+            }
+        }
+        IDM_CONTEXT_COPY_RELATIVE_PATH => copy_relative_paths(window, state, lparam.0 as usize),
+        IDM_CONTEXT_PIN => {
+            if let Some(result) = state.result_at(lparam.0 as usize) {
+                toggle_pin(state, PathBuf::from(&result.full_path));
             }
         }
+        IDM_CONTEXT_COPY_TO | IDM_CONTEXT_MOVE_TO => {
+            transfer_selection(window, state, lparam.0 as usize, control_id == IDM_CONTEXT_MOVE_TO);
+        }
         _ => {}
     }
     LRESULT(0)
@@ -440,9 +1646,17 @@ fn handle_command(window: HWND, wparam: WPARAM, lparam: LPARAM, state: &mut AppS
 
 /// WM_TIMER メッセージのハンドラ
 fn handle_timer(window: HWND, wparam: WPARAM, state: &mut AppState) -> LRESULT {
-    if wparam.0 == TIMER_ID {
-        let _ = unsafe { KillTimer(Some(window), TIMER_ID) };
-        perform_search(state);
+    match wparam.0 {
+        TIMER_ID => {
+            let _ = unsafe { KillTimer(Some(window), TIMER_ID) };
+            perform_search(state, false);
+        }
+        STATUS_FLASH_TIMER_ID => {
+            let _ = unsafe { KillTimer(Some(window), STATUS_FLASH_TIMER_ID) };
+            update_selection_status(state);
+        }
+        AUTO_REFRESH_TIMER_ID => auto_refresh(window, state),
+        _ => {}
     }
     LRESULT(0)
 }
@@ -451,16 +1665,42 @@ fn handle_timer(window: HWND, wparam: WPARAM, state: &mut AppState) -> LRESULT {
 fn handle_notify(window: HWND, lparam: LPARAM, state: &mut AppState) -> LRESULT {
     let nmhdr = unsafe { &*(lparam.0 as *const NMHDR) };
 
+    if nmhdr.code == NM_CUSTOMDRAW && nmhdr.hwndFrom == listview_header(state.listview_hwnd) {
+        return handle_header_custom_draw(lparam, state);
+    }
     if nmhdr.hwndFrom == state.listview_hwnd {
         match nmhdr.code {
             LVN_GETDISPINFOW => handle_get_disp_info(lparam, state),
+            LVN_ODFINDITEMW => return LRESULT(find_typed_item(lparam, state)),
+            LVN_ODCACHEHINT => {
+                let hint = unsafe { &*(lparam.0 as *const NMLVCACHEHINT) };
+                state.prefetch_rows(hint.iFrom.max(0) as usize, hint.iTo.max(0) as usize);
+            }
             NM_CUSTOMDRAW => return handle_custom_draw(lparam, state),
             NM_RCLICK => handle_right_click(window, lparam, state),
+            LVN_ITEMCHANGED | LVN_ODSTATECHANGED => {
+                update_selection_status(state);
+                update_preview(state);
+            }
+            NM_CLICK => {
+                let item_activate = unsafe { &*(lparam.0 as *const NMITEMACTIVATE) };
+                if item_activate.iItem >= 0 {
+                    toggle_folder_group(state, item_activate.iItem as usize);
+                }
+            }
             NM_DBLCLK => {
                 let item_activate = unsafe { &*(lparam.0 as *const NMITEMACTIVATE) };
                 if item_activate.iItem != -1 {
+                    if state.folder_double_click == FolderDoubleClick::NavigateScope {
+                        let folder = state.result_at(item_activate.iItem as usize).filter(|result| result.is_folder).map(|result| PathBuf::from(&result.full_path));
+                        if let Some(folder) = folder {
+                            navigate_to_folder(window, state, folder);
+                            return LRESULT(0);
+                        }
+                    }
+                    let command = double_click_command(state.double_click_action);
                     unsafe {
-                        SendMessageW(window, WM_COMMAND, Some(WPARAM(IDM_CONTEXT_OPEN as usize)), Some(LPARAM(item_activate.iItem as isize)));
+                        SendMessageW(window, WM_COMMAND, Some(WPARAM(command as usize)), Some(LPARAM(item_activate.iItem as isize)));
                     }
                 }
             }
@@ -470,26 +1710,108 @@ fn handle_notify(window: HWND, lparam: LPARAM, state: &mut AppState) -> LRESULT
     LRESULT(0)
 }
 
+/// LVN_ODFINDITEMW（リストでの型付き検索）のハンドラ
+/// `iStart` の行から順に名前が入力した文字で始まる行を探し、末尾まで来たら先頭に戻る
+/// 見つからなければ -1 を返す。ページを読み込みながら探すため、調べる行数は `TYPE_AHEAD_MAX_ROWS` までにする
+fn find_typed_item(lparam: LPARAM, state: &mut AppState) -> isize {
+    let find_item = unsafe { &*(lparam.0 as *const NMLVFINDITEMW) };
+    // 検索ボックスへの入力とは関係なく、リストにフォーカスがあるときだけ応じる
+    if unsafe { GetFocus() } != state.listview_hwnd { return -1; }
+    if (find_item.lvfi.flags & (LVFI_STRING | LVFI_PARTIAL)).0 == 0 || find_item.lvfi.psz.is_null() { return -1; }
+    let typed = String::from_utf16_lossy(unsafe { find_item.lvfi.psz.as_wide() });
+    let row_count = state.row_count();
+    if row_count == 0 { return -1; }
+
+    let start = (find_item.iStart.max(0) as usize).min(row_count - 1);
+    for step in 0..row_count.min(TYPE_AHEAD_MAX_ROWS) {
+        let index = (start + step) % row_count;
+        if state.result_at(index).is_some_and(|result| starts_with_ignore_case(&result.name, &typed)) {
+            return index as isize;
+        }
+    }
+    -1
+}
+
 /// WM_SIZE メッセージのハンドラ
-fn handle_size(_window: HWND, lparam: LPARAM, state: &AppState) -> LRESULT {
+fn handle_size(window: HWND, wparam: WPARAM, lparam: LPARAM, state: &mut AppState) -> LRESULT {
+    if wparam.0 as u32 == SIZE_MINIMIZED && state.minimize_to_tray {
+        // タスクバーから消し、通知領域のアイコンからだけ戻せるようにする
+        let _ = unsafe { ShowWindow(window, SW_HIDE) };
+        return LRESULT(0);
+    }
     let width = loword(lparam.0 as u32) as i32;
     let height = hiword(lparam.0 as u32) as i32;
     layout_controls(width, height, state);
     LRESULT(0)
 }
 
-/// WM_SETFOCUS メッセージのハンドラ
-fn handle_setfocus(state: &AppState) -> LRESULT {
-    let _ = unsafe { SetFocus(Some(state.edit_hwnd)) };
-    LRESULT(0)
-}
-
+/// WM_EDIT_COMPOSITION メッセージのハンドラ
+/// 変換の開始時は予約済みの検索を取り消し、終了時に（確定した文字で）予約し直す
+fn handle_edit_composition(window: HWND, wparam: WPARAM, state: &mut AppState) -> LRESULT {
+    state.ime_composing = wparam.0 != 0;
+    if !state.live_search { return LRESULT(0); }
+    unsafe {
+        if state.ime_composing {
+            let _ = KillTimer(Some(window), TIMER_ID);
+        } else {
+            SetTimer(Some(window), TIMER_ID, 500, None);
+        }
+    }
+    LRESULT(0)
+}
+
+/// WM_TRAY_ICON メッセージのハンドラ
+/// 左クリックでウィンドウを戻し、右クリックで「表示」「終了」のメニューを出す
+fn handle_tray_icon(window: HWND, lparam: LPARAM) -> LRESULT {
+    match lparam.0 as u32 {
+        WM_LBUTTONUP => restore_from_tray(window),
+        WM_RBUTTONUP => show_tray_menu(window),
+        _ => {}
+    }
+    LRESULT(0)
+}
+
+/// WM_SETFOCUS メッセージのハンドラ
+fn handle_setfocus(state: &AppState) -> LRESULT {
+    let _ = unsafe { SetFocus(Some(state.edit_hwnd)) };
+    LRESULT(0)
+}
+
+/// WM_SETTINGCHANGE / WM_SYSCOLORCHANGE / WM_THEMECHANGED / WM_DWMCOLORIZATIONCOLORCHANGED メッセージのハンドラ
+/// ハイコントラストやテーマ・配色の切り替えを再起動せずに反映する
+fn handle_setting_change(window: HWND, message: u32, state: &mut AppState) -> LRESULT {
+    state.high_contrast = is_high_contrast();
+    refresh_accent_color(state);
+    unsafe {
+        // コモンコントロールは配色の変更をトップレベルウィンドウから転送してもらう必要がある
+        if message == WM_SYSCOLORCHANGE {
+            for control in [state.listview_hwnd, listview_header(state.listview_hwnd), state.status_hwnd] {
+                SendMessageW(control, WM_SYSCOLORCHANGE, None, None);
+            }
+        }
+        // 行の背景色や強調色は描画のたびにシステムの色から求めるため、全体を描き直せば新しい色になる
+        let _ = RedrawWindow(Some(window), None, None, RDW_INVALIDATE | RDW_ERASE | RDW_FRAME | RDW_ALLCHILDREN);
+    }
+    LRESULT(0)
+}
+
 /// WM_DPICHANGED メッセージのハンドラ
 fn handle_dpi_changed(window: HWND, wparam: WPARAM, lparam: LPARAM, state: &mut AppState) -> LRESULT {
     let new_dpi = hiword(wparam.0 as u32) as u32;
+    let old_dpi = state.current_dpi;
     state.current_dpi = new_dpi;
     state.scale_factor = new_dpi as f32 / 96.0;
-    
+
+    // フォントを新しいスケールで作り直す
+    let old_font = std::mem::replace(&mut state.h_font, create_ui_font(state.scale_factor));
+    apply_font(state);
+    if !old_font.is_invalid() {
+        let _ = unsafe { DeleteObject(old_font.into()) };
+    }
+
+    refresh_imagelist(state);
+    rescale_columns(state, old_dpi, new_dpi);
+
     let suggested_rect = unsafe { &*(lparam.0 as *const RECT) };
     
     unsafe {
@@ -500,9 +1822,53 @@ fn handle_dpi_changed(window: HWND, wparam: WPARAM, lparam: LPARAM, state: &mut
             suggested_rect.bottom - suggested_rect.top,
             SWP_NOZORDER | SWP_NOACTIVATE,
         );
+        // サイズが変わらない場合はWM_SIZEが来ないため、明示的に再配置する
+        let mut client = RECT::default();
+        let _ = GetClientRect(window, &mut client);
+        layout_controls(client.right, client.bottom, state);
         let _ = InvalidateRect(Some(window), None, true);
     }
-    
+
+    LRESULT(0)
+}
+
+/// DPI変更に合わせてリストビューの各カラム幅を拡大縮小する
+fn rescale_columns(state: &AppState, old_dpi: u32, new_dpi: u32) {
+    if old_dpi == 0 || old_dpi == new_dpi { return; }
+    let mut column = 0;
+    loop {
+        let width = unsafe { SendMessageW(state.listview_hwnd, LVM_GETCOLUMNWIDTH, Some(WPARAM(column)), Some(LPARAM(0))) }.0;
+        // 存在しないカラムでは0が返る
+        if width == 0 { break; }
+        let new_width = width * new_dpi as isize / old_dpi as isize;
+        unsafe { SendMessageW(state.listview_hwnd, LVM_SETCOLUMNWIDTH, Some(WPARAM(column)), Some(LPARAM(new_width))) };
+        column += 1;
+    }
+}
+
+/// WM_GETMINMAXINFO メッセージのハンドラ
+/// ツールバーとリスト数行が収まる最小サイズを設定する
+fn handle_get_min_max_info(window: HWND, lparam: LPARAM, state: &AppState) -> LRESULT {
+    let info = unsafe { &mut *(lparam.0 as *mut MINMAXINFO) };
+    let mut rect = RECT {
+        left: 0,
+        top: 0,
+        right: (MIN_CLIENT_WIDTH as f32 * state.scale_factor) as i32,
+        bottom: ((MIN_CLIENT_HEIGHT + preview_height(state)) as f32 * state.scale_factor) as i32,
+    };
+    let style = WINDOW_STYLE(unsafe { GetWindowLongW(window, GWL_STYLE) } as u32);
+    let ex_style = WINDOW_EX_STYLE(unsafe { GetWindowLongW(window, GWL_EXSTYLE) } as u32);
+    adjust_window_rect_for_dpi(&mut rect, style, ex_style, state.current_dpi);
+    info.ptMinTrackSize = POINT { x: rect.right - rect.left, y: rect.bottom - rect.top };
+    LRESULT(0)
+}
+
+/// WM_ICON_LOADED メッセージのハンドラ
+/// ワーカーが取得したファイル固有のアイコンを登録し、リストを再描画する
+fn handle_icon_loaded(wparam: WPARAM, lparam: LPARAM, state: &mut AppState) -> LRESULT {
+    let full_path = unsafe { Box::from_raw(lparam.0 as *mut String) };
+    state.icon_cache.complete(*full_path, wparam.0 as i32);
+    let _ = unsafe { InvalidateRect(Some(state.listview_hwnd), None, false) };
     LRESULT(0)
 }
 
@@ -513,71 +1879,131 @@ fn handle_get_disp_info(lparam: LPARAM, state: &mut AppState) {
     let item = &mut dispinfo.item;
     let item_index = item.iItem as usize;
 
-    if item_index >= state.total_results as usize { return; }
-
-    ensure_data_available(state, item_index);
-
-    let results = state.search_results.lock().unwrap();
-    let local_index = item_index - state.current_page_offset;
-    
-    if let Some(result) = results.get(local_index) {
+    if let Some(result) = state.result_at(item_index) {
         if (item.mask & LVIF_TEXT) == LVIF_TEXT {
             let sub_item_index = item.iSubItem as usize;
+            // 列を増やしたときにバッファの数が追いついていなければ、範囲外に書く前に気づけるようにする
+            debug_assert!(sub_item_index < COLUMN_COUNT, "item_wide_buffer has no slot for column {}", sub_item_index);
+            if sub_item_index >= COLUMN_COUNT { return; }
             let text = match sub_item_index {
-                0 => if !result.highlighted_name.is_empty() { parse_highlight_text(&result.highlighted_name).0 } else { result.name.clone() },
-                1 => if !result.highlighted_path.is_empty() { parse_highlight_text(&result.highlighted_path).0 } else { result.path.clone() },
-                2 => format_size(result.size),
-                3 => format_date(result.modified_date),
+                COLUMN_NAME if state.compact_rows => format!("{}{}{}", result.name, NAME_FOLDER_SEPARATOR, result.path),
+                COLUMN_NAME => if !result.highlighted_name.is_empty() { parse_highlight_text(&result.highlighted_name).0 } else { result.name.clone() },
+                COLUMN_PATH => if !result.highlighted_path.is_empty() { parse_highlight_text(&result.highlighted_path).0 } else { result.path.clone() },
+                COLUMN_SIZE => format_size(result.size, result.is_folder, state.binary_units),
+                COLUMN_MODIFIED => format_date(result.modified_date),
+                COLUMN_CREATED => format_date(result.created_date),
+                COLUMN_TYPE => state.type_name_cache.get(&result.name, result.is_folder),
                 _ => String::new(),
             };
-            state.item_wide_buffer[sub_item_index] = str_to_wide(&text);
-            item.pszText = PWSTR(state.item_wide_buffer[sub_item_index].as_mut_ptr());
+            set_item_text(item, &mut state.item_wide_buffer[sub_item_index], &text);
         }
         if item.iSubItem == 0 && (item.mask & LVIF_IMAGE) == LVIF_IMAGE && !result.name.is_empty() {
-            item.iImage = get_icon_index(&result.name, result.is_folder, state.himagelist);
+            item.iImage = state.icon_cache.get(&result.name, Path::new(&result.full_path), result.is_folder, state.main_hwnd);
+        }
+    } else if let Some(group) = state.group_header_at(item_index) {
+        // グループの見出しは名前の列にフォルダと件数を表示し、ほかの列は空にする
+        let text = if item.iSubItem as usize == COLUMN_NAME { group.label() } else { String::new() };
+        let folder = group.folder.clone();
+        if (item.mask & LVIF_TEXT) == LVIF_TEXT && (item.iSubItem as usize) < COLUMN_COUNT {
+            set_item_text(item, &mut state.item_wide_buffer[item.iSubItem as usize], &text);
         }
+        if item.iSubItem == 0 && (item.mask & LVIF_IMAGE) == LVIF_IMAGE {
+            item.iImage = state.icon_cache.get(&folder, Path::new(&folder), true, state.main_hwnd);
+        }
+    }
+}
+
+/// LVN_GETDISPINFOW で返す文字列を列のバッファに書き、そのポインタを渡す
+fn set_item_text(item: &mut LVITEMW, buffer: &mut Vec<u16>, text: &str) {
+    // コントロールの表示用バッファ（cchTextMax、通常260文字）を超える分は表示されないため、NULを含めて収まるよう切り詰める
+    // 確保済みの領域を使い回すので、長いパスが続いても通知のたびに確保し直さない
+    buffer.clear();
+    buffer.extend(text.encode_utf16());
+    if item.cchTextMax > 0 {
+        truncate_utf16(buffer, item.cchTextMax as usize - 1);
     }
+    buffer.push(0);
+    item.pszText = PWSTR(buffer.as_mut_ptr());
 }
 
 fn handle_custom_draw(lparam: LPARAM, state: &mut AppState) -> LRESULT {
     let custom_draw = unsafe { &mut *(lparam.0 as *mut NMLVCUSTOMDRAW) };
 
     match custom_draw.nmcd.dwDrawStage {
-        CDDS_PREPAINT => LRESULT(CDRF_NOTIFYITEMDRAW as isize),
+        CDDS_PREPAINT => LRESULT((CDRF_NOTIFYITEMDRAW | CDRF_NOTIFYPOSTPAINT) as isize),
+        CDDS_POSTPAINT => {
+            if state.total_results == 0 && state.duplicates.is_none() {
+                draw_list_status(custom_draw.nmcd.hdc, state);
+            }
+            LRESULT(CDRF_DODEFAULT as isize)
+        }
+        CDDS_ITEMPREPAINT if state.duplicates.is_some() || state.folder_groups.is_some() => LRESULT((CDRF_NOTIFYSUBITEMDRAW | CDRF_NOTIFYPOSTPAINT) as isize),
         CDDS_ITEMPREPAINT => LRESULT(CDRF_NOTIFYSUBITEMDRAW as isize),
+        CDDS_ITEMPOSTPAINT => {
+            // 重複グループの先頭行とフォルダのグループの見出しの上端に区切り線を引く
+            let item_index = custom_draw.nmcd.dwItemSpec as usize;
+            let is_group_start = state.duplicates.as_ref().is_some_and(|duplicates| duplicates.is_group_start(item_index))
+                || state.group_header_at(item_index).is_some();
+            if item_index > 0 && is_group_start {
+                let rect = custom_draw.nmcd.rc;
+                let line = RECT { bottom: rect.top + (state.scale_factor.round() as i32).max(1), ..rect };
+                unsafe {
+                    let brush = CreateSolidBrush(COLORREF(GetSysColor(COLOR_GRAYTEXT)));
+                    FillRect(custom_draw.nmcd.hdc, &line, brush);
+                    let _ = DeleteObject(brush.into());
+                }
+            }
+            LRESULT(CDRF_DODEFAULT as isize)
+        }
         stage if stage.0 == (CDDS_SUBITEM.0 | CDDS_ITEMPREPAINT.0) => {
             let item_index = custom_draw.nmcd.dwItemSpec as usize;
             let sub_item_index = custom_draw.iSubItem as usize;
-
-            if item_index >= state.total_results as usize { return LRESULT(CDRF_DODEFAULT as isize); }
-
-            ensure_data_available(state, item_index);
-
-            let results = state.search_results.lock().unwrap();
-            let local_index = item_index - state.current_page_offset;
-            
-            if let Some(result) = results.get(local_index) {
-                let (text_to_draw, highlight_ranges) = match sub_item_index {
-                    0 if !result.highlighted_name.is_empty() => parse_highlight_text(&result.highlighted_name),
-                    1 if !result.highlighted_path.is_empty() => parse_highlight_text(&result.highlighted_path),
+            // 既定の描画に任せる列も同じ背景色で塗られるようにする
+            let row_color = row_background_color(state, item_index);
+            custom_draw.clrTextBk = COLORREF(row_color);
+
+            if let Some(result) = state.result_at(item_index) {
+                let (mut text_to_draw, mut highlight_ranges) = match sub_item_index {
+                    COLUMN_NAME if !result.highlighted_name.is_empty() => parse_highlight_text(&result.highlighted_name),
+                    COLUMN_NAME if state.compact_rows => (result.name.clone(), Vec::new()),
+                    COLUMN_PATH if state.highlight_path && !result.highlighted_path.is_empty() => parse_highlight_text(&result.highlighted_path),
+                    // 末尾以外を省略する場合は、一致箇所がなくても自前で描く
+                    COLUMN_PATH if state.path_ellipsis != EllipsisPosition::End => (result.path.clone(), Vec::new()),
+                    // ハイライトのない列（サイズ・日時・種類）はLVN_GETDISPINFOのテキストで既定の描画を行う
                     _ => return LRESULT(CDRF_DODEFAULT as isize),
                 };
 
-                if highlight_ranges.is_empty() { return LRESULT(CDRF_DODEFAULT as isize); }
+                // 1列表示ではフォルダを名前の後ろに続け、区切りから後ろを薄い色で描く
+                let mut dim_from = None;
+                if state.compact_rows && sub_item_index == COLUMN_NAME {
+                    let (folder, folder_ranges) = if state.highlight_path && !result.highlighted_path.is_empty() {
+                        parse_highlight_text(&result.highlighted_path)
+                    } else {
+                        (result.path.clone(), Vec::new())
+                    };
+                    let (joined, ranges, separator_from) = join_name_and_folder(&text_to_draw, &highlight_ranges, &folder, &folder_ranges);
+                    text_to_draw = joined;
+                    highlight_ranges = ranges;
+                    dim_from = Some(separator_from);
+                }
+                let is_dimmed = |pos: usize| dim_from.is_some_and(|from| pos >= from);
+
+                let custom_ellipsis = sub_item_index == COLUMN_PATH && state.path_ellipsis != EllipsisPosition::End;
+                if highlight_ranges.is_empty() && dim_from.is_none() && !custom_ellipsis { return LRESULT(CDRF_DODEFAULT as isize); }
 
                 let hdc = custom_draw.nmcd.hdc;
                 let mut rect = custom_draw.nmcd.rc;
                 let is_selected = (custom_draw.nmcd.uItemState & CDIS_SELECTED).0 != 0;
 
-                let bg_color = if is_selected { unsafe { GetSysColor(COLOR_HIGHLIGHT) } } else { unsafe { GetSysColor(COLOR_WINDOW) } };
+                let bg_color = if is_selected { unsafe { GetSysColor(COLOR_HIGHLIGHT) } } else { row_color };
                 let bg_brush = unsafe { CreateSolidBrush(COLORREF(bg_color)) };
                 unsafe { FillRect(hdc, &rect, bg_brush) };
                 let _ = unsafe { DeleteObject(bg_brush.into()) };
 
-                if sub_item_index == 0 && !result.name.is_empty() {
-                    let icon_index = get_icon_index(&result.name, result.is_folder, state.himagelist);
+                if sub_item_index == COLUMN_NAME && !result.name.is_empty() {
+                    let icon_index = state.icon_cache.get(&result.name, Path::new(&result.full_path), result.is_folder, state.main_hwnd);
                     if state.himagelist.0 != 0 && icon_index >= 0 {
-                        let icon_size = (16.0 * state.scale_factor) as i32;
+                        let icon_size = state.icon_size;
                         let icon_padding = (2.0 * state.scale_factor) as i32;
                         let icon_y = rect.top + (rect.bottom - rect.top - icon_size) / 2;
                         
@@ -585,111 +2011,121 @@ fn handle_custom_draw(lparam: LPARAM, state: &mut AppState) -> LRESULT {
                             let _ = unsafe { ImageList_Draw(state.himagelist, icon_index, hdc, rect.left + icon_padding, icon_y, ILD_TRANSPARENT) };
                         }
                     }
-                    rect.left += (22.0 * state.scale_factor) as i32;
+                    rect.left += state.icon_size + (6.0 * state.scale_factor) as i32;
                 } else {
                     rect.left += (4.0 * state.scale_factor) as i32;
                 }
                 rect.right -= (4.0 * state.scale_factor) as i32;
 
                 let text_color = if is_selected { unsafe { GetSysColor(COLOR_HIGHLIGHTTEXT) } } else { unsafe { GetSysColor(COLOR_WINDOWTEXT) } };
+                let dim_color = if is_selected { text_color } else { unsafe { GetSysColor(COLOR_GRAYTEXT) } };
+                // ハイコントラストでは固定の黄色を使わず、選択色で一致箇所を示す
+                let (match_back_color, match_text_color) = if state.high_contrast {
+                    unsafe { (GetSysColor(COLOR_HIGHLIGHT), GetSysColor(COLOR_HIGHLIGHTTEXT)) }
+                } else if let Some(accent) = state.accent_color {
+                    let back = blend_color(row_color, accent, ACCENT_HIGHLIGHT_TINT);
+                    (back, contrasting_text_color(back))
+                } else {
+                    (HIGHLIGHT_BACK_COLOR, text_color)
+                };
                 unsafe {
                     SetBkMode(hdc, TRANSPARENT);
                     SetTextColor(hdc, COLORREF(text_color));
                 }
 
-                let mut x = rect.left;
                 let font_offset = (8.0 * state.scale_factor) as i32;
                 let y = rect.top + (rect.bottom - rect.top) / 2 - font_offset;
                 let chars: Vec<char> = text_to_draw.chars().collect();
                 
                 let full_text_wide = str_to_wide(&text_to_draw);
-                let mut char_widths = vec![0i32; chars.len()];
-                let mut max_fit_chars = chars.len();
+                // 部分幅はUTF-16単位で返るため、単位数分のバッファで受けてから文字ごとの幅に変換する
+                let mut unit_widths = vec![0i32; full_text_wide.len().saturating_sub(1)];
                 
-                if chars.len() > 0 && full_text_wide.len() > 1 {
-                    let mut fit_count = 0i32;
+                if !unit_widths.is_empty() {
+                    // 表示する範囲は elide_text で求めるため、ここではすべての文字の部分幅を取得する
                     let mut size = SIZE::default();
                     let _ = unsafe {
-                        GetTextExtentExPointW(hdc, PCWSTR(full_text_wide.as_ptr()), (full_text_wide.len() - 1) as i32, rect.right - rect.left, Some(&mut fit_count), Some(char_widths.as_mut_ptr()), &mut size)
+                        GetTextExtentExPointW(hdc, PCWSTR(full_text_wide.as_ptr()), unit_widths.len() as i32, 0, None, Some(unit_widths.as_mut_ptr()), &mut size)
                     };
-                    max_fit_chars = fit_count as usize;
                 }
+                let char_widths = char_extents(&text_to_draw, &unit_widths);
                 
                 let ellipsis = "...";
                 let ellipsis_wide = str_to_wide(ellipsis);
                 let ellipsis_width = unsafe { let mut size = SIZE::default(); let _ = GetTextExtentPointW(hdc, &ellipsis_wide, &mut size); size.cx };
                 
-                let is_truncated = chars.len() > max_fit_chars;
-                
-                let effective_max_chars = if is_truncated {
-                    let available_width_for_text = rect.right - rect.left - ellipsis_width;
-                    if available_width_for_text > 0 {
-                        let mut truncated_fit_count = 0i32;
-                        let mut size = SIZE::default();
-                        let _ = unsafe { GetTextExtentExPointW(hdc, PCWSTR(full_text_wide.as_ptr()), (full_text_wide.len() - 1) as i32, available_width_for_text, Some(&mut truncated_fit_count), None, &mut size) };
-                        std::cmp::min(truncated_fit_count as usize, max_fit_chars)
-                    } else { 0 }
-                } else { max_fit_chars };
-                
-                let mut current_pos = 0;
-                let mut last_drawn_pos = 0;
-                
-                while current_pos < chars.len() && current_pos < effective_max_chars {
-                    let is_current_highlighted = highlight_ranges.iter().any(|(start, end)| current_pos >= *start && current_pos < *end);
-                    let mut end_pos = current_pos + 1;
-                    
-                    while end_pos < chars.len() && end_pos <= effective_max_chars {
-                        let is_next_highlighted = highlight_ranges.iter().any(|(start, end)| end_pos >= *start && end_pos < *end);
-                        if is_current_highlighted == is_next_highlighted { end_pos += 1; } else { break; }
-                    }
-                    
-                    end_pos = std::cmp::min(end_pos, effective_max_chars);
-                    let text_segment: String = chars[current_pos..end_pos].iter().collect();
-                    let text_wide = str_to_wide(&text_segment);
-                    
-                    let start_x = if current_pos == 0 { 0 } else { char_widths[current_pos - 1] };
-                    let end_x = if end_pos > 0 && end_pos <= char_widths.len() { char_widths[end_pos - 1] } else { 0 };
-                    let segment_width = end_x - start_x;
-                    let available_space = rect.right - x;
-                    let actual_segment_width = std::cmp::min(segment_width, available_space);
-                    
-                    if actual_segment_width <= 0 || x >= rect.right { break; }
-                    
-                    if is_current_highlighted && !is_selected {
-                        let highlight_left = x;
-                        let highlight_right = std::cmp::min(x + segment_width, rect.right);
-                        
-                        if highlight_right > highlight_left && highlight_left < rect.right {
-                            let highlight_brush = unsafe { CreateSolidBrush(COLORREF(0x00FFFF)) };
-                            let highlight_rect = RECT { left: highlight_left, top: rect.top, right: highlight_right, bottom: rect.bottom };
-                            unsafe { FillRect(hdc, &highlight_rect, highlight_brush) };
-                            let _ = unsafe { DeleteObject(highlight_brush.into()) };
+                // フォルダ列は設定に従って中央や先頭を省略できる。それ以外の列は末尾を省略する
+                let position = if sub_item_index == COLUMN_PATH { state.path_ellipsis } else { EllipsisPosition::End };
+                let elision = elide_text(&text_to_draw, &char_widths, rect.right - rect.left, ellipsis_width, position);
+
+                // from..to の文字を一致箇所と薄く描く範囲の境目で区切って x から描き、描き終えた位置を返す
+                let draw_run = |from: usize, to: usize, mut x: i32| -> i32 {
+                    let mut current_pos = from;
+
+                    while current_pos < chars.len() && current_pos < to {
+                        let is_current_highlighted = highlight_ranges.iter().any(|(start, end)| current_pos >= *start && current_pos < *end);
+                        let mut end_pos = current_pos + 1;
+
+                        while end_pos < chars.len() && end_pos <= to {
+                            let is_next_highlighted = highlight_ranges.iter().any(|(start, end)| end_pos >= *start && end_pos < *end);
+                            if is_current_highlighted == is_next_highlighted && is_dimmed(current_pos) == is_dimmed(end_pos) { end_pos += 1; } else { break; }
                         }
+
+                        end_pos = std::cmp::min(end_pos, to);
+                        let text_segment: String = chars[current_pos..end_pos].iter().collect();
+                        let text_wide = str_to_wide(&text_segment);
+
+                        let start_x = current_pos.checked_sub(1).map_or(0, |i| char_widths[i]);
+                        let end_x = end_pos.checked_sub(1).map_or(0, |i| char_widths[i]);
+                        let segment_width = end_x - start_x;
+                        let available_space = rect.right - x;
+                        let actual_segment_width = std::cmp::min(segment_width, available_space);
+
+                        if actual_segment_width <= 0 || x >= rect.right { break; }
+
+                        let is_match_drawn = is_current_highlighted && !is_selected;
+                        if is_match_drawn {
+                            let highlight_left = x;
+                            let highlight_right = std::cmp::min(x + segment_width, rect.right);
+
+                            if highlight_right > highlight_left && highlight_left < rect.right {
+                                let highlight_brush = unsafe { CreateSolidBrush(COLORREF(match_back_color)) };
+                                let highlight_rect = RECT { left: highlight_left, top: rect.top, right: highlight_right, bottom: rect.bottom };
+                                unsafe { FillRect(hdc, &highlight_rect, highlight_brush) };
+                                let _ = unsafe { DeleteObject(highlight_brush.into()) };
+                            }
+                        }
+
+                        unsafe {
+                            let clip_region = CreateRectRgn(rect.left, rect.top, rect.right, rect.bottom);
+                            SelectClipRgn(hdc, Some(clip_region));
+                            let segment_color = if is_match_drawn { match_text_color } else if is_dimmed(current_pos) { dim_color } else { text_color };
+                            SetTextColor(hdc, COLORREF(segment_color));
+                            let _ = TextOutW(hdc, x, y, &text_wide);
+                            SelectClipRgn(hdc, None);
+                            let _ = DeleteObject(clip_region.into());
+                        }
+
+                        x += actual_segment_width;
+                        current_pos = end_pos;
+                        if x >= rect.right { break; }
                     }
-                    
-                    unsafe {
-                        let clip_region = CreateRectRgn(rect.left, rect.top, rect.right, rect.bottom);
-                        SelectClipRgn(hdc, Some(clip_region));
-                        let _ = TextOutW(hdc, x, y, &text_wide);
-                        SelectClipRgn(hdc, None);
-                        let _ = DeleteObject(clip_region.into());
-                    }
-                    
-                    x += actual_segment_width;
-                    current_pos = end_pos;
-                    last_drawn_pos = end_pos;
-                    if x >= rect.right { break; }
-                }
-                
-                if is_truncated && last_drawn_pos < chars.len() && x + ellipsis_width <= rect.right {
+                    x
+                };
+
+                let mut x = draw_run(0, elision.head, rect.left);
+                if elision.ellipsis && x + ellipsis_width <= rect.right {
                     unsafe {
                         let clip_region = CreateRectRgn(rect.left, rect.top, rect.right, rect.bottom);
                         SelectClipRgn(hdc, Some(clip_region));
+                        SetTextColor(hdc, COLORREF(text_color));
                         let _ = TextOutW(hdc, x, y, &ellipsis_wide);
                         SelectClipRgn(hdc, None);
                         let _ = DeleteObject(clip_region.into());
                     }
+                    x += ellipsis_width;
                 }
+                draw_run(elision.tail, chars.len(), x);
                 return LRESULT(CDRF_SKIPDEFAULT as isize);
             }
             LRESULT(CDRF_DODEFAULT as isize)
@@ -698,51 +2134,192 @@ fn handle_custom_draw(lparam: LPARAM, state: &mut AppState) -> LRESULT {
     }
 }
 
+/// ヘッダーのカスタムドロー
+/// 検索語の `size:` / `dm:` / `dc:` で絞り込んでいる列の見出しを強調色の文字と下線で示す
+fn handle_header_custom_draw(lparam: LPARAM, state: &AppState) -> LRESULT {
+    let custom_draw = unsafe { &*(lparam.0 as *const NMCUSTOMDRAW) };
+    let is_filtered = || {
+        let filters = state.column_filters;
+        match column_positions(state).get(custom_draw.dwItemSpec) {
+            Some(&COLUMN_SIZE) => filters.size,
+            Some(&COLUMN_MODIFIED) => filters.date_modified,
+            Some(&COLUMN_CREATED) => filters.date_created,
+            _ => false,
+        }
+    };
+
+    match custom_draw.dwDrawStage {
+        CDDS_PREPAINT if state.column_filters != ColumnFilters::default() => LRESULT(CDRF_NOTIFYITEMDRAW as isize),
+        CDDS_ITEMPREPAINT if is_filtered() => {
+            unsafe { SetTextColor(custom_draw.hdc, COLORREF(GetSysColor(COLOR_HIGHLIGHT))) };
+            LRESULT((CDRF_NEWFONT | CDRF_NOTIFYPOSTPAINT) as isize)
+        }
+        CDDS_ITEMPOSTPAINT => {
+            let rect = custom_draw.rc;
+            let line = RECT { top: rect.bottom - (2.0 * state.scale_factor).round() as i32, ..rect };
+            unsafe {
+                let brush = CreateSolidBrush(COLORREF(GetSysColor(COLOR_HIGHLIGHT)));
+                FillRect(custom_draw.hdc, &line, brush);
+                let _ = DeleteObject(brush.into());
+            }
+            LRESULT(CDRF_DODEFAULT as isize)
+        }
+        _ => LRESULT(CDRF_DODEFAULT as isize),
+    }
+}
+
+/// 列見出しの強調に使う絞り込みを更新し、変わった場合はヘッダーを再描画する
+fn set_column_filters(state: &mut AppState, filters: ColumnFilters) {
+    if state.column_filters == filters { return; }
+    state.column_filters = filters;
+    let _ = unsafe { InvalidateRect(Some(listview_header(state.listview_hwnd)), None, true) };
+}
+
+/// リストビューのヘッダーコントロール
+fn listview_header(listview: HWND) -> HWND {
+    HWND(unsafe { SendMessageW(listview, LVM_GETHEADER, None, None) }.0 as *mut c_void)
+}
+
+/// 結果が0件のとき、リスト領域の中央に状態メッセージを描画する
+fn draw_list_status(hdc: HDC, state: &AppState) {
+    let message = match state.list_status {
+        ListStatus::Idle => return,
+        ListStatus::Searching => "検索中…",
+        ListStatus::NoResults => "該当なし",
+    };
+
+    unsafe {
+        let mut rect = RECT::default();
+        let _ = GetClientRect(state.listview_hwnd, &mut rect);
+        // ヘッダーの下から描画する
+        let header = listview_header(state.listview_hwnd);
+        let mut header_rect = RECT::default();
+        if GetWindowRect(header, &mut header_rect).is_ok() {
+            rect.top += header_rect.bottom - header_rect.top;
+        }
+
+        let old_font = SelectObject(hdc, state.h_font.into());
+        SetBkMode(hdc, TRANSPARENT);
+        SetTextColor(hdc, COLORREF(GetSysColor(COLOR_GRAYTEXT)));
+        let mut text: Vec<u16> = message.encode_utf16().collect();
+        DrawTextW(hdc, &mut text, &mut rect, DT_CENTER | DT_VCENTER | DT_SINGLELINE | DT_NOPREFIX);
+        SelectObject(hdc, old_font);
+    }
+}
+
 fn handle_right_click(window: HWND, lparam: LPARAM, state: &mut AppState) {
     let item_activate = unsafe { &*(lparam.0 as *const NMITEMACTIVATE) };
-    let item_index = item_activate.iItem;
+    if item_activate.iItem == -1 { return; }
+    show_item_context_menu(window, state, item_activate.iItem, item_activate.ptAction);
+}
 
-    if item_index == -1 { return; }
+/// WM_CONTEXTMENU メッセージのハンドラ（リストビューから転送されたもの）
+/// マウスの右クリックは NM_RCLICK で処理済みのため、キーボード（アプリケーションキー・Shift+F10）で座標が -1 のときだけ扱う
+/// 選択中のフォーカス行の左上に、右クリックと同じメニューを表示する
+fn handle_context_menu(window: HWND, lparam: LPARAM, state: &mut AppState) -> LRESULT {
+    if lparam.0 as u32 != u32::MAX { return LRESULT(0); }
+    let flags = LVNI_FOCUSED | LVNI_SELECTED;
+    let index = unsafe { SendMessageW(state.listview_hwnd, LVM_GETNEXTITEM, Some(WPARAM(usize::MAX)), Some(LPARAM(flags as isize))) }.0;
+    if index < 0 { return LRESULT(0); }
 
-    // デッドロックを避けるため、メニュー表示の前にファイルパスを取得し、Mutexロックを解放する
-    let maybe_full_path: Option<PathBuf> = {
-        ensure_data_available(state, item_index as usize);
-        let results = state.search_results.lock().unwrap();
-        let local_index = item_index as usize - state.current_page_offset;
-        results.get(local_index).map(|result| {
-            Path::new(&result.path).join(&result.name)
-        })
-    };
+    let mut rect = RECT { left: LVIR_BOUNDS as i32, ..Default::default() };
+    unsafe {
+        SendMessageW(state.listview_hwnd, LVM_ENSUREVISIBLE, Some(WPARAM(index as usize)), Some(LPARAM(0)));
+        SendMessageW(state.listview_hwnd, LVM_GETITEMRECT, Some(WPARAM(index as usize)), Some(LPARAM(&mut rect as *mut _ as isize)));
+    }
+    // 横にスクロールしていると行の左端がリストの外に出るため、見えている範囲に収める
+    let point = POINT { x: rect.left.max(0), y: rect.top };
+    show_item_context_menu(window, state, index as i32, point);
+    LRESULT(0)
+}
+
+/// 行のコンテキストメニュー（シェルのメニューまたは独自のメニュー）を表示する
+/// `point` はリストビューのクライアント座標
+fn show_item_context_menu(window: HWND, state: &mut AppState, item_index: i32, point: POINT) {
+    // メニュー表示中にページが読み込み直されても影響しないよう、先にファイルパスを取得しておく
+    let maybe_item: Option<(PathBuf, bool)> = state.result_at(item_index as usize)
+        .map(|result| (PathBuf::from(&result.full_path), result.is_folder));
 
     // 有効なパスが取得できた場合のみ続行
-    if let Some(full_path) = maybe_full_path {
+    if let Some((full_path, is_folder)) = maybe_item {
         if state.shell_context_enabled {
             // --- Shell Context Menu Logic ---
-            show_shell_context_menu(window, state.listview_hwnd, &full_path, item_activate.ptAction);
-        } else {
-            // --- Original Custom Menu Logic ---
-            unsafe {
-                let h_popup_menu = CreatePopupMenu().unwrap();
-                let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_CONTEXT_OPEN as usize, w!("開く(&O)"));
-                let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_CONTEXT_OPEN_FOLDER as usize, w!("フォルダを開く(&F)"));
-                let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_CONTEXT_COPY_PATH as usize, w!("フルパスをコピー(&C)"));
-                let _ = SetMenuDefaultItem(h_popup_menu, IDM_CONTEXT_OPEN as u32, 0);
-
-                let mut pt = item_activate.ptAction;
-                let _ = ClientToScreen(state.listview_hwnd, &mut pt);
-
-                // コンパイルエラーを修正: 5番目の引数は Option<i32> 型であるため Some(0) を渡す
-                let cmd = TrackPopupMenu(h_popup_menu, TPM_LEFTALIGN | TPM_RIGHTBUTTON | TPM_RETURNCMD, pt.x, pt.y, Some(0), window, None);
-
-                if cmd.as_bool() {
-                    SendMessageW(window, WM_COMMAND, Some(WPARAM(cmd.0 as usize)), Some(LPARAM(item_index as isize)));
+            match show_shell_context_menu(window, state.listview_hwnd, &full_path, point) {
+                ShellMenuOutcome::Invoked => {
+                    // 削除・移動・名前の変更で結果が古くなるため検索し直す。フォーカス行はパスで選択し直される
+                    if state.refresh_after_shell_command && state.last_search.is_some() && state.duplicates.is_none() {
+                        perform_search(state, true);
+                    }
+                    return;
                 }
-                let _ = DestroyMenu(h_popup_menu);
+                ShellMenuOutcome::Dismissed => return,
+                ShellMenuOutcome::Unavailable => {}
             }
+            // 切断されたドライブや削除済みのパスなどでPIDLを解決できない場合は独自メニューで代替する
+            flash_status(window, state, "このパスのメニューを取得できません");
         }
+        let is_pinned = state.pinned_paths.contains(&full_path);
+        let can_elevate = !is_folder && can_run_as_admin(&full_path);
+        show_custom_context_menu(window, state, item_index, is_folder, is_pinned, can_elevate, point);
     }
 }
 
+/// 独自のコンテキストメニューを表示し、選ばれたコマンドを WM_COMMAND で送る
+fn show_custom_context_menu(window: HWND, state: &AppState, item_index: i32, is_folder: bool, is_pinned: bool, can_elevate: bool, point: POINT) {
+    unsafe {
+        let h_popup_menu = CreatePopupMenu().unwrap();
+        let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_CONTEXT_OPEN as usize, w!("開く(&O)"));
+        // フォルダは関連付けで開くものではないため「プログラムから開く」を無効にする
+        let open_with_flags = if is_folder { MF_STRING | MF_GRAYED } else { MF_STRING };
+        let _ = AppendMenuW(h_popup_menu, open_with_flags, IDM_CONTEXT_OPEN_WITH as usize, w!("プログラムから開く(&H)..."));
+        if can_elevate {
+            let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_CONTEXT_RUN_AS_ADMIN as usize, w!("管理者として実行(&A)"));
+        }
+        let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_CONTEXT_OPEN_FOLDER as usize, w!("フォルダを開く(&F)"));
+        let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_CONTEXT_OPEN_TERMINAL as usize, w!("ターミナルで開く(&T)"));
+        let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_CONTEXT_COPY_PATH as usize, w!("フルパスをコピー(&C)"));
+        let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_CONTEXT_COPY_RELATIVE_PATH as usize, w!("相対パスをコピー(&E)"));
+        let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_CONTEXT_COPY_MARKDOWN as usize, w!("Markdownでコピー(&M)"));
+        let _ = AppendMenuW(h_popup_menu, MF_SEPARATOR, 0, None);
+        let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_CONTEXT_COPY_TO as usize, w!("フォルダへコピー(&Y)..."));
+        let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_CONTEXT_MOVE_TO as usize, w!("フォルダへ移動(&V)..."));
+        let h_send_to_submenu = CreatePopupMenu().unwrap();
+        for (i, command) in state.send_to_commands.iter().enumerate() {
+            let label_w = str_to_wide(&command.name);
+            let _ = AppendMenuW(h_send_to_submenu, MF_STRING, (IDM_CONTEXT_SEND_TO_FIRST + i as u16) as usize, PCWSTR(label_w.as_ptr()));
+        }
+        if state.send_to_commands.is_empty() {
+            let _ = AppendMenuW(h_send_to_submenu, MF_STRING | MF_GRAYED, 0, w!("(設定ファイルの send_to= で追加)"));
+        }
+        let _ = AppendMenuW(h_popup_menu, MF_POPUP, h_send_to_submenu.0 as usize, w!("送る(&S)"));
+        let _ = AppendMenuW(h_popup_menu, MF_SEPARATOR, 0, None);
+        let pin_label = if is_pinned { w!("ピン留めを外す(&P)") } else { w!("ピン留め(&P)") };
+        let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_CONTEXT_PIN as usize, pin_label);
+        let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_CONTEXT_PROPERTIES as usize, w!("プロパティ(&R)"));
+        // 太字の項目がダブルクリックと同じ動作になるようにする
+        let _ = SetMenuDefaultItem(h_popup_menu, double_click_command(state.double_click_action) as u32, 0);
+
+        let mut pt = point;
+        let _ = ClientToScreen(state.listview_hwnd, &mut pt);
+
+        // コンパイルエラーを修正: 5番目の引数は Option<i32> 型であるため Some(0) を渡す
+        let cmd = TrackPopupMenu(h_popup_menu, TPM_LEFTALIGN | TPM_RIGHTBUTTON | TPM_RETURNCMD, pt.x, pt.y, Some(0), window, None);
+
+        if cmd.as_bool() {
+            SendMessageW(window, WM_COMMAND, Some(WPARAM(cmd.0 as usize)), Some(LPARAM(item_index as isize)));
+        }
+        let _ = DestroyMenu(h_popup_menu);
+    }
+}
+
+/// 管理者として実行できる種類のファイルか（拡張子で判断する）
+fn can_run_as_admin(full_path: &Path) -> bool {
+    full_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| RUN_AS_ADMIN_EXTENSIONS.iter().any(|candidate| candidate.eq_ignore_ascii_case(ext)))
+}
+
 
 // --- UI関連の関数 ---
 
@@ -751,80 +2328,366 @@ fn create_menu(window: HWND) {
     unsafe {
         let h_menu = CreateMenu().unwrap();
         let h_file_submenu = CreatePopupMenu().unwrap();
+        let _ = AppendMenuW(h_file_submenu, MF_STRING, IDM_CONTEXT_COPY_MARKDOWN as usize, w!("結果をMarkdownでコピー(&M)"));
+        let _ = AppendMenuW(h_file_submenu, MF_STRING, IDM_FILE_SETTINGS as usize, w!("設定(&S)..."));
+        let _ = AppendMenuW(h_file_submenu, MF_SEPARATOR, 0, None);
         let _ = AppendMenuW(h_file_submenu, MF_STRING, IDM_FILE_EXIT as usize, w!("終了(&E)\tCtrl+Q"));
         let _ = AppendMenuW(h_menu, MF_POPUP, h_file_submenu.0 as usize, w!("ファイル(&F)"));
 
         let h_search_submenu = CreatePopupMenu().unwrap();
         let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_REGEX as usize, w!("正規表現で検索\tCtrl+R"));
         let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_MIGEMO as usize, w!("Migemoで検索\tCtrl+Shift+R"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_WILDCARD as usize, w!("ワイルドカードで検索"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_CYCLE_MODE as usize, w!("検索モードを切り替え\tCtrl+M"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_NOW as usize, w!("今すぐ検索\tEnter"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_RESTORE_QUERY as usize, w!("直前の検索語に戻す\tEsc"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_LIVE as usize, w!("入力中に検索(&I)"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_LITERAL as usize, w!("通常検索で記号をそのまま検索(&T)"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_DIACRITICS as usize, w!("アクセント記号を区別(&A)"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_MIGEMO_MATCH_PATH as usize, w!("Migemoでパス全体を検索(&P)"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_GOTO as usize, w!("入力したパスへ移動(&G)\tCtrl+G"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_FOCUS_EDIT as usize, w!("検索ボックスへ移動(&B)\tCtrl+L"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_NARROW as usize, w!("結果内を絞り込む(&N)\tCtrl+F"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_CLIPBOARD as usize, w!("コピーした文字列で検索(&V)"));
+        let _ = AppendMenuW(h_search_submenu, MF_SEPARATOR, 0, None);
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_SCOPE as usize, w!("検索範囲を選択(&D)..."));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_SCOPE_EXPLORER as usize, w!("エクスプローラのフォルダを検索範囲に(&W)\tCtrl+E"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_SCOPE_UP as usize, w!("検索範囲を親フォルダへ(&U)\tAlt+↑"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_SCOPE_CLEAR as usize, w!("検索範囲をクリア(&L)"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_EXCLUDES as usize, w!("除外フォルダの編集(&X)..."));
+        let h_kind_submenu = CreatePopupMenu().unwrap();
+        for kind in ItemKind::ALL {
+            let label_w = str_to_wide(kind.label());
+            let _ = AppendMenuW(h_kind_submenu, MF_STRING, item_kind_menu_id(kind) as usize, PCWSTR(label_w.as_ptr()));
+        }
+        let _ = AppendMenuW(h_search_submenu, MF_POPUP, h_kind_submenu.0 as usize, w!("種類で絞り込み(&K)"));
+        let h_auto_refresh_submenu = CreatePopupMenu().unwrap();
+        for (i, secs) in AUTO_REFRESH_CHOICES.into_iter().enumerate() {
+            let label = match secs {
+                Some(secs) => format!("{}秒ごと", secs),
+                None => "オフ(&O)".to_string(),
+            };
+            let label_w = str_to_wide(&label);
+            let _ = AppendMenuW(h_auto_refresh_submenu, MF_STRING, (IDM_SEARCH_AUTO_REFRESH_FIRST + i as u16) as usize, PCWSTR(label_w.as_ptr()));
+        }
+        let _ = AppendMenuW(h_search_submenu, MF_POPUP, h_auto_refresh_submenu.0 as usize, w!("結果を自動更新(&R)"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_REFRESH_AFTER_SHELL as usize, w!("シェルのメニューの実行後に再検索(&H)"));
+        let _ = AppendMenuW(h_search_submenu, MF_SEPARATOR, 0, None);
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_SHOW_QUERY as usize, w!("クエリを表示(&Q)..."));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_COPY_QUERY as usize, w!("クエリをコピー(&C)"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_OPEN_IN_EVERYTHING as usize, w!("Everythingで開く(&E)"));
+        let _ = AppendMenuW(h_search_submenu, MF_SEPARATOR, 0, None);
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_DUPLICATES as usize, w!("重複を検索(&U)"));
+        let _ = AppendMenuW(h_search_submenu, MF_STRING, IDM_SEARCH_DUPLICATES_BY_NAME as usize, w!("重複を検索 (名前も一致)"));
         let _ = AppendMenuW(h_menu, MF_POPUP, h_search_submenu.0 as usize, w!("検索(&S)"));
+
+        let h_sort_submenu = CreatePopupMenu().unwrap();
+        for key in SortKey::ALL {
+            let label_w = str_to_wide(key.label());
+            let _ = AppendMenuW(h_sort_submenu, MF_STRING, sort_menu_id(key) as usize, PCWSTR(label_w.as_ptr()));
+        }
+        let _ = AppendMenuW(h_sort_submenu, MF_SEPARATOR, 0, None);
+        let _ = AppendMenuW(h_sort_submenu, MF_STRING, IDM_VIEW_SORT_DESCENDING as usize, w!("降順(&D)"));
+        let h_secondary_sort_submenu = CreatePopupMenu().unwrap();
+        let _ = AppendMenuW(h_secondary_sort_submenu, MF_STRING, IDM_VIEW_SECONDARY_SORT_FIRST as usize, w!("なし(&N)"));
+        for key in SortKey::SECONDARY {
+            let label_w = str_to_wide(key.label());
+            let _ = AppendMenuW(h_secondary_sort_submenu, MF_STRING, secondary_sort_menu_id(Some(key)) as usize, PCWSTR(label_w.as_ptr()));
+        }
+        let _ = AppendMenuW(h_sort_submenu, MF_POPUP, h_secondary_sort_submenu.0 as usize, w!("同じ値の並べ替え(&T)"));
+        let h_column_submenu = CreatePopupMenu().unwrap();
+        let _ = AppendMenuW(h_column_submenu, MF_STRING, IDM_VIEW_COLUMN_CREATED as usize, w!("作成日時(&C)"));
+        let _ = AppendMenuW(h_column_submenu, MF_STRING, IDM_VIEW_COLUMN_TYPE as usize, w!("種類(&T)"));
+        let _ = AppendMenuW(h_column_submenu, MF_SEPARATOR, 0, None);
+        let _ = AppendMenuW(h_column_submenu, MF_STRING, IDM_VIEW_COMPACT_ROWS as usize, w!("名前とフォルダを1列に表示(&O)"));
+        let _ = AppendMenuW(h_column_submenu, MF_SEPARATOR, 0, None);
+        let _ = AppendMenuW(h_column_submenu, MF_STRING, IDM_VIEW_UNITS_BINARY as usize, w!("サイズを KiB (1024 バイト) で表示(&B)"));
+        let _ = AppendMenuW(h_column_submenu, MF_STRING, IDM_VIEW_UNITS_DECIMAL as usize, w!("サイズを KB (1000 バイト) で表示(&D)"));
+        let h_view_submenu = CreatePopupMenu().unwrap();
+        let _ = AppendMenuW(h_view_submenu, MF_POPUP, h_sort_submenu.0 as usize, w!("並べ替え(&S)"));
+        let _ = AppendMenuW(h_view_submenu, MF_POPUP, h_column_submenu.0 as usize, w!("列(&L)"));
+        let _ = AppendMenuW(h_view_submenu, MF_STRING, IDM_VIEW_GROUP_BY_FOLDER as usize, w!("フォルダごとにまとめて表示(&G)"));
+        let _ = AppendMenuW(h_view_submenu, MF_STRING, IDM_VIEW_STRIPED_ROWS as usize, w!("1行おきに色を付ける(&Z)"));
+        let _ = AppendMenuW(h_view_submenu, MF_STRING, IDM_VIEW_HIGHLIGHT_PATH as usize, w!("フォルダ列の一致箇所を強調(&H)"));
+        let h_path_ellipsis_submenu = CreatePopupMenu().unwrap();
+        for (i, position) in EllipsisPosition::ALL.into_iter().enumerate() {
+            let label_w = str_to_wide(position.label());
+            let _ = AppendMenuW(h_path_ellipsis_submenu, MF_STRING, (IDM_VIEW_PATH_ELLIPSIS_FIRST + i as u16) as usize, PCWSTR(label_w.as_ptr()));
+        }
+        let _ = AppendMenuW(h_view_submenu, MF_POPUP, h_path_ellipsis_submenu.0 as usize, w!("フォルダ列の省略位置(&E)"));
+        let _ = AppendMenuW(h_view_submenu, MF_STRING, IDM_VIEW_ACCENT_HIGHLIGHT as usize, w!("一致箇所をアクセントカラーで強調(&C)"));
+        let _ = AppendMenuW(h_view_submenu, MF_STRING, IDM_VIEW_PREVIEW as usize, w!("プレビュー欄(&P)"));
+        let _ = AppendMenuW(h_view_submenu, MF_STRING, IDM_VIEW_PREVIEW_CONTENT as usize, w!("プレビュー欄にファイルの内容を表示(&W)"));
+        let _ = AppendMenuW(h_view_submenu, MF_SEPARATOR, 0, None);
+        let _ = AppendMenuW(h_view_submenu, MF_STRING, IDM_VIEW_ALWAYS_ON_TOP as usize, w!("最前面に表示(&T)"));
+        let _ = AppendMenuW(h_view_submenu, MF_STRING, IDM_VIEW_MINIMIZE_TO_TRAY as usize, w!("最小化時に通知領域へ格納(&N)"));
+        let h_double_click_submenu = CreatePopupMenu().unwrap();
+        for action in DoubleClickAction::ALL {
+            let label_w = str_to_wide(action.label());
+            let _ = AppendMenuW(h_double_click_submenu, MF_STRING, double_click_menu_id(action) as usize, PCWSTR(label_w.as_ptr()));
+        }
+        let _ = AppendMenuW(h_double_click_submenu, MF_SEPARATOR, 0, None);
+        for (i, action) in FolderDoubleClick::ALL.into_iter().enumerate() {
+            let label_w = str_to_wide(action.label());
+            let _ = AppendMenuW(h_double_click_submenu, MF_STRING, (IDM_VIEW_FOLDER_DOUBLE_CLICK_FIRST + i as u16) as usize, PCWSTR(label_w.as_ptr()));
+        }
+        let _ = AppendMenuW(h_view_submenu, MF_POPUP, h_double_click_submenu.0 as usize, w!("ダブルクリックの動作(&B)"));
+        let _ = AppendMenuW(h_menu, MF_POPUP, h_view_submenu.0 as usize, w!("表示(&V)"));
         let _ = SetMenu(window, Some(h_menu));
     }
 }
 
 /// すべてのUIコントロールを作成する（DPI対応）
 fn create_controls(window: HWND, instance: HINSTANCE, state: &mut AppState) {
-    let scale = state.scale_factor;
-    let font_height = (-12.0 * scale) as i32;
-    
-    let h_font = unsafe {
-        CreateFontW(font_height, 0, 0, 0, FW_NORMAL.0 as i32, 0, 0, 0, DEFAULT_CHARSET, OUT_DEFAULT_PRECIS, CLIP_DEFAULT_PRECIS, DEFAULT_QUALITY, (FF_DONTCARE.0 | VARIABLE_PITCH.0) as u32, w!("Segoe UI"))
-    };
 
     unsafe {
-        state.status_hwnd = CreateWindowExW(WINDOW_EX_STYLE::default(), w!("STATIC"), w!("Ready"), WS_CHILD | WS_VISIBLE, 0, 0, 0, 0, Some(window), None, Some(instance), None).unwrap();
+        // タブ移動の順序は作成順（Zオーダー）で決まる: 入力 → 上へ → Dir → ▾ → 種類 → * → RE → Mi → Live → Shell Menu → 絞り込み → リスト
+        // ステータスバーは下端全体を使うため、Shell Menuはツールバーの右端に置く
         state.edit_hwnd = CreateWindowExW(WS_EX_CLIENTEDGE, w!("EDIT"), w!(""), WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(ES_LEFT as u32 | ES_AUTOHSCROLL as u32), 0, 0, 0, 0, Some(window), Some(HMENU(EDIT_ID as isize as *mut c_void)), Some(instance), None).unwrap();
+        state.up_button_hwnd = CreateWindowExW(WINDOW_EX_STYLE::default(), w!("BUTTON"), w!("上へ"), WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_PUSHBUTTON as u32), 0, 0, 0, 0, Some(window), Some(HMENU(UP_BUTTON_ID as isize as *mut c_void)), Some(instance), None).unwrap();
+        state.scope_button_hwnd = CreateWindowExW(WINDOW_EX_STYLE::default(), w!("BUTTON"), w!("Dir"), WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_PUSHBUTTON as u32), 0, 0, 0, 0, Some(window), Some(HMENU(SCOPE_BUTTON_ID as isize as *mut c_void)), Some(instance), None).unwrap();
+        state.recent_scopes_button_hwnd = CreateWindowExW(WINDOW_EX_STYLE::default(), w!("BUTTON"), w!("▾"), WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_PUSHBUTTON as u32), 0, 0, 0, 0, Some(window), Some(HMENU(RECENT_SCOPES_BUTTON_ID as isize as *mut c_void)), Some(instance), None).unwrap();
+        state.item_kind_button_hwnd = CreateWindowExW(WINDOW_EX_STYLE::default(), w!("BUTTON"), w!("All"), WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_PUSHBUTTON as u32), 0, 0, 0, 0, Some(window), Some(HMENU(ITEM_KIND_BUTTON_ID as isize as *mut c_void)), Some(instance), None).unwrap();
+        state.wc_button_hwnd = CreateWindowExW(WINDOW_EX_STYLE::default(), w!("BUTTON"), w!("*"), WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_PUSHBUTTON as u32), 0, 0, 0, 0, Some(window), Some(HMENU(WC_BUTTON_ID as isize as *mut c_void)), Some(instance), None).unwrap();
         state.re_button_hwnd = CreateWindowExW(WINDOW_EX_STYLE::default(), w!("BUTTON"), w!("RE"), WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_PUSHBUTTON as u32), 0, 0, 0, 0, Some(window), Some(HMENU(RE_BUTTON_ID as isize as *mut c_void)), Some(instance), None).unwrap();
         state.mi_button_hwnd = CreateWindowExW(WINDOW_EX_STYLE::default(), w!("BUTTON"), w!("Mi"), WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_PUSHBUTTON as u32), 0, 0, 0, 0, Some(window), Some(HMENU(MI_BUTTON_ID as isize as *mut c_void)), Some(instance), None).unwrap();
-        state.listview_hwnd = CreateWindowExW(WINDOW_EX_STYLE::default(), w!("SysListView32"), w!(""), WS_CHILD | WS_VISIBLE | WS_BORDER | WS_TABSTOP | WS_VSCROLL | WS_HSCROLL | WINDOW_STYLE(LVS_REPORT as u32 | LVS_OWNERDATA as u32), 0, 0, 0, 0, Some(window), None, Some(instance), None).unwrap();
-        
+
+        // ライブ検索切り替えボタン
+        state.live_search_toggle_hwnd = CreateWindowExW(
+            WINDOW_EX_STYLE::default(), w!("BUTTON"), w!("Live"),
+            WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_CHECKBOX as u32),
+            0, 0, 0, 0, Some(window), Some(HMENU(LIVE_SEARCH_TOGGLE_ID as isize as *mut c_void)), Some(instance), None,
+        ).unwrap();
         // シェルコンテキストメニュー切り替えボタン
         state.shell_context_toggle_hwnd = CreateWindowExW(
             WINDOW_EX_STYLE::default(), w!("BUTTON"), w!("Shell Menu"),
             WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_AUTOCHECKBOX as u32),
             0, 0, 0, 0, Some(window), Some(HMENU(SHELL_CONTEXT_TOGGLE_ID as isize as *mut c_void)), Some(instance), None,
         ).unwrap();
+        // 結果内の絞り込み欄は検索ボックスの下の行に置き、表示するかはlayout_controlsで切り替える
+        state.narrow_hwnd = CreateWindowExW(WS_EX_CLIENTEDGE, w!("EDIT"), w!(""), WS_CHILD | WS_TABSTOP | WINDOW_STYLE(ES_LEFT as u32 | ES_AUTOHSCROLL as u32), 0, 0, 0, 0, Some(window), Some(HMENU(NARROW_EDIT_ID as isize as *mut c_void)), Some(instance), None).unwrap();
+        SendMessageW(state.narrow_hwnd, EM_SETCUEBANNER, Some(WPARAM(1)), Some(LPARAM(w!("結果内を絞り込み (Esc で閉じる)").as_ptr() as isize)));
+        // 設定するのはシステムイメージリストなので、リストビューの破棄と一緒に破棄されないよう共有の指定にする
+        state.listview_hwnd = CreateWindowExW(WINDOW_EX_STYLE::default(), w!("SysListView32"), w!(""), WS_CHILD | WS_VISIBLE | WS_BORDER | WS_TABSTOP | WS_VSCROLL | WS_HSCROLL | WINDOW_STYLE(LVS_REPORT as u32 | LVS_OWNERDATA as u32 | LVS_SHAREIMAGELISTS as u32), 0, 0, 0, 0, Some(window), None, Some(instance), None).unwrap();
+        // プレビュー欄は読み取り専用のエディットにして、長いパスを折り返して表示し選択・コピーもできるようにする
+        state.preview_hwnd = CreateWindowExW(WS_EX_CLIENTEDGE, w!("EDIT"), w!(""), WS_CHILD | WS_VSCROLL | WINDOW_STYLE(ES_MULTILINE as u32 | ES_READONLY as u32 | ES_AUTOVSCROLL as u32), 0, 0, 0, 0, Some(window), None, Some(instance), None).unwrap();
+        state.status_hwnd = CreateWindowExW(WINDOW_EX_STYLE::default(), STATUSCLASSNAMEW, w!(""), WS_CHILD | WS_VISIBLE | WINDOW_STYLE(SBARS_SIZEGRIP), 0, 0, 0, 0, Some(window), None, Some(instance), None).unwrap();
+
+        // Enterキーでの即時検索などのためにエディットボックスをサブクラス化する
+        let _ = SetWindowSubclass(state.edit_hwnd, Some(edit_subclass_proc), EDIT_SUBCLASS_ID, 0);
+        let _ = SetWindowSubclass(state.narrow_hwnd, Some(edit_subclass_proc), EDIT_SUBCLASS_ID, 0);
+        // ヘッダーのカスタムドローをメインウィンドウで扱うためにリストビューをサブクラス化する
+        let _ = SetWindowSubclass(state.listview_hwnd, Some(listview_subclass_proc), LISTVIEW_SUBCLASS_ID, 0);
+
+        // モードの切り替えボタンに説明とショートカットのツールチップを付ける
+        let tooltip_hwnd = CreateWindowExW(WS_EX_TOPMOST, TOOLTIPS_CLASSW, None, WS_POPUP | WINDOW_STYLE(TTS_ALWAYSTIP), CW_USEDEFAULT, CW_USEDEFAULT, CW_USEDEFAULT, CW_USEDEFAULT, Some(window), None, Some(instance), None);
+        if let Ok(tooltip_hwnd) = tooltip_hwnd {
+            add_tooltip(tooltip_hwnd, window, state.re_button_hwnd, "正規表現検索 (Ctrl+R)");
+            add_tooltip(tooltip_hwnd, window, state.mi_button_hwnd, "Migemo検索 (Ctrl+Shift+R)");
+            add_tooltip(tooltip_hwnd, window, state.shell_context_toggle_hwnd, "シェルのコンテキストメニューを使用");
+            add_tooltip(tooltip_hwnd, window, state.up_button_hwnd, "検索範囲を親フォルダへ (Alt+↑)");
+        }
+
+
+        state.h_font = create_ui_font(state.scale_factor);
+        apply_font(state);
+    }
+}
+
+/// コントロールにツールチップを登録する（テキストはツールチップ側にコピーされる）
+fn add_tooltip(tooltip_hwnd: HWND, owner: HWND, control: HWND, text: &str) {
+    let mut text_w = str_to_wide(text);
+    let info = TTTOOLINFOW {
+        cbSize: std::mem::size_of::<TTTOOLINFOW>() as u32,
+        uFlags: TTF_IDISHWND | TTF_SUBCLASS,
+        hwnd: owner,
+        uId: control.0 as usize,
+        lpszText: PWSTR(text_w.as_mut_ptr()),
+        ..Default::default()
+    };
+    unsafe { SendMessageW(tooltip_hwnd, TTM_ADDTOOLW, Some(WPARAM(0)), Some(LPARAM(&info as *const _ as isize))); }
+}
+
+/// UIフォントを現在のスケールで作成する
+fn create_ui_font(scale: f32) -> HFONT {
+    let font_height = (-12.0 * scale) as i32;
+    unsafe {
+        CreateFontW(font_height, 0, 0, 0, FW_NORMAL.0 as i32, 0, 0, 0, DEFAULT_CHARSET, OUT_DEFAULT_PRECIS, CLIP_DEFAULT_PRECIS, DEFAULT_QUALITY, (FF_DONTCARE.0 | VARIABLE_PITCH.0) as u32, w!("Segoe UI"))
+    }
+}
 
+/// 現在のフォントをすべてのコントロールに設定する
+fn apply_font(state: &AppState) {
+    if state.h_font.is_invalid() { return; }
+    let controls = [
+        state.status_hwnd, state.edit_hwnd, state.narrow_hwnd, state.re_button_hwnd, state.mi_button_hwnd,
+        state.scope_button_hwnd, state.wc_button_hwnd, state.listview_hwnd, state.shell_context_toggle_hwnd,
+        state.live_search_toggle_hwnd, state.item_kind_button_hwnd, state.recent_scopes_button_hwnd, state.up_button_hwnd, state.preview_hwnd,
+    ];
+    for hwnd in controls {
+        unsafe { SendMessageW(hwnd, WM_SETFONT, Some(WPARAM(state.h_font.0 as usize)), Some(LPARAM(1))) };
+    }
+}
+
+/// 現在のDPIに合ったシステムイメージリストを取得し、リストビューに設定する
+fn refresh_imagelist(state: &mut AppState) {
+    // 高DPIでは小アイコンを拡大表示するとぼやけるため大きいアイコンを使う
+    let image_list_size = if state.scale_factor >= 1.75 { SHIL_LARGE } else { SHIL_SMALL };
+    if let Ok(image_list) = unsafe { SHGetImageList::<IImageList>(image_list_size as i32) } {
+        // システムイメージリストはシステムが所有するため、参照を解放しても破棄されない
+        state.himagelist = HIMAGELIST(image_list.as_raw() as isize);
+    }
 
-        if !h_font.is_invalid() {
-            SendMessageW(state.status_hwnd, WM_SETFONT, Some(WPARAM(h_font.0 as usize)), Some(LPARAM(1)));
-            SendMessageW(state.edit_hwnd, WM_SETFONT, Some(WPARAM(h_font.0 as usize)), Some(LPARAM(1)));
-            SendMessageW(state.re_button_hwnd, WM_SETFONT, Some(WPARAM(h_font.0 as usize)), Some(LPARAM(1)));
-            SendMessageW(state.mi_button_hwnd, WM_SETFONT, Some(WPARAM(h_font.0 as usize)), Some(LPARAM(1)));
-            SendMessageW(state.listview_hwnd, WM_SETFONT, Some(WPARAM(h_font.0 as usize)), Some(LPARAM(1)));
-            SendMessageW(state.shell_context_toggle_hwnd, WM_SETFONT, Some(WPARAM(h_font.0 as usize)), Some(LPARAM(1)));
+    if state.himagelist.0 != 0 {
+        let (mut cx, mut cy) = (0, 0);
+        if unsafe { ImageList_GetIconSize(state.himagelist, &mut cx, &mut cy) }.as_bool() {
+            state.icon_size = cy;
         }
+        unsafe { SendMessageW(state.listview_hwnd, LVM_SETIMAGELIST, Some(WPARAM(LVSIL_SMALL as usize)), Some(LPARAM(state.himagelist.0))) };
+    }
+}
+
+/// 最前面表示の設定をウィンドウに反映する
+fn apply_always_on_top(state: &AppState) {
+    let insert_after = if state.always_on_top { HWND_TOPMOST } else { HWND_NOTOPMOST };
+    let _ = unsafe { SetWindowPos(state.main_hwnd, Some(insert_after), 0, 0, 0, 0, SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE) };
+}
+
+/// ダブルクリックの動作に対応するコンテキストメニューのコマンドID
+fn double_click_command(action: DoubleClickAction) -> u16 {
+    match action {
+        DoubleClickAction::Open => IDM_CONTEXT_OPEN,
+        DoubleClickAction::Reveal => IDM_CONTEXT_OPEN_FOLDER,
+        DoubleClickAction::Properties => IDM_CONTEXT_PROPERTIES,
+    }
+}
+
+/// システムのハイコントラストが有効か
+fn is_high_contrast() -> bool {
+    let mut high_contrast = HIGHCONTRASTW { cbSize: std::mem::size_of::<HIGHCONTRASTW>() as u32, ..Default::default() };
+    let ok = unsafe {
+        SystemParametersInfoW(SPI_GETHIGHCONTRAST, high_contrast.cbSize, Some(&mut high_contrast as *mut _ as *mut c_void), SYSTEM_PARAMETERS_INFO_UPDATE_FLAGS(0))
+    };
+    ok.is_ok() && (high_contrast.dwFlags & HCF_HIGHCONTRASTON).0 != 0
+}
+
+/// 行の見た目に関する拡張スタイルを設定する
+/// 1行おきの塗り分けが有効な場合は罫線を消す（背景色の差で行を区別できるため）
+fn apply_row_style(state: &AppState) {
+    let mask = LVS_EX_FULLROWSELECT | LVS_EX_GRIDLINES;
+    let ex_style = if state.striped_rows { LVS_EX_FULLROWSELECT } else { mask };
+    unsafe {
+        SendMessageW(state.listview_hwnd, LVM_SETEXTENDEDLISTVIEWSTYLE, Some(WPARAM(mask as usize)), Some(LPARAM(ex_style as isize)));
+        let _ = InvalidateRect(Some(state.listview_hwnd), None, true);
+    }
+}
+
+/// 選択されていない行の背景色
+/// 塗り分けが有効な場合、奇数行はウィンドウの背景色を文字色へ少し寄せた色にする
+/// ハイコントラストでは文字が読みにくくならないよう、塗り分けずにウィンドウの背景色を使う
+fn row_background_color(state: &AppState, item_index: usize) -> u32 {
+    let window = unsafe { GetSysColor(COLOR_WINDOW) };
+    // グループの見出しは縞模様より少し濃く塗り、検索結果の行と見分けられるようにする
+    if state.group_header_at(item_index).is_some() && !state.high_contrast {
+        let text = unsafe { GetSysColor(COLOR_WINDOWTEXT) };
+        return blend_color(window, text, 2);
     }
+    if !state.striped_rows || state.high_contrast || item_index % 2 == 0 { return window; }
+    let text = unsafe { GetSysColor(COLOR_WINDOWTEXT) };
+    blend_color(window, text, 1)
+}
+
+/// COLORREFの各チャンネル（R, G, B）を `from` から `to` へ `sixteenths`/16 だけ近づけた色
+fn blend_color(from: u32, to: u32, sixteenths: i32) -> u32 {
+    (0..3).fold(0, |color, channel| {
+        let shift = channel * 8;
+        let from = ((from >> shift) & 0xFF) as i32;
+        let to = ((to >> shift) & 0xFF) as i32;
+        color | (((from + (to - from) * sixteenths / 16) as u32) << shift)
+    })
+}
+
+/// 背景色の上で読みやすい文字色（明るい背景には黒、暗い背景には白）
+fn contrasting_text_color(back: u32) -> u32 {
+    let (r, g, b) = (back & 0xFF, (back >> 8) & 0xFF, (back >> 16) & 0xFF);
+    let luminance = (299 * r + 587 * g + 114 * b) / 1000;
+    if luminance >= 128 { 0x000000 } else { 0xFFFFFF }
+}
+
+/// Windowsのアクセントカラー（DWMの配色、COLORREF）。読み取れなければ None
+fn accent_color() -> Option<u32> {
+    let mut argb = 0u32;
+    let mut opaque_blend = BOOL(0);
+    unsafe { DwmGetColorizationColor(&mut argb, &mut opaque_blend) }.ok()?;
+    // 0xAARRGGBB を 0x00BBGGRR に並べ替える
+    Some(((argb & 0xFF) << 16) | (argb & 0xFF00) | ((argb >> 16) & 0xFF))
+}
+
+/// 設定に合わせて強調に使うアクセントカラーを取り直す
+fn refresh_accent_color(state: &mut AppState) {
+    state.accent_color = if state.accent_highlight { accent_color() } else { None };
 }
 
 /// リストビューの初期設定（カラム、拡張スタイル、イメージリスト）（DPI対応）
 fn setup_listview(state: &mut AppState) {
-    unsafe {
-        let ex_style = LVS_EX_FULLROWSELECT | LVS_EX_GRIDLINES;
-        SendMessageW(state.listview_hwnd, LVM_SETEXTENDEDLISTVIEWSTYLE, Some(WPARAM(ex_style as usize)), Some(LPARAM(ex_style as isize)));
+    apply_row_style(state);
+    refresh_imagelist(state);
+
+    for sub_item in [COLUMN_NAME, COLUMN_PATH, COLUMN_SIZE, COLUMN_MODIFIED] {
+        insert_column(state, sub_item);
+    }
+    set_column_visible(state, COLUMN_CREATED, state.show_created_column);
+    set_column_visible(state, COLUMN_TYPE, state.show_type_column);
+    apply_compact_rows(state);
+}
 
-        let mut shfi: SHFILEINFOW = std::mem::zeroed();
-        state.himagelist = HIMAGELIST(SHGetFileInfoW(w!(""), FILE_ATTRIBUTE_NORMAL, Some(&mut shfi as *mut _), std::mem::size_of::<SHFILEINFOW>() as u32, SHGFI_USEFILEATTRIBUTES | SHGFI_SYSICONINDEX | SHGFI_SMALLICON) as isize);
+/// 1列表示の切り替えに合わせて「フォルダ」列を出し入れし、「名前」列の見出しを変える
+fn apply_compact_rows(state: &AppState) {
+    set_column_visible(state, COLUMN_PATH, !state.compact_rows);
+    let Some(position) = column_positions(state).iter().position(|&s| s == COLUMN_NAME) else { return };
+    let title = if state.compact_rows { format!("{}{}{}", COLUMNS[COLUMN_NAME].0, NAME_FOLDER_SEPARATOR, COLUMNS[COLUMN_PATH].0) } else { COLUMNS[COLUMN_NAME].0.to_string() };
+    let title_w = str_to_wide(&title);
+    let col = LVCOLUMNW { mask: LVCF_TEXT, pszText: PWSTR(title_w.as_ptr() as *mut _), ..Default::default() };
+    unsafe { SendMessageW(state.listview_hwnd, LVM_SETCOLUMNW, Some(WPARAM(position)), Some(LPARAM(&col as *const _ as isize))) };
+}
 
-        if state.himagelist.0 != 0 {
-            SendMessageW(state.listview_hwnd, LVM_SETIMAGELIST, Some(WPARAM(LVSIL_SMALL as usize)), Some(LPARAM(state.himagelist.0)));
-        }
+/// カラムを追加する（表示中のカラムはサブアイテム番号の順に並ぶ）
+fn insert_column(state: &AppState, sub_item: usize) {
+    let (title, width) = COLUMNS[sub_item];
+    let title_w = str_to_wide(title);
+    let mut col = LVCOLUMNW {
+        mask: LVCF_TEXT | LVCF_WIDTH | LVCF_SUBITEM,
+        cx: (width * state.scale_factor) as i32,
+        pszText: PWSTR(title_w.as_ptr() as *mut _),
+        iSubItem: sub_item as i32,
+        ..Default::default()
+    };
+    if sub_item == COLUMN_SIZE { col.mask |= LVCF_FMT; col.fmt = LVCFMT_RIGHT; }
+    let position = column_positions(state).iter().filter(|&&s| s < sub_item).count();
+    unsafe { SendMessageW(state.listview_hwnd, LVM_INSERTCOLUMNW, Some(WPARAM(position)), Some(LPARAM(&mut col as *mut _ as isize))) };
+}
 
-        let scale = state.scale_factor;
-        let columns = [
-            (w!("名前"), (300.0 * scale) as i32),
-            (w!("フォルダ"), (300.0 * scale) as i32),
-            (w!("サイズ"), (80.0 * scale) as i32),
-            (w!("更新日時"), (150.0 * scale) as i32)
-        ];
-        
-        for (i, (text, width)) in columns.iter().enumerate() {
-            let mut col = LVCOLUMNW {
-                mask: LVCF_TEXT | LVCF_WIDTH, cx: *width, pszText: PWSTR(text.as_ptr() as *mut _), ..Default::default()
-            };
-            if i == 2 { col.mask |= LVCF_FMT; col.fmt = LVCFMT_RIGHT; }
-            SendMessageW(state.listview_hwnd, LVM_INSERTCOLUMNW, Some(WPARAM(i)), Some(LPARAM(&mut col as *mut _ as isize)));
-        }
+/// 表示中のカラムのサブアイテム番号を左から順に返す
+fn column_positions(state: &AppState) -> Vec<usize> {
+    let mut sub_items = Vec::new();
+    loop {
+        let mut col = LVCOLUMNW { mask: LVCF_SUBITEM, ..Default::default() };
+        let found = unsafe { SendMessageW(state.listview_hwnd, LVM_GETCOLUMNW, Some(WPARAM(sub_items.len())), Some(LPARAM(&mut col as *mut _ as isize))) };
+        if found.0 == 0 { break; }
+        sub_items.push(col.iSubItem as usize);
+    }
+    sub_items
+}
+
+/// 任意表示のカラムを表示/非表示にする
+fn set_column_visible(state: &AppState, sub_item: usize, visible: bool) {
+    let position = column_positions(state).iter().position(|&s| s == sub_item);
+    match (position, visible) {
+        (None, true) => insert_column(state, sub_item),
+        (Some(position), false) => unsafe {
+            SendMessageW(state.listview_hwnd, LVM_DELETECOLUMN, Some(WPARAM(position)), None);
+        },
+        _ => {}
     }
 }
 
@@ -832,211 +2695,2203 @@ fn setup_listview(state: &mut AppState) {
 fn update_ui_states(state: &AppState) {
     unsafe {
         let h_menu = GetMenu(state.main_hwnd);
+        let mode_controls = [
+            (state.search_mode.regex_input(), IDM_SEARCH_REGEX, state.re_button_hwnd),
+            (state.search_mode.uses_migemo(), IDM_SEARCH_MIGEMO, state.mi_button_hwnd),
+            (state.search_mode == SearchMode::Wildcard, IDM_SEARCH_WILDCARD, state.wc_button_hwnd),
+        ];
+        for (is_active, menu_id, button_hwnd) in mode_controls {
+            if h_menu.0 != std::ptr::null_mut() {
+                let flag = if is_active { MF_CHECKED } else { MF_UNCHECKED };
+                let _ = CheckMenuItem(h_menu, menu_id as u32, flag.0);
+            }
+
+            let style = if is_active { BS_DEFPUSHBUTTON } else { BS_PUSHBUTTON };
+            SetWindowLongW(button_hwnd, GWL_STYLE, (GetWindowLongW(button_hwnd, GWL_STYLE) & !(BS_DEFPUSHBUTTON as i32)) | style as i32);
+            let _ = InvalidateRect(Some(button_hwnd), None, true);
+        }
+
         if h_menu.0 != std::ptr::null_mut() {
-            let re_flag = if state.regex_enabled { MF_CHECKED } else { MF_UNCHECKED };
-            let _ = CheckMenuItem(h_menu, IDM_SEARCH_REGEX as u32, re_flag.0);
-            let mi_flag = if state.migemo_enabled { MF_CHECKED } else { MF_UNCHECKED };
-            let _ = CheckMenuItem(h_menu, IDM_SEARCH_MIGEMO as u32, mi_flag.0);
+            let last_sort_id = IDM_VIEW_SORT_FIRST + SortKey::ALL.len() as u16 - 1;
+            let _ = CheckMenuRadioItem(h_menu, IDM_VIEW_SORT_FIRST as u32, last_sort_id as u32, sort_menu_id(state.sort_key) as u32, MF_BYCOMMAND.0);
+            let units_id = if state.binary_units { IDM_VIEW_UNITS_BINARY } else { IDM_VIEW_UNITS_DECIMAL };
+            let _ = CheckMenuRadioItem(h_menu, IDM_VIEW_UNITS_BINARY as u32, IDM_VIEW_UNITS_DECIMAL as u32, units_id as u32, MF_BYCOMMAND.0);
+            let last_secondary_sort_id = IDM_VIEW_SECONDARY_SORT_FIRST + SortKey::SECONDARY.len() as u16;
+            let _ = CheckMenuRadioItem(h_menu, IDM_VIEW_SECONDARY_SORT_FIRST as u32, last_secondary_sort_id as u32, secondary_sort_menu_id(state.secondary_sort) as u32, MF_BYCOMMAND.0);
+            let last_path_ellipsis_id = IDM_VIEW_PATH_ELLIPSIS_FIRST + EllipsisPosition::ALL.len() as u16 - 1;
+            let path_ellipsis_id = IDM_VIEW_PATH_ELLIPSIS_FIRST + EllipsisPosition::ALL.iter().position(|&p| p == state.path_ellipsis).unwrap_or(0) as u16;
+            let _ = CheckMenuRadioItem(h_menu, IDM_VIEW_PATH_ELLIPSIS_FIRST as u32, last_path_ellipsis_id as u32, path_ellipsis_id as u32, MF_BYCOMMAND.0);
+            let last_double_click_id = IDM_VIEW_DOUBLE_CLICK_FIRST + DoubleClickAction::ALL.len() as u16 - 1;
+            let _ = CheckMenuRadioItem(h_menu, IDM_VIEW_DOUBLE_CLICK_FIRST as u32, last_double_click_id as u32, double_click_menu_id(state.double_click_action) as u32, MF_BYCOMMAND.0);
+            let last_folder_double_click_id = IDM_VIEW_FOLDER_DOUBLE_CLICK_FIRST + FolderDoubleClick::ALL.len() as u16 - 1;
+            let folder_double_click_id = IDM_VIEW_FOLDER_DOUBLE_CLICK_FIRST + FolderDoubleClick::ALL.iter().position(|&a| a == state.folder_double_click).unwrap_or(0) as u16;
+            let _ = CheckMenuRadioItem(h_menu, IDM_VIEW_FOLDER_DOUBLE_CLICK_FIRST as u32, last_folder_double_click_id as u32, folder_double_click_id as u32, MF_BYCOMMAND.0);
+            let check_items = [
+                (IDM_SEARCH_LIVE, state.live_search),
+                (IDM_SEARCH_REFRESH_AFTER_SHELL, state.refresh_after_shell_command),
+                (IDM_SEARCH_LITERAL, state.literal_search),
+                (IDM_SEARCH_DIACRITICS, state.match_diacritics),
+                (IDM_SEARCH_MIGEMO_MATCH_PATH, state.migemo_match_path),
+                (IDM_SEARCH_CLIPBOARD, state.clipboard_search),
+                (IDM_VIEW_SORT_DESCENDING, state.sort_descending),
+                (IDM_VIEW_COLUMN_CREATED, state.show_created_column),
+                (IDM_VIEW_COLUMN_TYPE, state.show_type_column),
+                (IDM_VIEW_STRIPED_ROWS, state.striped_rows),
+                (IDM_VIEW_HIGHLIGHT_PATH, state.highlight_path),
+                (IDM_VIEW_COMPACT_ROWS, state.compact_rows),
+                (IDM_VIEW_GROUP_BY_FOLDER, state.group_by_folder),
+                (IDM_VIEW_ACCENT_HIGHLIGHT, state.accent_highlight),
+                (IDM_VIEW_ALWAYS_ON_TOP, state.always_on_top),
+                (IDM_VIEW_MINIMIZE_TO_TRAY, state.minimize_to_tray),
+                (IDM_VIEW_PREVIEW, state.show_preview),
+                (IDM_SEARCH_NARROW, state.show_narrow_box),
+                (IDM_VIEW_PREVIEW_CONTENT, state.preview_content),
+            ];
+            for (menu_id, checked) in check_items {
+                let flag = if checked { MF_CHECKED } else { MF_UNCHECKED };
+                let _ = CheckMenuItem(h_menu, menu_id as u32, flag.0);
+            }
         }
 
-        let re_style = if state.regex_enabled { BS_DEFPUSHBUTTON } else { BS_PUSHBUTTON };
-        SetWindowLongW(state.re_button_hwnd, GWL_STYLE, (GetWindowLongW(state.re_button_hwnd, GWL_STYLE) & !(BS_DEFPUSHBUTTON as i32)) | re_style as i32);
-        let _ = InvalidateRect(Some(state.re_button_hwnd), None, true);
+        if h_menu.0 != std::ptr::null_mut() {
+            let last_kind_id = IDM_SEARCH_KIND_FIRST + ItemKind::ALL.len() as u16 - 1;
+            let _ = CheckMenuRadioItem(h_menu, IDM_SEARCH_KIND_FIRST as u32, last_kind_id as u32, item_kind_menu_id(state.item_kind) as u32, MF_BYCOMMAND.0);
+            // 設定ファイルで一覧にない間隔を指定した場合はどれにもチェックを付けない
+            let last_auto_refresh_id = IDM_SEARCH_AUTO_REFRESH_FIRST + AUTO_REFRESH_CHOICES.len() as u16 - 1;
+            for (i, secs) in AUTO_REFRESH_CHOICES.into_iter().enumerate() {
+                if secs == state.auto_refresh_secs {
+                    let id = IDM_SEARCH_AUTO_REFRESH_FIRST + i as u16;
+                    let _ = CheckMenuRadioItem(h_menu, IDM_SEARCH_AUTO_REFRESH_FIRST as u32, last_auto_refresh_id as u32, id as u32, MF_BYCOMMAND.0);
+                }
+            }
+        }
+        let kind_text_w = str_to_wide(state.item_kind.button_text());
+        let _ = SetWindowTextW(state.item_kind_button_hwnd, PCWSTR(kind_text_w.as_ptr()));
 
-        let mi_style = if state.migemo_enabled { BS_DEFPUSHBUTTON } else { BS_PUSHBUTTON };
-        SetWindowLongW(state.mi_button_hwnd, GWL_STYLE, (GetWindowLongW(state.mi_button_hwnd, GWL_STYLE) & !(BS_DEFPUSHBUTTON as i32)) | mi_style as i32);
-        let _ = InvalidateRect(Some(state.mi_button_hwnd), None, true);
+        let live_check = if state.live_search { BST_CHECKED } else { BST_UNCHECKED };
+        SendMessageW(state.live_search_toggle_hwnd, BM_SETCHECK, Some(WPARAM(live_check.0 as usize)), None);
     }
+    update_status_text(state);
 }
 
 /// ウィンドウリサイズ時にコントロールを再配置する（DPI対応）
-fn layout_controls(width: i32, height: i32, state: &AppState) {
+fn layout_controls(width: i32, height: i32, state: &mut AppState) {
     let scale = state.scale_factor;
     let bar_height = (25.0 * scale) as i32;
-    let status_bar_height = (20.0 * scale) as i32;
     let button_width = (40.0 * scale) as i32;
     let toggle_button_width = (100.0 * scale) as i32;
-    let total_button_width = button_width * 2;
-    let list_y = bar_height;
+    let live_toggle_width = (56.0 * scale) as i32;
+    let kind_button_width = (56.0 * scale) as i32;
+    let dropdown_button_width = (20.0 * scale) as i32;
+    let total_button_width = button_width * 5 + dropdown_button_width + kind_button_width + live_toggle_width + toggle_button_width;
+    let narrow_height = if state.show_narrow_box { bar_height } else { 0 };
+    let list_y = bar_height + narrow_height;
     let toggle_x = width - toggle_button_width;
+    let live_x = toggle_x - live_toggle_width;
+
+    // ステータスバーはWM_SIZEを受けると自分で下端に収まる
+    let mut status_rect = RECT::default();
+    unsafe {
+        SendMessageW(state.status_hwnd, WM_SIZE, None, None);
+        let _ = GetWindowRect(state.status_hwnd, &mut status_rect);
+    }
+    let status_bar_height = status_rect.bottom - status_rect.top;
+    let preview_height = (preview_height(state) as f32 * scale) as i32;
+    let list_height = (height - list_y - status_bar_height - preview_height).max(0);
+    update_status_parts(state, width);
 
     unsafe {
-        let _ = MoveWindow(state.edit_hwnd, 0, 0, width - total_button_width, bar_height, true);
-        let _ = MoveWindow(state.re_button_hwnd, width - total_button_width, 0, button_width, bar_height, true);
-        let _ = MoveWindow(state.mi_button_hwnd, width - button_width, 0, button_width, bar_height, true);
-        let _ = MoveWindow(state.listview_hwnd, 0, list_y, width, height - list_y - status_bar_height, true);
-        let _ = MoveWindow(state.status_hwnd, 0, height - status_bar_height, toggle_x, status_bar_height, true);
-        let _ = MoveWindow(state.shell_context_toggle_hwnd, toggle_x, height - status_bar_height, toggle_button_width, status_bar_height, true);
+        let _ = MoveWindow(state.edit_hwnd, 0, 0, (width - total_button_width).max(0), bar_height, true);
+        let _ = MoveWindow(state.up_button_hwnd, width - total_button_width, 0, button_width, bar_height, true);
+        let _ = MoveWindow(state.scope_button_hwnd, width - total_button_width + button_width, 0, button_width, bar_height, true);
+        let _ = MoveWindow(state.recent_scopes_button_hwnd, width - total_button_width + button_width * 2, 0, dropdown_button_width, bar_height, true);
+        let _ = MoveWindow(state.item_kind_button_hwnd, width - total_button_width + button_width * 2 + dropdown_button_width, 0, kind_button_width, bar_height, true);
+        let _ = MoveWindow(state.wc_button_hwnd, live_x - button_width * 3, 0, button_width, bar_height, true);
+        let _ = MoveWindow(state.re_button_hwnd, live_x - button_width * 2, 0, button_width, bar_height, true);
+        let _ = MoveWindow(state.mi_button_hwnd, live_x - button_width, 0, button_width, bar_height, true);
+        let _ = MoveWindow(state.live_search_toggle_hwnd, live_x + (4.0 * scale) as i32, 0, live_toggle_width, bar_height, true);
+        let _ = MoveWindow(state.shell_context_toggle_hwnd, toggle_x + (4.0 * scale) as i32, 0, toggle_button_width, bar_height, true);
+        let _ = MoveWindow(state.narrow_hwnd, 0, bar_height, width, narrow_height, true);
+        let _ = ShowWindow(state.narrow_hwnd, if state.show_narrow_box { SW_SHOWNA } else { SW_HIDE });
+        let _ = MoveWindow(state.listview_hwnd, 0, list_y, width, list_height, true);
+        let _ = MoveWindow(state.preview_hwnd, 0, list_y + list_height, width, preview_height, true);
+        let _ = ShowWindow(state.preview_hwnd, if state.show_preview { SW_SHOWNA } else { SW_HIDE });
     }
+    update_page_size(state);
 }
 
-// --- 検索関連の関数 ---
+/// リストに見えている行数（一部だけ見える最後の行を含む）に合わせて一度に読み込む件数を更新する
+/// 初回検索で最初の画面が埋まらず、空の行が見えてから読み込まれるのを防ぐ
+fn update_page_size(state: &mut AppState) {
+    let visible_rows = unsafe { SendMessageW(state.listview_hwnd, LVM_GETCOUNTPERPAGE, None, None) }.0.max(0) as usize;
+    state.pages.set_page_size(page_size_for_rows(visible_rows + 1));
+}
 
-/// Migemo辞書を初期化する
-fn init_migemo_dict() -> Option<CompactDictionary> {
-    use std::env;
-    let paths = [
-        PathBuf::from("migemo-compact-dict"),
-        env::current_exe().ok().and_then(|p| p.parent().map(|d| d.join("migemo-compact-dict"))).unwrap_or_default(),
+/// ステータスバーのペインの区切り位置を設定する
+fn update_status_parts(state: &AppState, width: i32) {
+    let scale = state.scale_factor;
+    let count_width = (180.0 * scale) as i32;
+    let selection_width = (120.0 * scale) as i32;
+    let mode_width = (110.0 * scale) as i32;
+    // 各ペインの右端の座標。最後のペインは -1 で残り全体を使う
+    let mut parts = [
+        count_width,
+        count_width + selection_width,
+        count_width + selection_width + mode_width,
+        -1,
     ];
+    for part in parts.iter_mut().take(3) {
+        *part = (*part).min(width);
+    }
+    unsafe {
+        SendMessageW(state.status_hwnd, SB_SETPARTS, Some(WPARAM(parts.len())), Some(LPARAM(parts.as_ptr() as isize)));
+    }
+}
+
+/// フォルダ選択ダイアログを表示し、選択されたフォルダのパスを返す
+fn pick_folder(owner: HWND) -> Option<PathBuf> {
+    unsafe {
+        let dialog: IFileOpenDialog = CoCreateInstance(&FileOpenDialog, None, CLSCTX_INPROC_SERVER).ok()?;
+        let options = dialog.GetOptions().ok()?;
+        dialog.SetOptions(options | FOS_PICKFOLDERS | FOS_FORCEFILESYSTEM).ok()?;
+        // キャンセル時はErrが返る
+        dialog.Show(Some(owner)).ok()?;
+        let item = dialog.GetResult().ok()?;
+        let display_name = item.GetDisplayName(SIGDN_FILESYSPATH).ok()?;
+        let path = display_name.to_string().ok().map(PathBuf::from);
+        CoTaskMemFree(Some(display_name.0 as *const _));
+        path
+    }
+}
+
+/// 直前に使っていたエクスプローラのウィンドウ（Zオーダーで自分のすぐ後ろ）が開いているフォルダを返す
+/// 「PC」などファイルシステム上にないフォルダを開いている場合は None
+fn explorer_folder(window: HWND) -> Option<PathBuf> {
+    unsafe {
+        let shell_windows: IShellWindows = CoCreateInstance(&ShellWindows, None, CLSCTX_ALL).ok()?;
+        let count = shell_windows.Count().ok()?;
+        let mut folders: Vec<(HWND, PathBuf)> = Vec::new();
+        for index in 0..count {
+            let Ok(dispatch) = shell_windows.Item(&VARIANT::from(index)) else { continue };
+            let Ok(browser) = dispatch.cast::<IWebBrowserApp>() else { continue };
+            let Ok(handle) = browser.HWND() else { continue };
+            let Ok(url) = browser.LocationURL() else { continue };
+            if let Some(folder) = path_from_file_url(&url.to_string()) {
+                folders.push((HWND(handle.0 as *mut c_void), folder));
+            }
+        }
 
-    for path in paths.iter() {
-        if let Ok(mut f) = File::open(path) {
-            let mut buf = Vec::new();
-            if f.read_to_end(&mut buf).is_ok() {
-                return Some(CompactDictionary::new(&buf));
+        let mut current = GetWindow(window, GW_HWNDNEXT).ok();
+        while let Some(hwnd) = current {
+            if let Some((_, folder)) = folders.iter().find(|(explorer, _)| *explorer == hwnd) {
+                return Some(folder.clone());
             }
+            current = GetWindow(hwnd, GW_HWNDNEXT).ok();
         }
+        None
     }
-    None
 }
 
-/// Migemo検索を実行する
-fn migemo_query(text: &str, dict: &Option<CompactDictionary>) -> Option<String> {
-    dict.as_ref().map(|d| query(text.to_string(), d, &RegexOperator::Default))
+/// `file:///C:/...` 形式のURLをパスに変換する
+fn path_from_file_url(url: &str) -> Option<PathBuf> {
+    let url_w = str_to_wide(url);
+    let mut buffer = vec![0u16; 32768];
+    let mut len = buffer.len() as u32;
+    unsafe { PathCreateFromUrlW(PCWSTR(url_w.as_ptr()), PWSTR(buffer.as_mut_ptr()), &mut len, None).ok()? };
+    let path = PathBuf::from(String::from_utf16_lossy(&buffer[..len as usize]));
+    path.is_dir().then_some(path)
 }
 
-/// 検索を即座に実行するためのタイマーをセットする
-fn trigger_search(window: HWND) {
-    unsafe { SetTimer(Some(window), TIMER_ID, 100, None) };
+/// 選択中の項目（選択がなければ右クリックした行）のパスを、検索範囲からの相対パスにして改行区切りでコピーする
+/// 検索範囲がなければフルパスをコピーする
+fn copy_relative_paths(window: HWND, state: &mut AppState, clicked_index: usize) {
+    let mut indices = selected_indices(state);
+    if indices.is_empty() { indices.push(clicked_index); }
+    let scope = state.scope_path.as_ref().map(|scope| scope.to_string_lossy().into_owned());
+    let paths: Vec<String> = indices
+        .into_iter()
+        .filter_map(|index| state.result_at(index))
+        .map(|result| match &scope {
+            Some(scope) => relative_to_scope(&result.full_path, scope),
+            None => result.full_path,
+        })
+        .collect();
+    if paths.is_empty() { return; }
+    copy_text_to_clipboard(window, &paths.join("\r\n"));
+    flash_status(window, state, &format!("{} 件のパスをコピーしました", paths.len()));
 }
 
-/// Everythingを使用して検索を実行し、結果を更新する
-fn perform_search(state: &mut AppState) {
-    let mut buffer: [u16; 512] = [0; 512];
-    let len = unsafe { GetWindowTextW(state.edit_hwnd, &mut buffer) };
-    let search_term = String::from_utf16_lossy(&buffer[..len as usize]);
-
-    let window_title = if search_term.is_empty() { "Migemo Everything".to_string() } else { format!("{} - Migemo Everything", search_term) };
-    unsafe {
-        let title_wide = str_to_wide(&window_title);
-        let _ = SetWindowTextW(state.main_hwnd, PCWSTR(title_wide.as_ptr()));
+// --- ファイルのコピー/移動 ---
+
+/// 選択中の項目（選択がなければ右クリックした行）をフォルダへコピー/移動する
+/// 移動した場合はリストを更新するため検索をやり直す
+fn transfer_selection(window: HWND, state: &mut AppState, clicked_index: usize, is_move: bool) {
+    let mut indices = selected_indices(state);
+    if indices.is_empty() { indices.push(clicked_index); }
+    let paths: Vec<PathBuf> = indices
+        .into_iter()
+        .filter_map(|index| state.result_at(index))
+        .map(|result| PathBuf::from(&result.full_path))
+        .collect();
+    if paths.is_empty() { return; }
+
+    let verb = if is_move { "移動" } else { "コピー" };
+    if paths.len() >= FILE_OPERATION_CONFIRM_COUNT {
+        let text_w = str_to_wide(&format!("{} 件の項目を{}します。よろしいですか？", paths.len(), verb));
+        let answer = unsafe { MessageBoxW(Some(window), PCWSTR(text_w.as_ptr()), w!("確認"), MB_OKCANCEL | MB_ICONQUESTION) };
+        if answer != IDOK { return; }
     }
 
-    if search_term.is_empty() {
-        state.search_results.lock().unwrap().clear();
-        state.total_results = 0;
-        state.current_search_term.clear();
-        state.current_page_offset = 0;
+    let Some(destination) = pick_folder(window) else { return };
+    match transfer_items(window, &paths, &destination, is_move) {
+        Ok(true) => flash_status(window, state, &format!("{} 件を{}しました", paths.len(), verb)),
+        Ok(false) => flash_status(window, state, &format!("{}を中断しました", verb)),
+        Err(e) => {
+            eprintln!("IFileOperation failed: {:?}", e);
+            flash_status(window, state, &format!("{}に失敗しました", verb));
+        }
+    }
+    if is_move {
+        perform_search(state, true);
+    }
+}
+
+/// IFileOperationで項目をフォルダへコピー/移動する
+/// エクスプローラと同じ進捗表示と「元に戻す」が使える
+/// すべて完了した場合は true、ユーザーが中断した場合は false を返す
+fn transfer_items(owner: HWND, paths: &[PathBuf], destination: &Path, is_move: bool) -> Result<bool> {
+    unsafe {
+        let operation: IFileOperation = CoCreateInstance(&FileOperation, None, CLSCTX_INPROC_SERVER)?;
+        operation.SetOwnerWindow(owner)?;
+        operation.SetOperationFlags(FOF_ALLOWUNDO | FOF_NOCONFIRMMKDIR)?;
+
+        let destination_w = str_to_wide(&destination.to_string_lossy());
+        let destination_item: IShellItem = SHCreateItemFromParsingName(PCWSTR(destination_w.as_ptr()), None)?;
+        for path in paths {
+            let path_w = str_to_wide(&path.to_string_lossy());
+            let item: IShellItem = SHCreateItemFromParsingName(PCWSTR(path_w.as_ptr()), None)?;
+            if is_move {
+                operation.MoveItem(&item, &destination_item, PCWSTR::null(), None)?;
+            } else {
+                operation.CopyItem(&item, &destination_item, PCWSTR::null(), None)?;
+            }
+        }
+
+        operation.PerformOperations()?;
+        Ok(!operation.GetAnyOperationsAborted()?.as_bool())
+    }
+}
+
+// --- リスト編集ダイアログ ---
+
+/// リスト編集ダイアログのウィンドウクラス名
+const LIST_DIALOG_CLASS: PCWSTR = w!("MigemoEverythingListDialog");
+
+/// リスト編集ダイアログの状態
+struct ListDialogState {
+    edit_hwnd: HWND,
+    initial_text: Vec<u16>,
+    result: Option<Vec<String>>,
+    done: bool,
+}
+
+/// 1行に1項目を編集するモーダルダイアログを表示する
+/// OKで閉じた場合は空行を除いた項目を返し、キャンセル時はNoneを返す
+fn edit_list_dialog(owner: HWND, title: &str, items: &[String]) -> Option<Vec<String>> {
+    unsafe {
+        let instance = GetModuleHandleA(None).ok()?;
+        let wc = WNDCLASSW {
+            hCursor: LoadCursorW(None, IDC_ARROW).ok()?,
+            hInstance: instance.into(),
+            lpszClassName: LIST_DIALOG_CLASS,
+            lpfnWndProc: Some(list_dialog_proc),
+            hbrBackground: HBRUSH((COLOR_BTNFACE.0 + 1) as isize as *mut c_void),
+            ..Default::default()
+        };
+        // 2回目以降は登録済みで失敗するが問題ない
+        RegisterClassW(&wc);
+
+        let dialog_state = Box::into_raw(Box::new(ListDialogState {
+            edit_hwnd: HWND::default(),
+            initial_text: str_to_wide(&items.join("\r\n")),
+            result: None,
+            done: false,
+        }));
+
+        let scale = window_dpi(owner) as f32 / 96.0;
+        let title_w = str_to_wide(title);
+        let created = CreateWindowExW(
+            WS_EX_DLGMODALFRAME,
+            LIST_DIALOG_CLASS,
+            PCWSTR(title_w.as_ptr()),
+            WS_POPUP | WS_CAPTION | WS_SYSMENU | WS_VISIBLE,
+            CW_USEDEFAULT, CW_USEDEFAULT,
+            (420.0 * scale) as i32, (320.0 * scale) as i32,
+            Some(owner), None, Some(instance.into()),
+            Some(dialog_state as *const c_void),
+        );
+
+        if let Ok(dialog) = created {
+            // 閉じるまでオーナーを無効化してモーダルにする
+            let _ = EnableWindow(owner, false);
+            let mut message = MSG::default();
+            while !(*dialog_state).done {
+                if !GetMessageW(&mut message, None, 0, 0).as_bool() {
+                    // WM_QUITはメインループに引き継ぐ
+                    PostQuitMessage(message.wParam.0 as i32);
+                    break;
+                }
+                if !IsDialogMessageW(dialog, &message).as_bool() {
+                    let _ = TranslateMessage(&message);
+                    DispatchMessageW(&message);
+                }
+            }
+            // WM_QUITで抜けた場合はダイアログが残っている。状態を解放する前に破棄し、ウィンドウから状態を参照させない
+            if !(*dialog_state).done {
+                let _ = DestroyWindow(dialog);
+            }
+            let _ = EnableWindow(owner, true);
+            let _ = SetForegroundWindow(owner);
+        }
+
+        Box::from_raw(dialog_state).result
+    }
+}
+
+/// リスト編集ダイアログのウィンドウプロシージャ
+extern "system" fn list_dialog_proc(window: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    unsafe {
+        if message == WM_CREATE {
+            let create_struct = &*(lparam.0 as *const CREATESTRUCTW);
+            SetWindowLongPtrW(window, GWLP_USERDATA, create_struct.lpCreateParams as isize);
+            let dialog_state = &mut *(create_struct.lpCreateParams as *mut ListDialogState);
+
+            let scale = window_dpi(window) as f32 / 96.0;
+            let mut client = RECT::default();
+            let _ = GetClientRect(window, &mut client);
+            let margin = (8.0 * scale) as i32;
+            let button_width = (80.0 * scale) as i32;
+            let button_height = (25.0 * scale) as i32;
+            let button_y = client.bottom - margin - button_height;
+
+            dialog_state.edit_hwnd = CreateWindowExW(
+                WS_EX_CLIENTEDGE, w!("EDIT"), PCWSTR(dialog_state.initial_text.as_ptr()),
+                WS_CHILD | WS_VISIBLE | WS_TABSTOP | WS_VSCROLL | WS_HSCROLL
+                    | WINDOW_STYLE(ES_MULTILINE as u32 | ES_AUTOVSCROLL as u32 | ES_AUTOHSCROLL as u32 | ES_WANTRETURN as u32),
+                margin, margin, client.right - margin * 2, button_y - margin * 2,
+                Some(window), None, None, None,
+            ).unwrap_or_default();
+            let ok_hwnd = CreateWindowExW(
+                WINDOW_EX_STYLE::default(), w!("BUTTON"), w!("OK"),
+                WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_DEFPUSHBUTTON as u32),
+                client.right - margin * 2 - button_width * 2, button_y, button_width, button_height,
+                Some(window), Some(HMENU(IDOK.0 as isize as *mut c_void)), None, None,
+            ).unwrap_or_default();
+            let cancel_hwnd = CreateWindowExW(
+                WINDOW_EX_STYLE::default(), w!("BUTTON"), w!("キャンセル"),
+                WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_PUSHBUTTON as u32),
+                client.right - margin - button_width, button_y, button_width, button_height,
+                Some(window), Some(HMENU(IDCANCEL.0 as isize as *mut c_void)), None, None,
+            ).unwrap_or_default();
+
+            let h_font = GetStockObject(DEFAULT_GUI_FONT);
+            for hwnd in [dialog_state.edit_hwnd, ok_hwnd, cancel_hwnd] {
+                SendMessageW(hwnd, WM_SETFONT, Some(WPARAM(h_font.0 as usize)), Some(LPARAM(1)));
+            }
+            let _ = SetFocus(Some(dialog_state.edit_hwnd));
+            return LRESULT(0);
+        }
+
+        let dialog_state_ptr = GetWindowLongPtrW(window, GWLP_USERDATA) as *mut ListDialogState;
+        if dialog_state_ptr.is_null() {
+            return DefWindowProcW(window, message, wparam, lparam);
+        }
+        let dialog_state = &mut *dialog_state_ptr;
+
+        match message {
+            WM_COMMAND => {
+                let control_id = loword(wparam.0 as u32) as i32;
+                if control_id == IDOK.0 {
+                    let len = GetWindowTextLengthW(dialog_state.edit_hwnd);
+                    let mut buffer = vec![0u16; len as usize + 1];
+                    let copied = GetWindowTextW(dialog_state.edit_hwnd, &mut buffer);
+                    let text = String::from_utf16_lossy(&buffer[..copied as usize]);
+                    dialog_state.result = Some(
+                        text.lines().map(|line| line.trim()).filter(|line| !line.is_empty()).map(|line| line.to_string()).collect()
+                    );
+                    let _ = DestroyWindow(window);
+                } else if control_id == IDCANCEL.0 {
+                    let _ = DestroyWindow(window);
+                }
+                LRESULT(0)
+            }
+            WM_CLOSE => {
+                let _ = DestroyWindow(window);
+                LRESULT(0)
+            }
+            WM_DESTROY => {
+                SetWindowLongPtrW(window, GWLP_USERDATA, 0);
+                dialog_state.done = true;
+                LRESULT(0)
+            }
+            _ => DefWindowProcW(window, message, wparam, lparam),
+        }
+    }
+}
+
+// --- 設定ダイアログ ---
+
+/// 設定ダイアログのウィンドウクラス名
+const SETTINGS_DIALOG_CLASS: PCWSTR = w!("MigemoEverythingSettingsDialog");
+/// 設定ダイアログのコントロールID: チェックボックスの先頭（`SettingFlag::ALL` の順に連番）
+const SETTINGS_CHECK_FIRST: i32 = 100;
+/// 設定ダイアログのコントロールID: 自動更新の間隔
+const SETTINGS_AUTO_REFRESH_EDIT_ID: i32 = 200;
+/// 設定ダイアログのコントロールID: 表示する最大件数
+const SETTINGS_MAX_RESULTS_EDIT_ID: i32 = 201;
+/// 設定ダイアログのコントロールID: 既定に戻す
+const SETTINGS_RESET_BUTTON_ID: i32 = 300;
+/// 自動更新の間隔として入力できる最大値（秒）
+const MAX_AUTO_REFRESH_SECS: u32 = 86_400;
+/// 表示する最大件数として入力できる最大値
+const MAX_DISPLAY_RESULTS_LIMIT: u32 = 10_000_000;
+/// 設定ダイアログのクライアント領域の幅（96DPI基準）
+const SETTINGS_DIALOG_WIDTH: i32 = 480;
+/// 設定ダイアログのチェックボックス1行の高さ（96DPI基準）
+const SETTINGS_ROW_HEIGHT: i32 = 24;
+/// 設定ダイアログのグループボックスの見出しの高さ（96DPI基準）
+const SETTINGS_GROUP_HEADER: i32 = 20;
+
+/// 設定ダイアログの区分（グループボックス）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SettingSection {
+    Search,
+    Appearance,
+    Behavior,
+}
+
+impl SettingSection {
+    const ALL: [SettingSection; 3] = [SettingSection::Search, SettingSection::Appearance, SettingSection::Behavior];
+
+    fn label(self) -> &'static str {
+        match self {
+            SettingSection::Search => "検索",
+            SettingSection::Appearance => "外観",
+            SettingSection::Behavior => "動作",
+        }
+    }
+
+    /// 区分に並べるチェックボックス
+    fn flags(self) -> impl Iterator<Item = SettingFlag> {
+        SettingFlag::ALL.into_iter().filter(move |flag| flag.section() == self)
+    }
+
+    /// 区分の中の行数（チェックボックスは2列に並べ、検索の区分には数値の入力欄が2行続く）
+    fn rows(self) -> i32 {
+        let check_rows = (self.flags().count() as i32 + 1) / 2;
+        if self == SettingSection::Search { check_rows + 2 } else { check_rows }
+    }
+}
+
+/// 設定ダイアログのチェックボックスで切り替える設定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SettingFlag {
+    LiveSearch,
+    LiteralSearch,
+    MatchDiacritics,
+    MigemoMatchPath,
+    StripedRows,
+    HighlightPath,
+    AccentHighlight,
+    CompactRows,
+    ShowCreatedColumn,
+    ShowTypeColumn,
+    BinaryUnits,
+    ShowPreview,
+    PreviewContent,
+    AlwaysOnTop,
+    MinimizeToTray,
+    RefreshAfterShellCommand,
+    ClipboardSearch,
+}
+
+impl SettingFlag {
+    const ALL: [SettingFlag; 17] = [
+        SettingFlag::LiveSearch,
+        SettingFlag::LiteralSearch,
+        SettingFlag::MatchDiacritics,
+        SettingFlag::MigemoMatchPath,
+        SettingFlag::StripedRows,
+        SettingFlag::HighlightPath,
+        SettingFlag::AccentHighlight,
+        SettingFlag::CompactRows,
+        SettingFlag::ShowCreatedColumn,
+        SettingFlag::ShowTypeColumn,
+        SettingFlag::BinaryUnits,
+        SettingFlag::ShowPreview,
+        SettingFlag::PreviewContent,
+        SettingFlag::AlwaysOnTop,
+        SettingFlag::MinimizeToTray,
+        SettingFlag::RefreshAfterShellCommand,
+        SettingFlag::ClipboardSearch,
+    ];
+
+    fn section(self) -> SettingSection {
+        match self {
+            SettingFlag::LiveSearch | SettingFlag::LiteralSearch | SettingFlag::MatchDiacritics | SettingFlag::MigemoMatchPath => {
+                SettingSection::Search
+            }
+            SettingFlag::StripedRows
+            | SettingFlag::HighlightPath
+            | SettingFlag::AccentHighlight
+            | SettingFlag::CompactRows
+            | SettingFlag::ShowCreatedColumn
+            | SettingFlag::ShowTypeColumn
+            | SettingFlag::BinaryUnits => SettingSection::Appearance,
+            SettingFlag::ShowPreview
+            | SettingFlag::PreviewContent
+            | SettingFlag::AlwaysOnTop
+            | SettingFlag::MinimizeToTray
+            | SettingFlag::RefreshAfterShellCommand
+            | SettingFlag::ClipboardSearch => SettingSection::Behavior,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SettingFlag::LiveSearch => "入力中に検索",
+            SettingFlag::LiteralSearch => "通常検索で記号をそのまま検索",
+            SettingFlag::MatchDiacritics => "アクセント記号を区別",
+            SettingFlag::MigemoMatchPath => "Migemoでパス全体を検索",
+            SettingFlag::StripedRows => "1行おきに色を付ける",
+            SettingFlag::HighlightPath => "フォルダ列の一致箇所を強調",
+            SettingFlag::AccentHighlight => "一致箇所をアクセントカラーで強調",
+            SettingFlag::CompactRows => "名前とフォルダを1列に表示",
+            SettingFlag::ShowCreatedColumn => "「作成日時」列を表示",
+            SettingFlag::ShowTypeColumn => "「種類」列を表示",
+            SettingFlag::BinaryUnits => "サイズを KiB (1024 バイト) で表示",
+            SettingFlag::ShowPreview => "プレビュー欄を表示",
+            SettingFlag::PreviewContent => "プレビュー欄にファイルの内容を表示",
+            SettingFlag::AlwaysOnTop => "最前面に表示",
+            SettingFlag::MinimizeToTray => "最小化時に通知領域へ格納",
+            SettingFlag::RefreshAfterShellCommand => "シェルのメニューの実行後に再検索",
+            SettingFlag::ClipboardSearch => "コピーした文字列で検索",
+        }
+    }
+
+    fn get(self, config: &Config) -> bool {
+        match self {
+            SettingFlag::LiveSearch => config.live_search,
+            SettingFlag::LiteralSearch => config.literal_search,
+            SettingFlag::MatchDiacritics => config.match_diacritics,
+            SettingFlag::MigemoMatchPath => config.migemo_match_path,
+            SettingFlag::StripedRows => config.striped_rows,
+            SettingFlag::HighlightPath => config.highlight_path,
+            SettingFlag::AccentHighlight => config.accent_highlight,
+            SettingFlag::CompactRows => config.compact_rows,
+            SettingFlag::ShowCreatedColumn => config.show_created_column,
+            SettingFlag::ShowTypeColumn => config.show_type_column,
+            SettingFlag::BinaryUnits => config.binary_units,
+            SettingFlag::ShowPreview => config.show_preview,
+            SettingFlag::PreviewContent => config.preview_content,
+            SettingFlag::AlwaysOnTop => config.always_on_top,
+            SettingFlag::MinimizeToTray => config.minimize_to_tray,
+            SettingFlag::RefreshAfterShellCommand => config.refresh_after_shell_command,
+            SettingFlag::ClipboardSearch => config.clipboard_search,
+        }
+    }
+
+    fn set(self, config: &mut Config, value: bool) {
+        let field = match self {
+            SettingFlag::LiveSearch => &mut config.live_search,
+            SettingFlag::LiteralSearch => &mut config.literal_search,
+            SettingFlag::MatchDiacritics => &mut config.match_diacritics,
+            SettingFlag::MigemoMatchPath => &mut config.migemo_match_path,
+            SettingFlag::StripedRows => &mut config.striped_rows,
+            SettingFlag::HighlightPath => &mut config.highlight_path,
+            SettingFlag::AccentHighlight => &mut config.accent_highlight,
+            SettingFlag::CompactRows => &mut config.compact_rows,
+            SettingFlag::ShowCreatedColumn => &mut config.show_created_column,
+            SettingFlag::ShowTypeColumn => &mut config.show_type_column,
+            SettingFlag::BinaryUnits => &mut config.binary_units,
+            SettingFlag::ShowPreview => &mut config.show_preview,
+            SettingFlag::PreviewContent => &mut config.preview_content,
+            SettingFlag::AlwaysOnTop => &mut config.always_on_top,
+            SettingFlag::MinimizeToTray => &mut config.minimize_to_tray,
+            SettingFlag::RefreshAfterShellCommand => &mut config.refresh_after_shell_command,
+            SettingFlag::ClipboardSearch => &mut config.clipboard_search,
+        };
+        *field = value;
+    }
+}
+
+/// 設定ダイアログの状態
+struct SettingsDialogState {
+    /// `SettingFlag::ALL` の順のチェックボックス
+    check_hwnds: Vec<HWND>,
+    auto_refresh_edit: HWND,
+    max_results_edit: HWND,
+    /// ダイアログで扱わない設定（除外フォルダなど）を引き継ぐための元の設定
+    initial: Config,
+    result: Option<Config>,
+    done: bool,
+}
+
+/// 設定ダイアログのクライアント領域の高さ（96DPI基準）
+fn settings_dialog_height() -> i32 {
+    let margin = 8;
+    let groups: i32 = SettingSection::ALL.iter().map(|section| SETTINGS_GROUP_HEADER + section.rows() * SETTINGS_ROW_HEIGHT + margin + margin).sum();
+    margin + groups + 25 + margin
+}
+
+/// 設定をまとめて編集するモーダルダイアログを表示する
+/// OKで閉じた場合は入力を反映した設定を返し、キャンセル時はNoneを返す
+fn settings_dialog(owner: HWND, config: &Config) -> Option<Config> {
+    unsafe {
+        let instance = GetModuleHandleA(None).ok()?;
+        let wc = WNDCLASSW {
+            hCursor: LoadCursorW(None, IDC_ARROW).ok()?,
+            hInstance: instance.into(),
+            lpszClassName: SETTINGS_DIALOG_CLASS,
+            lpfnWndProc: Some(settings_dialog_proc),
+            hbrBackground: HBRUSH((COLOR_BTNFACE.0 + 1) as isize as *mut c_void),
+            ..Default::default()
+        };
+        // 2回目以降は登録済みで失敗するが問題ない
+        RegisterClassW(&wc);
+
+        let dialog_state = Box::into_raw(Box::new(SettingsDialogState {
+            check_hwnds: Vec::new(),
+            auto_refresh_edit: HWND::default(),
+            max_results_edit: HWND::default(),
+            initial: config.clone(),
+            result: None,
+            done: false,
+        }));
+
+        let scale = window_dpi(owner) as f32 / 96.0;
+        let style = WS_POPUP | WS_CAPTION | WS_SYSMENU | WS_VISIBLE;
+        let mut rect = RECT { left: 0, top: 0, right: (SETTINGS_DIALOG_WIDTH as f32 * scale) as i32, bottom: (settings_dialog_height() as f32 * scale) as i32 };
+        let _ = AdjustWindowRectEx(&mut rect, style, false, WS_EX_DLGMODALFRAME);
+        let created = CreateWindowExW(
+            WS_EX_DLGMODALFRAME,
+            SETTINGS_DIALOG_CLASS,
+            w!("設定"),
+            style,
+            CW_USEDEFAULT, CW_USEDEFAULT,
+            rect.right - rect.left, rect.bottom - rect.top,
+            Some(owner), None, Some(instance.into()),
+            Some(dialog_state as *const c_void),
+        );
+
+        if let Ok(dialog) = created {
+            // 閉じるまでオーナーを無効化してモーダルにする
+            let _ = EnableWindow(owner, false);
+            let mut message = MSG::default();
+            while !(*dialog_state).done {
+                if !GetMessageW(&mut message, None, 0, 0).as_bool() {
+                    // WM_QUITはメインループに引き継ぐ
+                    PostQuitMessage(message.wParam.0 as i32);
+                    break;
+                }
+                if !IsDialogMessageW(dialog, &message).as_bool() {
+                    let _ = TranslateMessage(&message);
+                    DispatchMessageW(&message);
+                }
+            }
+            // WM_QUITで抜けた場合はダイアログが残っている。状態を解放する前に破棄し、ウィンドウから状態を参照させない
+            if !(*dialog_state).done {
+                let _ = DestroyWindow(dialog);
+            }
+            let _ = EnableWindow(owner, true);
+            let _ = SetForegroundWindow(owner);
+        }
+
+        Box::from_raw(dialog_state).result
+    }
+}
+
+/// 設定ダイアログのコントロールに設定の値を表示する
+fn fill_settings_controls(dialog_state: &SettingsDialogState, config: &Config) {
+    unsafe {
+        for (flag, &hwnd) in SettingFlag::ALL.iter().zip(&dialog_state.check_hwnds) {
+            let check = if flag.get(config) { BST_CHECKED } else { BST_UNCHECKED };
+            SendMessageW(hwnd, BM_SETCHECK, Some(WPARAM(check.0 as usize)), None);
+        }
+        let auto_refresh_w = str_to_wide(&config.auto_refresh_secs.unwrap_or(0).to_string());
+        let _ = SetWindowTextW(dialog_state.auto_refresh_edit, PCWSTR(auto_refresh_w.as_ptr()));
+        let max_results_w = str_to_wide(&config.max_display_results.to_string());
+        let _ = SetWindowTextW(dialog_state.max_results_edit, PCWSTR(max_results_w.as_ptr()));
+    }
+}
+
+/// 数値の入力欄の値を読み取る（範囲外や数値でない場合は None）
+fn read_number(edit_hwnd: HWND, min: u32, max: u32) -> Option<u32> {
+    let mut buffer = [0u16; 32];
+    let len = unsafe { GetWindowTextW(edit_hwnd, &mut buffer) };
+    String::from_utf16_lossy(&buffer[..len as usize]).trim().parse::<u32>().ok().filter(|value| (min..=max).contains(value))
+}
+
+/// 設定ダイアログの入力から設定を組み立てる
+/// 数値が不正な場合はメッセージを表示してその入力欄へフォーカスを移し、None を返す
+fn read_settings_controls(window: HWND, dialog_state: &SettingsDialogState) -> Option<Config> {
+    let mut config = dialog_state.initial.clone();
+    for (flag, &hwnd) in SettingFlag::ALL.iter().zip(&dialog_state.check_hwnds) {
+        let checked = unsafe { SendMessageW(hwnd, BM_GETCHECK, None, None) } == LRESULT(BST_CHECKED.0 as isize);
+        flag.set(&mut config, checked);
+    }
+
+    let numbers = [
+        (dialog_state.auto_refresh_edit, 0, MAX_AUTO_REFRESH_SECS, "自動更新の間隔"),
+        (dialog_state.max_results_edit, 1, MAX_DISPLAY_RESULTS_LIMIT, "表示する最大件数"),
+    ];
+    let mut values = [0u32; 2];
+    for (value, (edit_hwnd, min, max, name)) in values.iter_mut().zip(numbers) {
+        match read_number(edit_hwnd, min, max) {
+            Some(number) => *value = number,
+            None => {
+                let text_w = str_to_wide(&format!("{}は {}〜{} の数値で入力してください。", name, min, format_with_commas(max as u64)));
+                unsafe {
+                    MessageBoxW(Some(window), PCWSTR(text_w.as_ptr()), w!("設定"), MB_OK | MB_ICONWARNING);
+                    let _ = SetFocus(Some(edit_hwnd));
+                    SendMessageW(edit_hwnd, EM_SETSEL, Some(WPARAM(0)), Some(LPARAM(-1)));
+                }
+                return None;
+            }
+        }
+    }
+    config.auto_refresh_secs = Some(values[0]).filter(|&secs| secs > 0);
+    config.max_display_results = values[1];
+    Some(config)
+}
+
+/// 設定ダイアログのウィンドウプロシージャ
+extern "system" fn settings_dialog_proc(window: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    unsafe {
+        if message == WM_CREATE {
+            let create_struct = &*(lparam.0 as *const CREATESTRUCTW);
+            SetWindowLongPtrW(window, GWLP_USERDATA, create_struct.lpCreateParams as isize);
+            let dialog_state = &mut *(create_struct.lpCreateParams as *mut SettingsDialogState);
+
+            let scale = window_dpi(window) as f32 / 96.0;
+            let scaled = |value: i32| (value as f32 * scale) as i32;
+            let mut client = RECT::default();
+            let _ = GetClientRect(window, &mut client);
+            let margin = scaled(8);
+            let row_height = scaled(SETTINGS_ROW_HEIGHT);
+            let control_height = scaled(20);
+            let button_width = scaled(80);
+            let button_height = scaled(25);
+            let group_width = client.right - margin * 2;
+            let column_width = (group_width - margin * 2) / 2;
+            let h_font = GetStockObject(DEFAULT_GUI_FONT);
+
+            let create_child = |ex_style: WINDOW_EX_STYLE, class: PCWSTR, text: &str, style: WINDOW_STYLE, x: i32, y: i32, width: i32, height: i32, id: i32| {
+                let text_w = str_to_wide(text);
+                let hwnd = CreateWindowExW(
+                    ex_style, class, PCWSTR(text_w.as_ptr()), WS_CHILD | WS_VISIBLE | style,
+                    x, y, width, height,
+                    Some(window), Some(HMENU(id as isize as *mut c_void)), None, None,
+                ).unwrap_or_default();
+                SendMessageW(hwnd, WM_SETFONT, Some(WPARAM(h_font.0 as usize)), Some(LPARAM(1)));
+                hwnd
+            };
+
+            let mut check_hwnds = vec![HWND::default(); SettingFlag::ALL.len()];
+            let mut y = margin;
+            for section in SettingSection::ALL {
+                let group_height = scaled(SETTINGS_GROUP_HEADER) + section.rows() * row_height + margin;
+                create_child(
+                    WINDOW_EX_STYLE::default(), w!("BUTTON"), section.label(), WINDOW_STYLE(BS_GROUPBOX as u32),
+                    margin, y, group_width, group_height, 0,
+                );
+                let content_y = y + scaled(SETTINGS_GROUP_HEADER);
+                let mut row = 0;
+                for (i, flag) in section.flags().enumerate() {
+                    let index = SettingFlag::ALL.iter().position(|&f| f == flag).unwrap_or(0);
+                    row = i as i32 / 2;
+                    check_hwnds[index] = create_child(
+                        WINDOW_EX_STYLE::default(), w!("BUTTON"), flag.label(), WS_TABSTOP | WINDOW_STYLE(BS_AUTOCHECKBOX as u32),
+                        margin * 2 + (i as i32 % 2) * column_width, content_y + row * row_height, column_width - margin, control_height,
+                        SETTINGS_CHECK_FIRST + index as i32,
+                    );
+                }
+                if section == SettingSection::Search {
+                    let label_width = scaled(220);
+                    let number_fields = [
+                        ("自動更新の間隔 (秒、0 でオフ):", SETTINGS_AUTO_REFRESH_EDIT_ID),
+                        ("表示する最大件数:", SETTINGS_MAX_RESULTS_EDIT_ID),
+                    ];
+                    for (label, id) in number_fields {
+                        row += 1;
+                        let row_y = content_y + row * row_height;
+                        create_child(
+                            WINDOW_EX_STYLE::default(), w!("STATIC"), label, WINDOW_STYLE::default(),
+                            margin * 2, row_y + scaled(3), label_width, control_height, 0,
+                        );
+                        let edit_hwnd = create_child(
+                            WS_EX_CLIENTEDGE, w!("EDIT"), "", WS_TABSTOP | WINDOW_STYLE(ES_NUMBER as u32 | ES_AUTOHSCROLL as u32),
+                            margin * 2 + label_width, row_y, scaled(100), control_height, id,
+                        );
+                        if id == SETTINGS_AUTO_REFRESH_EDIT_ID { dialog_state.auto_refresh_edit = edit_hwnd; } else { dialog_state.max_results_edit = edit_hwnd; }
+                    }
+                }
+                y += group_height + margin;
+            }
+            dialog_state.check_hwnds = check_hwnds;
+
+            let button_y = client.bottom - margin - button_height;
+            create_child(
+                WINDOW_EX_STYLE::default(), w!("BUTTON"), "既定に戻す", WS_TABSTOP | WINDOW_STYLE(BS_PUSHBUTTON as u32),
+                margin, button_y, scaled(100), button_height, SETTINGS_RESET_BUTTON_ID,
+            );
+            create_child(
+                WINDOW_EX_STYLE::default(), w!("BUTTON"), "OK", WS_TABSTOP | WINDOW_STYLE(BS_DEFPUSHBUTTON as u32),
+                client.right - margin * 2 - button_width * 2, button_y, button_width, button_height, IDOK.0,
+            );
+            create_child(
+                WINDOW_EX_STYLE::default(), w!("BUTTON"), "キャンセル", WS_TABSTOP | WINDOW_STYLE(BS_PUSHBUTTON as u32),
+                client.right - margin - button_width, button_y, button_width, button_height, IDCANCEL.0,
+            );
+
+            fill_settings_controls(dialog_state, &dialog_state.initial);
+            if let Some(&first) = dialog_state.check_hwnds.first() {
+                let _ = SetFocus(Some(first));
+            }
+            return LRESULT(0);
+        }
+
+        let dialog_state_ptr = GetWindowLongPtrW(window, GWLP_USERDATA) as *mut SettingsDialogState;
+        if dialog_state_ptr.is_null() {
+            return DefWindowProcW(window, message, wparam, lparam);
+        }
+        let dialog_state = &mut *dialog_state_ptr;
+
+        match message {
+            WM_COMMAND => {
+                let control_id = loword(wparam.0 as u32) as i32;
+                if control_id == IDOK.0 {
+                    if let Some(config) = read_settings_controls(window, dialog_state) {
+                        dialog_state.result = Some(config);
+                        let _ = DestroyWindow(window);
+                    }
+                } else if control_id == IDCANCEL.0 {
+                    let _ = DestroyWindow(window);
+                } else if control_id == SETTINGS_RESET_BUTTON_ID {
+                    // ダイアログの表示を既定値にするだけで、OKを押すまでは反映しない
+                    fill_settings_controls(dialog_state, &Config::default());
+                }
+                LRESULT(0)
+            }
+            WM_CLOSE => {
+                let _ = DestroyWindow(window);
+                LRESULT(0)
+            }
+            WM_DESTROY => {
+                SetWindowLongPtrW(window, GWLP_USERDATA, 0);
+                dialog_state.done = true;
+                LRESULT(0)
+            }
+            _ => DefWindowProcW(window, message, wparam, lparam),
+        }
+    }
+}
+
+/// 設定ダイアログで変更した設定を状態に反映して保存する
+/// 検索条件や表示上限が変わった場合は、表示中の検索をやり直す
+fn apply_settings(window: HWND, state: &mut AppState, config: Config) {
+    let previous = current_config(state);
+    state.live_search = config.live_search;
+    state.literal_search = config.literal_search;
+    state.match_diacritics = config.match_diacritics;
+    state.migemo_match_path = config.migemo_match_path;
+    state.auto_refresh_secs = config.auto_refresh_secs;
+    state.max_display_results = config.max_display_results;
+    state.striped_rows = config.striped_rows;
+    state.highlight_path = config.highlight_path;
+    state.accent_highlight = config.accent_highlight;
+    state.compact_rows = config.compact_rows;
+    state.show_created_column = config.show_created_column;
+    state.show_type_column = config.show_type_column;
+    state.binary_units = config.binary_units;
+    state.show_preview = config.show_preview;
+    state.preview_content = config.preview_content;
+    state.always_on_top = config.always_on_top;
+    state.minimize_to_tray = config.minimize_to_tray;
+    state.refresh_after_shell_command = config.refresh_after_shell_command;
+    state.clipboard_search = config.clipboard_search;
+
+    if !state.live_search {
+        let _ = unsafe { KillTimer(Some(window), TIMER_ID) };
+    }
+    if state.minimize_to_tray != previous.minimize_to_tray {
+        if state.minimize_to_tray { add_tray_icon(window); } else { remove_tray_icon(window); }
+    }
+    if state.clipboard_search != previous.clipboard_search {
+        apply_clipboard_listener(window, state.clipboard_search);
+    }
+    apply_always_on_top(state);
+    apply_auto_refresh(state);
+    refresh_accent_color(state);
+    set_column_visible(state, COLUMN_CREATED, state.show_created_column);
+    set_column_visible(state, COLUMN_TYPE, state.show_type_column);
+    apply_compact_rows(state);
+    apply_row_style(state);
+    save_config(state);
+    update_ui_states(state);
+    update_status_text(state);
+
+    let mut client = RECT::default();
+    let _ = unsafe { GetClientRect(window, &mut client) };
+    layout_controls(client.right, client.bottom, state);
+    update_preview(state);
+
+    let search_changed = state.literal_search != previous.literal_search
+        || state.match_diacritics != previous.match_diacritics
+        || state.migemo_match_path != previous.migemo_match_path
+        || state.max_display_results != previous.max_display_results;
+    if search_changed && state.last_search.is_some() && state.duplicates.is_none() {
+        perform_search(state, true);
+    }
+}
+
+// --- 時間のかかる処理の進捗ダイアログ ---
+
+/// 進捗ダイアログのウィンドウクラス名
+const PROGRESS_DIALOG_CLASS: PCWSTR = w!("MigemoEverythingProgressDialog");
+
+/// 進捗ダイアログの状態
+struct ProgressDialogState {
+    label_hwnd: HWND,
+    progress_hwnd: HWND,
+    cancel_hwnd: HWND,
+    /// 処理する件数（進捗の割合の分母。処理の段階ごとにワーカーから知らされる）
+    total: usize,
+    /// ワーカースレッドに中断を伝えるフラグ
+    cancel: Arc<AtomicBool>,
+    /// ワーカーの処理結果（`run_with_progress_dialog` の型で取り出す）
+    result: Option<Box<dyn Any + Send>>,
+    done: bool,
+}
+
+/// 進捗ダイアログのワーカーが進捗を知らせる関数（済んだ件数, 全体の件数）。false が返ったら中断する
+type ProgressReport<'a> = &'a dyn Fn(usize, usize) -> bool;
+
+/// ワーカースレッドで最大 `limit` 件を取得し、その間モーダルの進捗ダイアログを表示する
+/// キャンセルされた場合はページの取得を止めて None を返す
+/// 取得した結果はメモリに溜めるだけでファイルには書かないため、キャンセル時に消す書きかけのファイルはない
+fn fetch_with_progress_dialog(owner: HWND, backend: EverythingBackend, request: SearchRequest, page_size: usize, limit: usize) -> Option<Vec<FileResult>> {
+    let mut backend = backend;
+    run_with_progress_dialog(owner, w!("検索結果を取得中"), limit, move |report: ProgressReport<'_>| {
+        fetch_all(&mut backend, &request, page_size, limit, |fetched| report(fetched, limit))
+    })
+}
+
+/// ワーカースレッドで `job` を実行し、その間モーダルの進捗ダイアログを表示する
+/// `job` には進捗を知らせる関数が渡される。キャンセルされた場合や `job` が None を返した場合は None を返す
+fn run_with_progress_dialog<T: Send + 'static>(
+    owner: HWND,
+    title: PCWSTR,
+    total: usize,
+    job: impl FnOnce(ProgressReport<'_>) -> Option<T> + Send + 'static,
+) -> Option<T> {
+    unsafe {
+        let instance = GetModuleHandleA(None).ok()?;
+        let icc = INITCOMMONCONTROLSEX { dwSize: std::mem::size_of::<INITCOMMONCONTROLSEX>() as u32, dwICC: ICC_PROGRESS_CLASS };
+        let _ = InitCommonControlsEx(&icc);
+        let wc = WNDCLASSW {
+            hCursor: LoadCursorW(None, IDC_ARROW).ok()?,
+            hInstance: instance.into(),
+            lpszClassName: PROGRESS_DIALOG_CLASS,
+            lpfnWndProc: Some(progress_dialog_proc),
+            hbrBackground: HBRUSH((COLOR_BTNFACE.0 + 1) as isize as *mut c_void),
+            ..Default::default()
+        };
+        // 2回目以降は登録済みで失敗するが問題ない
+        RegisterClassW(&wc);
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let dialog_state = Box::into_raw(Box::new(ProgressDialogState {
+            label_hwnd: HWND::default(),
+            progress_hwnd: HWND::default(),
+            cancel_hwnd: HWND::default(),
+            total,
+            cancel: cancel.clone(),
+            result: None,
+            done: false,
+        }));
+
+        let scale = window_dpi(owner) as f32 / 96.0;
+        let created = CreateWindowExW(
+            WS_EX_DLGMODALFRAME,
+            PROGRESS_DIALOG_CLASS,
+            title,
+            WS_POPUP | WS_CAPTION | WS_SYSMENU | WS_VISIBLE,
+            CW_USEDEFAULT, CW_USEDEFAULT,
+            (360.0 * scale) as i32, (150.0 * scale) as i32,
+            Some(owner), None, Some(instance.into()),
+            Some(dialog_state as *const c_void),
+        );
+
+        if let Ok(dialog) = created {
+            // HWNDはスレッド間で送れないため数値として渡す
+            let dialog_raw = dialog.0 as isize;
+            thread::spawn(move || {
+                let dialog = HWND(dialog_raw as *mut c_void);
+                let report = |done: usize, total: usize| {
+                    let _ = PostMessageW(Some(dialog), WM_FETCH_PROGRESS, WPARAM(done), LPARAM(total as isize));
+                    !cancel.load(Ordering::Relaxed)
+                };
+                let results = job(&report).map(|value| Box::new(value) as Box<dyn Any + Send>);
+                let results_ptr = Box::into_raw(Box::new(results));
+                if PostMessageW(Some(dialog), WM_FETCH_DONE, WPARAM(0), LPARAM(results_ptr as isize)).is_err() {
+                    drop(Box::from_raw(results_ptr));
+                }
+            });
+
+            // 取得が終わるまでオーナーを無効化してモーダルにする
+            let _ = EnableWindow(owner, false);
+            let mut message = MSG::default();
+            while !(*dialog_state).done {
+                if !GetMessageW(&mut message, None, 0, 0).as_bool() {
+                    // WM_QUITはメインループに引き継ぐ
+                    (*dialog_state).cancel.store(true, Ordering::Relaxed);
+                    PostQuitMessage(message.wParam.0 as i32);
+                    break;
+                }
+                if !IsDialogMessageW(dialog, &message).as_bool() {
+                    let _ = TranslateMessage(&message);
+                    DispatchMessageW(&message);
+                }
+            }
+            // WM_QUITで抜けた場合はダイアログが残っている。状態を解放する前に破棄し、ウィンドウから状態を参照させない
+            if !(*dialog_state).done {
+                let _ = DestroyWindow(dialog);
+            }
+            let _ = EnableWindow(owner, true);
+            let _ = SetForegroundWindow(owner);
+        }
+
+        Box::from_raw(dialog_state).result.and_then(|result| result.downcast::<T>().ok()).map(|result| *result)
+    }
+}
+
+/// 進捗ダイアログのウィンドウプロシージャ
+/// キャンセルしてもワーカーの完了通知を受け取るまでは閉じない（取得結果を確実に解放するため）
+extern "system" fn progress_dialog_proc(window: HWND, message: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    unsafe {
+        if message == WM_CREATE {
+            let create_struct = &*(lparam.0 as *const CREATESTRUCTW);
+            SetWindowLongPtrW(window, GWLP_USERDATA, create_struct.lpCreateParams as isize);
+            let dialog_state = &mut *(create_struct.lpCreateParams as *mut ProgressDialogState);
+
+            let scale = window_dpi(window) as f32 / 96.0;
+            let mut client = RECT::default();
+            let _ = GetClientRect(window, &mut client);
+            let margin = (8.0 * scale) as i32;
+            let row_height = (20.0 * scale) as i32;
+            let button_width = (80.0 * scale) as i32;
+            let button_height = (25.0 * scale) as i32;
+
+            dialog_state.label_hwnd = CreateWindowExW(
+                WINDOW_EX_STYLE::default(), w!("STATIC"), w!(""),
+                WS_CHILD | WS_VISIBLE,
+                margin, margin, client.right - margin * 2, row_height,
+                Some(window), None, None, None,
+            ).unwrap_or_default();
+            dialog_state.progress_hwnd = CreateWindowExW(
+                WINDOW_EX_STYLE::default(), PROGRESS_CLASSW, w!(""),
+                WS_CHILD | WS_VISIBLE,
+                margin, margin * 2 + row_height, client.right - margin * 2, row_height,
+                Some(window), None, None, None,
+            ).unwrap_or_default();
+            dialog_state.cancel_hwnd = CreateWindowExW(
+                WINDOW_EX_STYLE::default(), w!("BUTTON"), w!("キャンセル"),
+                WS_CHILD | WS_VISIBLE | WS_TABSTOP | WINDOW_STYLE(BS_DEFPUSHBUTTON as u32),
+                client.right - margin - button_width, client.bottom - margin - button_height, button_width, button_height,
+                Some(window), Some(HMENU(IDCANCEL.0 as isize as *mut c_void)), None, None,
+            ).unwrap_or_default();
+
+            let h_font = GetStockObject(DEFAULT_GUI_FONT);
+            for hwnd in [dialog_state.label_hwnd, dialog_state.cancel_hwnd] {
+                SendMessageW(hwnd, WM_SETFONT, Some(WPARAM(h_font.0 as usize)), Some(LPARAM(1)));
+            }
+            SendMessageW(dialog_state.progress_hwnd, PBM_SETRANGE32, Some(WPARAM(0)), Some(LPARAM(100)));
+            update_progress(dialog_state, 0);
+            return LRESULT(0);
+        }
+
+        let dialog_state_ptr = GetWindowLongPtrW(window, GWLP_USERDATA) as *mut ProgressDialogState;
+        if dialog_state_ptr.is_null() {
+            return DefWindowProcW(window, message, wparam, lparam);
+        }
+        let dialog_state = &mut *dialog_state_ptr;
+
+        match message {
+            WM_FETCH_PROGRESS if !dialog_state.cancel.load(Ordering::Relaxed) => {
+                dialog_state.total = lparam.0 as usize;
+                update_progress(dialog_state, wparam.0);
+                LRESULT(0)
+            }
+            WM_FETCH_DONE => {
+                let results = Box::from_raw(lparam.0 as *mut Option<Box<dyn Any + Send>>);
+                if !dialog_state.cancel.load(Ordering::Relaxed) {
+                    dialog_state.result = *results;
+                }
+                let _ = DestroyWindow(window);
+                LRESULT(0)
+            }
+            WM_COMMAND if loword(wparam.0 as u32) as i32 == IDCANCEL.0 => {
+                cancel_progress(dialog_state);
+                LRESULT(0)
+            }
+            WM_CLOSE => {
+                cancel_progress(dialog_state);
+                LRESULT(0)
+            }
+            WM_DESTROY => {
+                SetWindowLongPtrW(window, GWLP_USERDATA, 0);
+                dialog_state.done = true;
+                LRESULT(0)
+            }
+            _ => DefWindowProcW(window, message, wparam, lparam),
+        }
+    }
+}
+
+/// 進捗ダイアログの件数と進捗バーを更新する
+fn update_progress(dialog_state: &ProgressDialogState, fetched: usize) {
+    let text = format!("{} / {} 件", format_with_commas(fetched as u64), format_with_commas(dialog_state.total as u64));
+    let text_w = str_to_wide(&text);
+    let percent = (fetched * 100).checked_div(dialog_state.total).unwrap_or(0);
+    unsafe {
+        let _ = SetWindowTextW(dialog_state.label_hwnd, PCWSTR(text_w.as_ptr()));
+        SendMessageW(dialog_state.progress_hwnd, PBM_SETPOS, Some(WPARAM(percent)), None);
+    }
+}
+
+/// ワーカーに中断を伝える。ダイアログは完了通知を受け取ったときに閉じる
+fn cancel_progress(dialog_state: &ProgressDialogState) {
+    dialog_state.cancel.store(true, Ordering::Relaxed);
+    unsafe {
+        let _ = SetWindowTextW(dialog_state.label_hwnd, w!("キャンセル中…"));
+        let _ = EnableWindow(dialog_state.cancel_hwnd, false);
+    }
+}
+
+// --- 検索関連の関数 ---
+
+/// 検索を即座に実行するためのタイマーをセットする
+fn trigger_search(window: HWND) {
+    unsafe { SetTimer(Some(window), TIMER_ID, 100, None) };
+}
+
+/// 自動更新の間隔に合わせてタイマーを設定し直す（オフなら止める）
+fn apply_auto_refresh(state: &AppState) {
+    unsafe {
+        let _ = KillTimer(Some(state.main_hwnd), AUTO_REFRESH_TIMER_ID);
+        if let Some(secs) = state.auto_refresh_secs {
+            SetTimer(Some(state.main_hwnd), AUTO_REFRESH_TIMER_ID, secs.saturating_mul(1000), None);
+        }
+    }
+}
+
+/// 表示中の検索を再実行して、Everythingのインデックスの変化を結果に反映する
+/// 検索中・重複の一覧の表示中・最小化中は何もしない。フォーカス行は再検索後に選択し直される
+fn auto_refresh(window: HWND, state: &mut AppState) {
+    if state.last_search.is_none() || state.pending_search.is_some() || state.duplicates.is_some() { return; }
+    if unsafe { IsIconic(window).as_bool() || !IsWindowVisible(window).as_bool() } { return; }
+    perform_search(state, true);
+}
+
+/// クリップボードの変更通知（WM_CLIPBOARDUPDATE）を受け取るかを切り替える
+fn apply_clipboard_listener(window: HWND, enabled: bool) {
+    unsafe {
+        if enabled {
+            let _ = AddClipboardFormatListener(window);
+        } else {
+            let _ = RemoveClipboardFormatListener(window);
+        }
+    }
+}
+
+/// WM_CLIPBOARDUPDATE メッセージのハンドラ
+/// 他のアプリでコピーした文字列を検索ボックスに入れて、デバウンスを待たずに検索する
+/// 「フルパスをコピー」などこのアプリ自身がコピーしたものは、検索し直すと結果が入れ替わってしまうため使わない
+fn handle_clipboard_update(window: HWND, state: &mut AppState) -> LRESULT {
+    if !state.clipboard_search { return LRESULT(0); }
+    let owner = unsafe { GetClipboardOwner() }.unwrap_or_default();
+    // 検索ボックスなどのエディットでのコピーは、そのコントロールがクリップボードを持つ
+    if owner == window || unsafe { IsChild(window, owner) }.as_bool() { return LRESULT(0); }
+    let Some(term) = read_clipboard_text(window).as_deref().and_then(search::clipboard_search_term) else { return LRESULT(0) };
+
+    let mut buffer: [u16; 512] = [0; 512];
+    let len = unsafe { GetWindowTextW(state.edit_hwnd, &mut buffer) };
+    if String::from_utf16_lossy(&buffer[..len as usize]) == term { return LRESULT(0); }
+
+    let text_w = str_to_wide(&term);
+    unsafe {
+        let _ = SetWindowTextW(state.edit_hwnd, PCWSTR(text_w.as_ptr()));
+        let len = text_w.len() - 1;
+        SendMessageW(state.edit_hwnd, EM_SETSEL, Some(WPARAM(len)), Some(LPARAM(len as isize)));
+        // SetWindowTextW の EN_CHANGE で予約された検索は不要になる
+        let _ = KillTimer(Some(window), TIMER_ID);
+    }
+    perform_search(state, false);
+    LRESULT(0)
+}
+
+/// 直前に検索した語を入力欄に戻して、デバウンスを待たずに検索する
+fn restore_previous_query(window: HWND, state: &mut AppState) {
+    if state.previous_query.is_empty() { return; }
+    let text_w = str_to_wide(&state.previous_query);
+    unsafe {
+        let _ = SetWindowTextW(state.edit_hwnd, PCWSTR(text_w.as_ptr()));
+        let len = text_w.len() - 1;
+        SendMessageW(state.edit_hwnd, EM_SETSEL, Some(WPARAM(len)), Some(LPARAM(len as isize)));
+        let _ = SetFocus(Some(state.edit_hwnd));
+        // SetWindowTextW の EN_CHANGE で予約された検索は不要になる
+        let _ = KillTimer(Some(window), TIMER_ID);
+    }
+    perform_search(state, false);
+}
+
+/// Everythingを使用して検索を実行し、結果を更新する
+/// `force` が偽で、検索条件が直前の検索と同じ場合は問い合わせを省略する
+/// 問い合わせはワーカースレッドで行い、結果は WM_SEARCH_COMPLETE で受け取る
+fn perform_search(state: &mut AppState, force: bool) {
+    let mut buffer: [u16; 512] = [0; 512];
+    let len = unsafe { GetWindowTextW(state.edit_hwnd, &mut buffer) };
+    let search_term = String::from_utf16_lossy(&buffer[..len as usize]);
+    let len = unsafe { GetWindowTextW(state.narrow_hwnd, &mut buffer) };
+    state.narrow_term = String::from_utf16_lossy(&buffer[..len as usize]).trim().to_string();
+
+    // 「フォルダの中へ移動」では、入力が空なら検索範囲のフォルダの中身を一覧する
+    let browsing = search_term.is_empty() && state.folder_double_click == FolderDoubleClick::NavigateScope && state.scope_path.is_some();
+    let window_title = match &state.scope_path {
+        Some(scope) if browsing => format!("{} - Migemo Everything", scope.display()),
+        _ if search_term.is_empty() => "Migemo Everything".to_string(),
+        _ => format!("{} - Migemo Everything", search_term),
+    };
+    unsafe {
+        let title_wide = str_to_wide(&window_title);
+        let _ = SetWindowTextW(state.main_hwnd, PCWSTR(title_wide.as_ptr()));
+    }
+
+    if search_term.is_empty() && !browsing {
+        // 実行中の検索の結果が後から届いても表示しない
+        state.search_generation.advance();
+        state.pending_search = None;
+        set_taskbar_busy(state, false);
+        state.pages.clear();
+        state.duplicates = None;
+        state.folder_groups = None;
+        state.pinned_rows.clear();
+        state.total_results = 0;
+        state.current_search_term.clear();
+        state.list_status = ListStatus::Idle;
+        state.last_search = None;
+        state.search_elapsed = None;
+        set_column_filters(state, ColumnFilters::default());
+        update_status_text(state);
         unsafe {
-            let _ = SetWindowTextW(state.status_hwnd, w!("Ready"));
             SendMessageW(state.listview_hwnd, LVM_SETITEMCOUNT, Some(WPARAM(0)), Some(LPARAM(0)));
             let _ = InvalidateRect(Some(state.listview_hwnd), None, true);
         }
+        update_preview(state);
+        return;
+    }
+
+    let final_search_term = if browsing {
+        String::new()
+    } else {
+        state.previous_query = search_term.clone();
+        expand_term(state, search_term)
+    };
+    let request = search_request(state, &final_search_term);
+
+    // モードを切り替えても組み立てたクエリが同じになることがある。その場合は結果もページ位置もそのまま使う
+    // インデックス作成中は同じ条件でも結果が増えるため省略しない
+    let unchanged = state.current_search_term == final_search_term && state.last_search.as_ref() == Some(&request);
+    if !force && unchanged && state.index_loaded {
+        // 入力を元に戻して実行中の検索が不要になった場合も、表示中の結果がそのまま最新になる
+        state.search_generation.advance();
+        state.pending_search = None;
+        set_taskbar_busy(state, false);
+        state.list_status = if state.total_results == 0 { ListStatus::NoResults } else { ListStatus::Idle };
+        update_status_text(state);
+        unsafe {
+            let _ = InvalidateRect(Some(state.listview_hwnd), None, true);
+        }
+        return;
+    }
+
+    // 再検索後に同じ行を選択し直すため、フォーカス行のパスを控えておく
+    let focused_path = focused_item_path(state);
+    // 正規表現の中の `size:` などは絞り込み関数として働かない
+    let column_filters = if state.search_mode.uses_regex() { ColumnFilters::default() } else { search::column_filters(&final_search_term) };
+    dispatch_search(state, PendingSearch { term: final_search_term, request, focused_path, column_filters });
+}
+
+/// ワーカースレッドで検索を開始する
+/// 実行中の検索があれば、その結果は古い世代として破棄される
+fn dispatch_search(state: &mut AppState, search: PendingSearch) {
+    let generation = state.search_generation.advance();
+    let request = search.request.clone();
+    state.pending_search = Some(search);
+    set_taskbar_busy(state, true);
+
+    // 結果が届くまで表示中の結果と件数はそのまま残す（0件のときだけ「検索中」が見える）
+    state.list_status = ListStatus::Searching;
+    unsafe {
+        let _ = InvalidateRect(Some(state.listview_hwnd), None, true);
+    }
+
+    // HWNDはスレッド間で送れないため数値として渡す
+    let hwnd_raw = state.main_hwnd.0 as isize;
+    let mut backend = state.backend;
+    // 表示上限を超える行は問い合わせない
+    let max = state.pages.page_size().min(state.max_display_results as usize) as u32;
+    let group = folder_group_request(state);
+    thread::spawn(move || {
+        let completion = run_search(&mut backend, generation, &request, max, group.as_ref());
+        let completion_ptr = Box::into_raw(Box::new(completion));
+        let posted = unsafe {
+            PostMessageW(Some(HWND(hwnd_raw as *mut c_void)), WM_SEARCH_COMPLETE, WPARAM(0), LPARAM(completion_ptr as isize))
+        };
+        if posted.is_err() {
+            // ウィンドウが既に破棄されている
+            drop(unsafe { Box::from_raw(completion_ptr) });
+        }
+    });
+}
+
+/// バックグラウンド検索の実行中、タスクバーボタンに不確定の進捗を表示する
+fn set_taskbar_busy(state: &AppState, busy: bool) {
+    let Some(taskbar) = &state.taskbar else { return };
+    let flags = if busy { TBPF_INDETERMINATE } else { TBPF_NOPROGRESS };
+    let _ = unsafe { taskbar.SetProgressState(state.main_hwnd, flags) };
+}
+
+/// WM_SEARCH_COMPLETE メッセージのハンドラ
+/// 最新の世代の結果だけを反映する。入力が変わった後に届いた古い結果は件数も含めて表示しない
+fn handle_search_complete(lparam: LPARAM, state: &mut AppState) -> LRESULT {
+    let completion = unsafe { Box::from_raw(lparam.0 as *mut SearchCompletion) };
+    if !state.search_generation.is_current(completion.generation) { return LRESULT(0); }
+    let Some(pending) = state.pending_search.take() else { return LRESULT(0) };
+    set_taskbar_busy(state, false);
+
+    let total = completion.page.total;
+    state.current_search_term = pending.term;
+    state.last_search = Some(pending.request);
+    state.total_results = total;
+    // インデックス作成中は結果が不完全なため、ステータスで知らせる
+    state.index_loaded = completion.index_loaded;
+    state.search_elapsed = Some(completion.elapsed);
+    state.pages.reset(completion.page.results);
+    state.pages.set_limit(state.display_count() as usize);
+    state.duplicates = None;
+    refresh_pinned_rows(state);
+    state.folder_groups = completion.folder_groups;
+    state.list_status = if total == 0 { ListStatus::NoResults } else { ListStatus::Idle };
+    set_column_filters(state, pending.column_filters);
+
+    update_status_text(state);
+    unsafe {
+        // 巨大な仮想件数は再描画を遅くするため、表示上限で切り詰める
+        SendMessageW(state.listview_hwnd, LVM_SETITEMCOUNT, Some(WPARAM(state.row_count())), Some(LPARAM(0)));
+        let _ = InvalidateRect(Some(state.listview_hwnd), None, true);
+    }
+    update_selection_status(state);
+    update_preview(state);
+
+    if let Some(path) = pending.focused_path {
+        if let Some(index) = find_result_index(state, &path) {
+            select_item(state, index);
+        }
+    }
+    LRESULT(0)
+}
+
+/// 入力ボックスのフルパスのファイルを選択する
+/// 表示中の結果にあればその行へ移動し、なければパスそのものを検索して、届いた結果の中で選択する
+fn go_to_entered_path(window: HWND, state: &mut AppState) {
+    let mut buffer: [u16; 512] = [0; 512];
+    let len = unsafe { GetWindowTextW(state.edit_hwnd, &mut buffer) };
+    let text = String::from_utf16_lossy(&buffer[..len as usize]);
+
+    // エクスプローラの「パスのコピー」は前後に引用符が付く
+    let path = PathBuf::from(text.trim().trim_matches('"'));
+    if !path.is_absolute() || !path.exists() {
+        flash_status(window, state, "存在するフルパスを入力してください");
+        return;
+    }
+
+    if let Some(index) = find_result_index(state, &path) {
+        select_item(state, index);
+        let _ = unsafe { SetFocus(Some(state.listview_hwnd)) };
+        return;
+    }
+
+    // Everythingは `\` を含む検索語をフルパスと照合する。検索範囲・除外条件は移動先を隠さないよう適用しない
+    let term = format!("\"{}\"", path.display());
+    let request = SearchRequest {
+        query: ComposedQuery { text: term.clone(), regex: false },
+        sort_key: state.sort_key,
+        sort_descending: state.sort_descending,
+        secondary_sort: state.secondary_sort,
+    };
+    dispatch_search(state, PendingSearch { term, request, focused_path: Some(path), column_filters: ColumnFilters::default() });
+    let _ = unsafe { SetFocus(Some(state.listview_hwnd)) };
+}
+
+/// フォーカスされている行のフルパスを取得する
+fn focused_item_path(state: &mut AppState) -> Option<PathBuf> {
+    let index = unsafe { SendMessageW(state.listview_hwnd, LVM_GETNEXTITEM, Some(WPARAM(usize::MAX)), Some(LPARAM(LVNI_FOCUSED as isize))) }.0;
+    if index < 0 { return None; }
+    state.result_at(index as usize).map(|result| PathBuf::from(&result.full_path))
+}
+
+/// 検索結果から指定パスの行を探す（先頭から RESTORE_SELECTION_MAX_PAGES ページまで）
+fn find_result_index(state: &mut AppState, path: &Path) -> Option<usize> {
+    if let Some(duplicates) = &state.duplicates {
+        return duplicates.rows().iter().position(|result| Path::new(&result.full_path) == path);
+    }
+    if let Some(index) = state.pinned_rows.iter().position(|result| Path::new(&result.full_path) == path) {
+        return Some(index);
+    }
+    let pinned_count = state.pinned_rows.len();
+    if let Some(groups) = &state.folder_groups {
+        return groups.position(|result| Path::new(&result.full_path) == path).map(|index| pinned_count + index);
+    }
+    for page in 0..RESTORE_SELECTION_MAX_PAGES {
+        let page_start = page * state.pages.page_size();
+        if page_start >= state.display_count() as usize { break; }
+
+        // 先頭行を取得してページを読み込み、ページ内を探す
+        state.result_at(pinned_count + page_start)?;
+        let found = state.pages.results().iter().position(|result| Path::new(&result.full_path) == path);
+        if let Some(local_index) = found {
+            return Some(pinned_count + state.pages.offset() + local_index);
+        }
+    }
+    None
+}
+
+/// ピン留めの有無を切り替えて保存し、表示中の結果の先頭行に反映する
+fn toggle_pin(state: &mut AppState, full_path: PathBuf) {
+    match state.pinned_paths.iter().position(|path| *path == full_path) {
+        Some(position) => { state.pinned_paths.remove(position); }
+        None => state.pinned_paths.push(full_path),
+    }
+    save_config(state);
+
+    if state.duplicates.is_some() || state.last_search.is_none() { return; }
+    refresh_pinned_rows(state);
+    unsafe {
+        SendMessageW(state.listview_hwnd, LVM_SETITEMCOUNT, Some(WPARAM(state.row_count())), Some(LPARAM(0)));
+        let _ = InvalidateRect(Some(state.listview_hwnd), None, true);
+    }
+    update_selection_status(state);
+    update_preview(state);
+}
+
+/// グループ表示が有効なら、検索結果の先頭から `FETCH_PROGRESS_THRESHOLD` 件までをフォルダごとにまとめる指定
+/// まとめるための取得は検索と一緒にワーカースレッドで行う（入力のたびにUIスレッドを止めない）
+/// 折りたたんでいたフォルダは、まとめ直した後も折りたたんだままにする
+fn folder_group_request(state: &AppState) -> Option<GroupRequest> {
+    state.group_by_folder.then(|| GroupRequest {
+        limit: (state.max_display_results as usize).min(FETCH_PROGRESS_THRESHOLD),
+        collapsed_folders: state.folder_groups.as_ref().map(FolderGroupList::collapsed_folders).unwrap_or_default(),
+    })
+}
+
+/// グループの見出しの行なら、そのグループを折りたたむ（展開する）
+fn toggle_folder_group(state: &mut AppState, index: usize) {
+    let pinned_count = state.pinned_rows.len();
+    let Some(groups) = &mut state.folder_groups else { return };
+    if index < pinned_count || !groups.toggle(index - pinned_count) { return; }
+    refresh_list_rows(state);
+}
+
+/// 行の並びが変わった後に、リストの行数と表示・ステータスを更新する
+fn refresh_list_rows(state: &mut AppState) {
+    update_status_text(state);
+    unsafe {
+        SendMessageW(state.listview_hwnd, LVM_SETITEMCOUNT, Some(WPARAM(state.row_count())), Some(LPARAM(LVSICF_NOSCROLL as isize)));
+        let _ = InvalidateRect(Some(state.listview_hwnd), None, true);
+    }
+    update_selection_status(state);
+    update_preview(state);
+}
+
+/// ピン留めしたパスのうち存在するものから、先頭に表示する行を作り直す
+fn refresh_pinned_rows(state: &mut AppState) {
+    state.pinned_rows = state.pinned_paths.iter().filter_map(|path| pinned_result(path)).collect();
+}
+
+/// ピン留めしたパスの行をファイルの属性から組み立てる（存在しなければ None）
+fn pinned_result(full_path: &Path) -> Option<FileResult> {
+    let metadata = std::fs::metadata(full_path).ok()?;
+    Some(FileResult {
+        name: full_path.file_name()?.to_string_lossy().into_owned(),
+        path: full_path.parent()?.to_string_lossy().into_owned(),
+        full_path: full_path.to_string_lossy().into_owned(),
+        size: if metadata.is_dir() { 0 } else { metadata.len() },
+        modified_date: metadata.last_write_time(),
+        created_date: metadata.creation_time(),
+        highlighted_name: String::new(),
+        highlighted_path: String::new(),
+        is_folder: metadata.is_dir(),
+    })
+}
+
+/// 指定行だけを選択・フォーカスし、見える位置までスクロールする
+fn select_item(state: &AppState, index: usize) {
+    unsafe {
+        let mut clear = LVITEMW { stateMask: LVIS_SELECTED, ..Default::default() };
+        SendMessageW(state.listview_hwnd, LVM_SETITEMSTATE, Some(WPARAM(usize::MAX)), Some(LPARAM(&mut clear as *mut _ as isize)));
+
+        let mut item = LVITEMW { stateMask: LVIS_SELECTED | LVIS_FOCUSED, state: LVIS_SELECTED | LVIS_FOCUSED, ..Default::default() };
+        SendMessageW(state.listview_hwnd, LVM_SETITEMSTATE, Some(WPARAM(index)), Some(LPARAM(&mut item as *mut _ as isize)));
+        SendMessageW(state.listview_hwnd, LVM_ENSUREVISIBLE, Some(WPARAM(index)), Some(LPARAM(0)));
+    }
+}
+
+/// 実行中のバックグラウンド検索（完了したら状態に反映する）
+struct PendingSearch {
+    /// 検索語（Migemo展開済み）
+    term: String,
+    /// 問い合わせた検索条件
+    request: SearchRequest,
+    /// 結果が届いたら選択する行のフルパス（検索開始時のフォーカス行など）
+    focused_path: Option<PathBuf>,
+    /// 検索語で使われている列の絞り込み
+    column_filters: ColumnFilters,
+}
+
+/// 検索範囲を設定して再検索する。最近使った検索範囲の先頭に加えて保存する
+fn set_scope(window: HWND, state: &mut AppState, folder: PathBuf) {
+    push_recent(&mut state.recent_scopes, folder.clone(), RECENT_SCOPES_MAX);
+    save_config(state);
+    state.scope_path = Some(folder);
+    update_status_text(state);
+    trigger_search(window);
+}
+
+/// フォルダの中へ移動する: 検索範囲をそのフォルダにし、入力を消して中身を一覧する
+/// 移動のたびに最近使った検索範囲が入れ替わらないよう、履歴には加えない
+fn navigate_to_folder(window: HWND, state: &mut AppState, folder: PathBuf) {
+    state.scope_path = Some(folder);
+    unsafe { let _ = SetWindowTextW(state.edit_hwnd, w!("")); }
+    update_status_text(state);
+    trigger_search(window);
+}
+
+/// 検索範囲を親フォルダへ移す。ドライブのルートなど親がなければ検索範囲をクリアする
+fn scope_up(window: HWND, state: &mut AppState) {
+    let Some(scope) = state.scope_path.take() else {
+        flash_status(window, state, "検索範囲が設定されていません");
+        return;
+    };
+    state.scope_path = scope.parent().map(Path::to_path_buf);
+    update_status_text(state);
+    trigger_search(window);
+}
+
+/// 最近使った検索範囲をドロップダウンボタンの下にメニューで表示する
+fn show_recent_scopes_menu(window: HWND, state: &AppState) {
+    unsafe {
+        let h_popup_menu = CreatePopupMenu().unwrap();
+        for (offset, folder) in state.recent_scopes.iter().enumerate() {
+            let label_w = str_to_wide(&folder.display().to_string().replace('&', "&&"));
+            let flags = if state.scope_path.as_ref() == Some(folder) { MF_STRING | MF_CHECKED } else { MF_STRING };
+            let _ = AppendMenuW(h_popup_menu, flags, (IDM_SCOPE_RECENT_FIRST + offset as u16) as usize, PCWSTR(label_w.as_ptr()));
+        }
+        if state.recent_scopes.is_empty() {
+            let _ = AppendMenuW(h_popup_menu, MF_STRING | MF_GRAYED, 0, w!("(最近使った検索範囲はありません)"));
+        }
+        let _ = AppendMenuW(h_popup_menu, MF_SEPARATOR, 0, None);
+        let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_SEARCH_SCOPE as usize, w!("フォルダを選択(&D)..."));
+        let clear_flags = if state.scope_path.is_some() { MF_STRING } else { MF_STRING | MF_GRAYED };
+        let _ = AppendMenuW(h_popup_menu, clear_flags, IDM_SEARCH_SCOPE_CLEAR as usize, w!("クリア(&L)"));
+
+        let mut rect = RECT::default();
+        let _ = GetWindowRect(state.recent_scopes_button_hwnd, &mut rect);
+        let cmd = TrackPopupMenu(h_popup_menu, TPM_LEFTALIGN | TPM_TOPALIGN | TPM_RETURNCMD, rect.left, rect.bottom, Some(0), window, None);
+        if cmd.as_bool() {
+            SendMessageW(window, WM_COMMAND, Some(WPARAM(cmd.0 as usize)), Some(LPARAM(0)));
+        }
+        let _ = DestroyMenu(h_popup_menu);
+    }
+}
+
+// --- DPI対応 ---
+
+/// 利用できるDPI認識の方式（新しいものほど上）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DpiSupport {
+    /// Windows 10 1703 以降: モニターごと (v2)
+    PerMonitorV2,
+    /// Windows 8.1 以降: モニターごと (shcore.dll)
+    PerMonitor,
+    /// それ以前: システムDPIのみ
+    System,
+}
+
+type SetProcessDpiAwarenessContextFn = unsafe extern "system" fn(DPI_AWARENESS_CONTEXT) -> BOOL;
+type IsValidDpiAwarenessContextFn = unsafe extern "system" fn(DPI_AWARENESS_CONTEXT) -> BOOL;
+type SetProcessDpiAwarenessFn = unsafe extern "system" fn(PROCESS_DPI_AWARENESS) -> HRESULT;
+type GetDpiForWindowFn = unsafe extern "system" fn(HWND) -> u32;
+type AdjustWindowRectExForDpiFn = unsafe extern "system" fn(*mut RECT, WINDOW_STYLE, BOOL, WINDOW_EX_STYLE, u32) -> BOOL;
+
+/// user32.dll の関数を名前で探す（古いWindowsにない関数は静的にリンクしない）
+fn user32_proc(name: PCSTR) -> FARPROC {
+    unsafe {
+        let module = GetModuleHandleW(w!("user32.dll")).ok()?;
+        GetProcAddress(module, name)
+    }
+}
+
+/// shcore.dll の関数を名前で探す（Windows 8.1 より前には存在しない）
+fn shcore_proc(name: PCSTR) -> FARPROC {
+    unsafe {
+        let module = LoadLibraryW(w!("shcore.dll")).ok()?;
+        GetProcAddress(module, name)
+    }
+}
+
+/// このWindowsで使える最も新しいDPI認識の方式を調べる
+fn detect_dpi_support() -> DpiSupport {
+    if let Some(proc) = user32_proc(s!("IsValidDpiAwarenessContext")) {
+        let is_valid: IsValidDpiAwarenessContextFn = unsafe { std::mem::transmute(proc) };
+        if unsafe { is_valid(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) }.as_bool() {
+            return DpiSupport::PerMonitorV2;
+        }
+    }
+    if shcore_proc(s!("SetProcessDpiAwareness")).is_some() {
+        return DpiSupport::PerMonitor;
+    }
+    DpiSupport::System
+}
+
+/// プロセス全体のDPI認識を設定する。失敗したら次に古い方式を試す
+fn enable_dpi_awareness(support: DpiSupport) {
+    if support == DpiSupport::PerMonitorV2 {
+        if let Some(proc) = user32_proc(s!("SetProcessDpiAwarenessContext")) {
+            let set_context: SetProcessDpiAwarenessContextFn = unsafe { std::mem::transmute(proc) };
+            if unsafe { set_context(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) }.as_bool() {
+                return;
+            }
+        }
+    }
+    if support != DpiSupport::System {
+        if let Some(proc) = shcore_proc(s!("SetProcessDpiAwareness")) {
+            let set_awareness: SetProcessDpiAwarenessFn = unsafe { std::mem::transmute(proc) };
+            if unsafe { set_awareness(PROCESS_PER_MONITOR_DPI_AWARE) }.is_ok() {
+                return;
+            }
+        }
+    }
+    let _ = unsafe { SetProcessDPIAware() };
+}
+
+/// ウィンドウのDPI（GetDpiForWindow がなければ画面のDPI）
+fn window_dpi(window: HWND) -> u32 {
+    if let Some(proc) = user32_proc(s!("GetDpiForWindow")) {
+        let get_dpi: GetDpiForWindowFn = unsafe { std::mem::transmute(proc) };
+        let dpi = unsafe { get_dpi(window) };
+        if dpi != 0 { return dpi; }
+    }
+    unsafe {
+        let hdc = GetDC(Some(window));
+        let dpi = GetDeviceCaps(Some(hdc), LOGPIXELSX);
+        ReleaseDC(Some(window), hdc);
+        if dpi > 0 { dpi as u32 } else { 96 }
+    }
+}
+
+/// 指定DPIでのクライアント領域からウィンドウ領域を求める（古いWindowsではDPIを考慮しない版を使う）
+fn adjust_window_rect_for_dpi(rect: &mut RECT, style: WINDOW_STYLE, ex_style: WINDOW_EX_STYLE, dpi: u32) {
+    if let Some(proc) = user32_proc(s!("AdjustWindowRectExForDpi")) {
+        let adjust: AdjustWindowRectExForDpiFn = unsafe { std::mem::transmute(proc) };
+        let _ = unsafe { adjust(rect, style, true.into(), ex_style, dpi) };
         return;
     }
+    let _ = unsafe { AdjustWindowRectEx(rect, style, true, ex_style) };
+}
 
-    let final_search_term = if state.migemo_enabled { migemo_query(&search_term, &state.migemo_dict).unwrap_or(search_term) } else { search_term };
+// --- 通知領域 ---
 
-    if state.current_search_term != final_search_term {
-        state.search_results.lock().unwrap().clear();
-        state.current_search_term = final_search_term.clone();
-        state.current_page_offset = 0;
+/// 通知領域のアイコンを識別する NOTIFYICONDATAW
+fn tray_icon_data(window: HWND) -> NOTIFYICONDATAW {
+    NOTIFYICONDATAW {
+        cbSize: std::mem::size_of::<NOTIFYICONDATAW>() as u32,
+        hWnd: window,
+        uID: TRAY_ICON_ID,
+        ..Default::default()
     }
+}
 
-    let mut guard = global().lock().unwrap();
-    let mut searcher = guard.searcher();
-    
-    searcher.set_search(&final_search_term);
-    searcher.set_regex(state.regex_enabled || state.migemo_enabled);
-    searcher.set_request_flags(
-        RequestFlags::EVERYTHING_REQUEST_FILE_NAME | RequestFlags::EVERYTHING_REQUEST_PATH |
-        RequestFlags::EVERYTHING_REQUEST_SIZE | RequestFlags::EVERYTHING_REQUEST_DATE_MODIFIED |
-        RequestFlags::EVERYTHING_REQUEST_ATTRIBUTES |
-        RequestFlags::EVERYTHING_REQUEST_HIGHLIGHTED_FILE_NAME |
-        RequestFlags::EVERYTHING_REQUEST_HIGHLIGHTED_PATH
-    );
-
-    let query_results = searcher.set_max(100).query();
-    state.total_results = query_results.total();
-
-    let mut initial_results = Vec::new();
-    for item in query_results.iter() {
-        initial_results.push(FileResult {
-            name: item.filename().unwrap_or_default().to_string_lossy().to_string(),
-            path: item.path().unwrap_or_default().to_string_lossy().to_string(),
-            size: item.size().unwrap_or(0),
-            modified_date: item.date_modified().unwrap_or(0),
-            highlighted_name: item.highlighted_filename().unwrap_or_default().to_string_lossy().to_string(),
-            highlighted_path: item.highlighted_path().unwrap_or_default().to_string_lossy().to_string(),
-            is_folder: item.is_folder(),
+/// 通知領域にアプリケーションのアイコンを追加する
+fn add_tray_icon(window: HWND) {
+    let mut data = tray_icon_data(window);
+    data.uFlags = NIF_MESSAGE | NIF_ICON | NIF_TIP;
+    data.uCallbackMessage = WM_TRAY_ICON;
+    data.hIcon = HICON(unsafe { GetClassLongPtrW(window, GCLP_HICON) } as *mut c_void);
+    let tip: Vec<u16> = "Migemo Everything".encode_utf16().collect();
+    data.szTip[..tip.len()].copy_from_slice(&tip);
+    let _ = unsafe { Shell_NotifyIconW(NIM_ADD, &data) };
+}
+
+/// 通知領域のアイコンを削除する
+fn remove_tray_icon(window: HWND) {
+    let _ = unsafe { Shell_NotifyIconW(NIM_DELETE, &tray_icon_data(window)) };
+}
+
+/// 通知領域へ格納したウィンドウを元に戻して前面に出す
+fn restore_from_tray(window: HWND) {
+    unsafe {
+        let _ = ShowWindow(window, SW_RESTORE);
+        let _ = SetForegroundWindow(window);
+    }
+}
+
+/// 通知領域のアイコンの右クリックメニューを表示する
+fn show_tray_menu(window: HWND) {
+    unsafe {
+        let h_popup_menu = CreatePopupMenu().unwrap();
+        let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_TRAY_SHOW as usize, w!("表示(&S)"));
+        let _ = AppendMenuW(h_popup_menu, MF_SEPARATOR, 0, None);
+        let _ = AppendMenuW(h_popup_menu, MF_STRING, IDM_FILE_EXIT as usize, w!("終了(&X)"));
+        let _ = SetMenuDefaultItem(h_popup_menu, IDM_TRAY_SHOW as u32, 0);
+
+        let mut point = POINT::default();
+        let _ = GetCursorPos(&mut point);
+        // 前面にしておかないと、メニューの外をクリックしてもメニューが閉じない
+        let _ = SetForegroundWindow(window);
+        let cmd = TrackPopupMenu(h_popup_menu, TPM_RIGHTBUTTON | TPM_RETURNCMD, point.x, point.y, Some(0), window, None);
+        let _ = PostMessageW(Some(window), WM_NULL, WPARAM(0), LPARAM(0));
+        if cmd.as_bool() {
+            SendMessageW(window, WM_COMMAND, Some(WPARAM(cmd.0 as usize)), Some(LPARAM(0)));
+        }
+        let _ = DestroyMenu(h_popup_menu);
+    }
+}
+
+/// 結果に含めるアイテムの種類を変更し、保存して再検索する
+fn set_item_kind(window: HWND, state: &mut AppState, kind: ItemKind) {
+    if state.item_kind == kind { return; }
+    state.item_kind = kind;
+    save_config(state);
+    update_ui_states(state);
+    trigger_search(window);
+}
+
+/// 検索語と現在の検索オプションから、1回の検索の条件一式を作る
+fn search_request(state: &AppState, term: &str) -> SearchRequest {
+    SearchRequest {
+        query: compose_query(state, term),
+        sort_key: state.sort_key,
+        sort_descending: state.sort_descending,
+        secondary_sort: state.secondary_sort,
+    }
+}
+
+/// 入力した語を検索モードに合わせてMigemoで展開する（辞書がなければそのまま）
+fn expand_term(state: &AppState, term: String) -> String {
+    match (state.search_mode, state.migemo_dict.as_ref()) {
+        (SearchMode::Migemo, Some(dict)) => search::migemo_query(&term, dict),
+        (SearchMode::MigemoRegex, Some(dict)) => search::migemo_regex_query(&term, dict),
+        _ => term,
+    }
+}
+
+/// 検索語（Migemo展開済み）と現在の検索条件から、Everythingに渡すクエリを組み立てる
+/// 実際の検索とクエリ表示は必ずこの関数を通すこと
+fn compose_query(state: &AppState, term: &str) -> ComposedQuery {
+    let mut clauses = search::filter_clauses(state.scope_path.as_deref(), &state.exclude_paths, state.item_kind);
+    // 結果内の絞り込みは検索語と同じモードで展開し、AND結合する条件として足す
+    if !state.narrow_term.is_empty() {
+        let narrow_term = expand_term(state, state.narrow_term.clone());
+        clauses.push(match term_clause(state, &narrow_term) {
+            (narrow_term, true) => search::regex_term(&narrow_term),
+            (narrow_term, false) => narrow_term,
         });
     }
+    // 検索語が空なのはフォルダの中を一覧するときだけ（`perform_search` を参照）
+    if term.is_empty() {
+        if let Some(scope) = &state.scope_path {
+            clauses.push(search::children_clause(scope));
+        }
+        return search::compose_query("", false, &clauses);
+    }
+    let (term, regex) = term_clause(state, term);
+    search::compose_query(&term, regex, &clauses)
+}
 
-    state.current_page_offset = 0;
-    *state.search_results.lock().unwrap() = initial_results;
+/// 検索語（Migemo展開済み）を検索モードと検索オプションに合わせて、Everythingの1つの条件にする
+/// 修飾子を付けない正規表現はそのまま返し（2番目が true）、Everythingの正規表現フラグで検索させる
+fn term_clause(state: &AppState, term: &str) -> (String, bool) {
+    let regex = state.search_mode.uses_regex();
+    let term = match state.search_mode {
+        SearchMode::Wildcard => search::wildcard_term(term),
+        SearchMode::Plain if state.literal_search => search::literal_term(term),
+        _ => term.to_string(),
+    };
+    let match_path = state.migemo_match_path && state.search_mode.uses_migemo();
+    if regex && !state.match_diacritics && !match_path {
+        return (term, true);
+    }
+    let term = search::diacritics_term(&term, regex, state.match_diacritics);
+    (if match_path { search::path_term(&term) } else { term }, false)
+}
 
-    let status_text = format!("{} items found", state.total_results);
+/// 結果内の絞り込み欄を表示/非表示にする
+/// 表示するときは欄へ移動し、閉じるときは入力を消して絞り込む前の結果に戻す
+fn show_narrow_box(window: HWND, state: &mut AppState, show: bool) {
+    if state.show_narrow_box != show {
+        state.show_narrow_box = show;
+        save_config(state);
+        update_ui_states(state);
+        let mut client = RECT::default();
+        let _ = unsafe { GetClientRect(window, &mut client) };
+        layout_controls(client.right, client.bottom, state);
+    }
     unsafe {
-        let _ = SetWindowTextW(state.status_hwnd, PCWSTR(str_to_wide(&status_text).as_ptr()));
-        SendMessageW(state.listview_hwnd, LVM_SETITEMCOUNT, Some(WPARAM(state.total_results as usize)), Some(LPARAM(0)));
-        let _ = InvalidateRect(Some(state.listview_hwnd), None, true);
+        if show {
+            let _ = SetFocus(Some(state.narrow_hwnd));
+            SendMessageW(state.narrow_hwnd, EM_SETSEL, Some(WPARAM(0)), Some(LPARAM(-1)));
+        } else {
+            let _ = SetFocus(Some(state.edit_hwnd));
+            if GetWindowTextLengthW(state.narrow_hwnd) > 0 {
+                // 非表示のまま絞り込みが効き続けないよう消して、すぐに検索し直す
+                let _ = SetWindowTextW(state.narrow_hwnd, w!(""));
+                trigger_search(window);
+            }
+        }
     }
 }
 
-/// 指定されたアイテムインデックスのデータが利用可能かを確認し、必要に応じて読み込む
-fn ensure_data_available(state: &mut AppState, item_index: usize) {
-    if state.current_search_term.is_empty() { return; }
-    
-    let page_start = (item_index / state.page_size) * state.page_size;
-    
-    if state.current_page_offset == page_start {
-        let results = state.search_results.lock().unwrap();
-        let local_index = item_index - page_start;
-        if local_index < results.len() { return; }
+/// 種類で絞り込みメニューのID
+fn item_kind_menu_id(kind: ItemKind) -> u16 {
+    IDM_SEARCH_KIND_FIRST + ItemKind::ALL.iter().position(|&k| k == kind).unwrap_or(0) as u16
+}
+
+/// ダブルクリックの動作メニューのID
+fn double_click_menu_id(action: DoubleClickAction) -> u16 {
+    IDM_VIEW_DOUBLE_CLICK_FIRST + DoubleClickAction::ALL.iter().position(|&a| a == action).unwrap_or(0) as u16
+}
+
+/// 並べ替えメニューのID
+fn sort_menu_id(key: SortKey) -> u16 {
+    IDM_VIEW_SORT_FIRST + SortKey::ALL.iter().position(|&k| k == key).unwrap_or(0) as u16
+}
+
+/// 同じ値の並べ替えメニューのID（先頭が「なし」）
+fn secondary_sort_menu_id(key: Option<SortKey>) -> u16 {
+    let position = key.and_then(|key| SortKey::SECONDARY.iter().position(|&k| k == key)).map_or(0, |i| i + 1);
+    IDM_VIEW_SECONDARY_SORT_FIRST + position as u16
+}
+
+/// ステータスバーの指定ペインに文字列を表示する
+fn set_status_pane(state: &AppState, pane: usize, text: &str) {
+    let text_w = str_to_wide(text);
+    unsafe {
+        SendMessageW(state.status_hwnd, SB_SETTEXTW, Some(WPARAM(pane)), Some(LPARAM(text_w.as_ptr() as isize)));
     }
-    
-    load_page(state, page_start);
 }
 
-/// 指定されたオフセットからページサイズ分のデータを読み込む
-fn load_page(state: &mut AppState, offset: usize) {
-    if state.current_search_term.is_empty() { return; }
-    
-    let mut guard = global().lock().unwrap();
-    let mut searcher = guard.searcher();
-    
-    searcher.set_search(&state.current_search_term);
-    searcher.set_regex(state.regex_enabled || state.migemo_enabled);
-    searcher.set_offset(offset as u32);
-    searcher.set_max(state.page_size as u32);
-    searcher.set_request_flags(
-        RequestFlags::EVERYTHING_REQUEST_FILE_NAME | RequestFlags::EVERYTHING_REQUEST_PATH |
-        RequestFlags::EVERYTHING_REQUEST_SIZE | RequestFlags::EVERYTHING_REQUEST_DATE_MODIFIED |
-        RequestFlags::EVERYTHING_REQUEST_ATTRIBUTES |
-        RequestFlags::EVERYTHING_REQUEST_HIGHLIGHTED_FILE_NAME |
-        RequestFlags::EVERYTHING_REQUEST_HIGHLIGHTED_PATH
-    );
-
-    let query_results = searcher.query();
-    let mut new_results = Vec::new();
-    
-    for item in query_results.iter() {
-        new_results.push(FileResult {
-            name: item.filename().unwrap_or_default().to_string_lossy().to_string(),
-            path: item.path().unwrap_or_default().to_string_lossy().to_string(),
-            size: item.size().unwrap_or(0),
-            modified_date: item.date_modified().unwrap_or(0),
-            highlighted_name: item.highlighted_filename().unwrap_or_default().to_string_lossy().to_string(),
-            highlighted_path: item.highlighted_path().unwrap_or_default().to_string_lossy().to_string(),
-            is_folder: item.is_folder(),
-        });
+/// 検索結果の件数・検索モード・絞り込み条件をステータスバーに表示する
+fn update_status_text(state: &AppState) {
+    let mut count_text = if state.last_search.is_none() {
+        "Ready".to_string()
+    } else if let Some(duplicates) = &state.duplicates {
+        format!("重複: {}グループ ({}件)", duplicates.group_count(), format_with_commas(duplicates.rows().len() as u64))
+    } else if let Some(groups) = state.folder_groups.as_ref().filter(|groups| groups.result_count() < state.total_results as usize) {
+        format!("最初の{}件を{}フォルダに分けて表示 (全{}件)", format_with_commas(groups.result_count() as u64), format_with_commas(groups.group_count() as u64), format_with_commas(state.total_results as u64))
+    } else if let Some(groups) = &state.folder_groups {
+        format!("{} items found ({}フォルダ)", format_with_commas(state.total_results as u64), format_with_commas(groups.group_count() as u64))
+    } else if state.total_results > state.max_display_results {
+        format!("最初の{}件を表示 (全{}件)", format_with_commas(state.max_display_results as u64), format_with_commas(state.total_results as u64))
+    } else {
+        format!("{} items found", format_with_commas(state.total_results as u64))
+    };
+    // 描画ではなくEverythingへの問い合わせにかかった時間（重複の一覧には当てはまらない）
+    if let Some(elapsed) = state.search_elapsed.filter(|_| state.duplicates.is_none()) {
+        count_text.push_str(&format!(" ({} ms)", format_with_commas(elapsed.as_millis() as u64)));
     }
-    
-    state.current_page_offset = offset;
-    *state.search_results.lock().unwrap() = new_results;
+    if state.last_search.is_some() && !state.index_loaded {
+        count_text.push_str(" (インデックス作成中)");
+    }
+    set_status_pane(state, STATUS_PANE_COUNT, &count_text);
+
+    set_status_pane(state, STATUS_PANE_MODE, state.search_mode.label());
+
+    let mut filters = Vec::new();
+    if !state.live_search {
+        filters.push("ライブ検索OFF (Enterで検索)".to_string());
+    }
+    if state.item_kind != ItemKind::All {
+        filters.push(state.item_kind.label().to_string());
+    }
+    if state.match_diacritics {
+        filters.push("アクセント区別".to_string());
+    }
+    if state.migemo_match_path && state.search_mode.uses_migemo() {
+        filters.push("Migemo: パス全体".to_string());
+    }
+    if !state.narrow_term.is_empty() {
+        filters.push(format!("絞り込み: {}", state.narrow_term));
+    }
+    if let Some(secs) = state.auto_refresh_secs {
+        filters.push(format!("自動更新: {}秒", secs));
+    }
+    filters.push(format!("並べ替え: {} ({})", state.sort_key.label(), if state.sort_descending { "降順" } else { "昇順" }));
+    if let Some(scope) = &state.scope_path {
+        filters.push(format!("範囲: {}", scope.display()));
+    }
+    if !state.exclude_paths.is_empty() {
+        filters.push(format!("除外: {}件", state.exclude_paths.len()));
+    }
+    set_status_pane(state, STATUS_PANE_FILTERS, &filters.join("  "));
+}
+
+/// ステータスバーに一時的なメッセージを表示し、数秒後に選択状況の表示に戻す
+fn flash_status(window: HWND, state: &AppState, text: &str) {
+    set_status_pane(state, STATUS_PANE_SELECTION, text);
+    unsafe { SetTimer(Some(window), STATUS_FLASH_TIMER_ID, 2000, None) };
+}
+
+/// 選択中の件数をステータスバーに表示する
+fn update_selection_status(state: &AppState) {
+    let selected = unsafe { SendMessageW(state.listview_hwnd, LVM_GETSELECTEDCOUNT, None, None) }.0;
+    let text = if selected > 0 { format!("{} 件選択", selected) } else { String::new() };
+    set_status_pane(state, STATUS_PANE_SELECTION, &text);
+}
+
+/// プレビュー欄の高さ（96DPI基準、非表示なら0）
+fn preview_height(state: &AppState) -> i32 {
+    match (state.show_preview, state.preview_content) {
+        (false, _) => 0,
+        (true, false) => PREVIEW_HEIGHT,
+        (true, true) => PREVIEW_CONTENT_HEIGHT,
+    }
+}
+
+/// ワーカースレッドで読み込んだプレビュー欄の内容
+struct PreviewLoaded {
+    /// 読み込みを始めたときの世代番号
+    generation: u64,
+    /// フルパスと属性に続けてファイルの先頭部分を付けた、プレビュー欄に表示する文字列
+    text: String,
+}
+
+/// プレビュー欄にフォーカス行のフルパスと属性を表示する（非表示のときは何もしない）
+/// 内容の表示が有効でテキストファイルなら、先頭部分をワーカースレッドで読み込んで後から付け加える
+fn update_preview(state: &mut AppState) {
+    if !state.show_preview { return; }
+    // 前の行の読み込みは、まだ始まっていなければ読まずに、届いても表示せずに捨てる
+    let generation = state.preview_generation.fetch_add(1, Ordering::SeqCst) + 1;
+    let index = unsafe { SendMessageW(state.listview_hwnd, LVM_GETNEXTITEM, Some(WPARAM(usize::MAX)), Some(LPARAM(LVNI_FOCUSED as isize))) }.0;
+    let result = if index >= 0 { state.result_at(index as usize) } else { None };
+    let header = result.as_ref().map(preview_text).unwrap_or_default();
+    set_preview_text(state, &header);
+
+    let Some(result) = result else { return };
+    if !state.preview_content || result.is_folder || result.size > MAX_PREVIEW_FILE_SIZE || !is_text_file_name(&result.name) { return; }
+    let full_path = PathBuf::from(&result.full_path);
+    let latest_generation = Arc::clone(&state.preview_generation);
+    // HWNDはスレッド間で送れないため数値として渡す
+    let hwnd_raw = state.main_hwnd.0 as isize;
+    thread::spawn(move || {
+        if latest_generation.load(Ordering::SeqCst) != generation { return; }
+        let content = read_preview_content(&full_path);
+        if latest_generation.load(Ordering::SeqCst) != generation { return; }
+        let loaded = Box::into_raw(Box::new(PreviewLoaded { generation, text: format!("{}\r\n\r\n{}", header, content) }));
+        let posted = unsafe { PostMessageW(Some(HWND(hwnd_raw as *mut c_void)), WM_PREVIEW_LOADED, WPARAM(0), LPARAM(loaded as isize)) };
+        if posted.is_err() {
+            // ウィンドウが既に破棄されている
+            drop(unsafe { Box::from_raw(loaded) });
+        }
+    });
+}
+
+/// WM_PREVIEW_LOADED メッセージのハンドラ
+/// フォーカス行が変わった後に届いた古い内容は表示しない
+fn handle_preview_loaded(lparam: LPARAM, state: &AppState) -> LRESULT {
+    let loaded = unsafe { Box::from_raw(lparam.0 as *mut PreviewLoaded) };
+    if state.show_preview && state.preview_generation.load(Ordering::SeqCst) == loaded.generation {
+        set_preview_text(state, &loaded.text);
+    }
+    LRESULT(0)
+}
+
+/// プレビュー欄の文字列を置き換える
+fn set_preview_text(state: &AppState, text: &str) {
+    let text_w = str_to_wide(text);
+    let _ = unsafe { SetWindowTextW(state.preview_hwnd, PCWSTR(text_w.as_ptr())) };
+}
+
+/// ファイルの先頭 PREVIEW_BYTES バイトを文字列にする
+/// UTF-8/UTF-16 として読めなければ Shift_JIS として読み、NULを含むものはバイナリとして内容を出さない
+fn read_preview_content(full_path: &Path) -> String {
+    let mut bytes = Vec::with_capacity(PREVIEW_BYTES);
+    let read = std::fs::File::open(full_path).and_then(|file| file.take(PREVIEW_BYTES as u64).read_to_end(&mut bytes));
+    if read.is_err() { return "(内容を読み込めません)".to_string(); }
+    match decode_text(&bytes) {
+        DecodedText::Text(text) => to_crlf(&text),
+        DecodedText::Binary => "(バイナリファイルのため内容を表示しません)".to_string(),
+        DecodedText::Other => to_crlf(&decode_shift_jis(&bytes)),
+    }
+}
+
+/// Shift_JIS（コードページ932）のバイト列を文字列にする
+fn decode_shift_jis(bytes: &[u8]) -> String {
+    unsafe {
+        let len = MultiByteToWideChar(SHIFT_JIS_CODE_PAGE, MULTI_BYTE_TO_WIDE_CHAR_FLAGS(0), bytes, None);
+        if len <= 0 { return String::new(); }
+        let mut buffer = vec![0u16; len as usize];
+        let len = MultiByteToWideChar(SHIFT_JIS_CODE_PAGE, MULTI_BYTE_TO_WIDE_CHAR_FLAGS(0), bytes, Some(&mut buffer));
+        String::from_utf16_lossy(&buffer[..len.max(0) as usize])
+    }
+}
+
+/// プレビュー欄に表示する文字列（1行目にフルパス、2行目に種類・サイズと日時）
+fn preview_text(result: &FileResult) -> String {
+    let kind = if result.is_folder { "フォルダ".to_string() } else { format!("{} バイト", format_with_commas(result.size)) };
+    format!(
+        "{}\r\n{}    更新日時: {}    作成日時: {}",
+        result.full_path,
+        kind,
+        format_date(result.modified_date),
+        format_date(result.created_date),
+    )
+}
+
+/// 選択中の行のインデックスを昇順で返す
+fn selected_indices(state: &AppState) -> Vec<usize> {
+    let mut indices = Vec::new();
+    let mut index = -1isize;
+    loop {
+        index = unsafe { SendMessageW(state.listview_hwnd, LVM_GETNEXTITEM, Some(WPARAM(index as usize)), Some(LPARAM(LVNI_SELECTED as isize))) }.0;
+        if index < 0 { break; }
+        indices.push(index as usize);
+    }
+    indices
+}
+
+/// エクスポート対象の検索結果を集める
+/// 選択行があればその行だけ、なければ全件をページ単位で取得する
+/// 全件の取得を中断した場合は None を返す
+fn collect_results_for_export(window: HWND, state: &mut AppState) -> Option<Vec<FileResult>> {
+    if state.last_search.is_none() { return Some(Vec::new()); }
+
+    let indices = selected_indices(state);
+    if !indices.is_empty() {
+        return Some(indices.into_iter().filter_map(|index| state.result_at(index)).collect());
+    }
+    if let Some(duplicates) = &state.duplicates {
+        return Some(duplicates.rows().to_vec());
+    }
+    fetch_results(window, state, state.total_results as usize)
+}
+
+/// 表示中の検索結果を先頭から最大 `limit` 件取得する
+/// 表示中のページを置き換えないよう、キャッシュを通さずに直接取得する
+/// 件数が多い場合はワーカースレッドで取得して進捗ダイアログを表示し、中断された場合は None を返す
+fn fetch_results(window: HWND, state: &mut AppState, limit: usize) -> Option<Vec<FileResult>> {
+    let Some(request) = &state.last_search else { return Some(Vec::new()) };
+    let page_size = state.pages.page_size();
+    if limit <= FETCH_PROGRESS_THRESHOLD {
+        let _wait_cursor = WaitCursor::show();
+        return fetch_all(&mut state.backend, request, page_size, limit, |_| true);
+    }
+    fetch_with_progress_dialog(window, state.backend, request.clone(), page_size, limit)
+}
+
+/// UIスレッドで同期的に問い合わせる間、待機カーソルを表示する
+/// 破棄されたときに元のカーソルへ戻すため、途中で return しても戻し忘れない
+struct WaitCursor {
+    previous: HCURSOR,
+}
+
+impl WaitCursor {
+    fn show() -> Self {
+        let wait = unsafe { LoadCursorW(None, IDC_WAIT) }.ok();
+        Self { previous: unsafe { SetCursor(wait) } }
+    }
+}
+
+impl Drop for WaitCursor {
+    fn drop(&mut self) {
+        unsafe { SetCursor(Some(self.previous)) };
+    }
+}
+
+/// 表示中の検索結果（表示上限まで）から重複ファイルを探し、グループごとにリストに表示する
+/// サイズ（`match_name` なら名前も）が同じファイルを、先頭部分の内容で確かめてからまとめる
+fn show_duplicates(window: HWND, state: &mut AppState, match_name: bool) {
+    if state.last_search.is_none() {
+        flash_status(window, state, "先に検索してください");
+        return;
+    }
+
+    set_status_pane(state, STATUS_PANE_COUNT, "重複を検索中…");
+    let Some(list) = find_duplicates_with_progress_dialog(window, state, match_name) else {
+        update_status_text(state);
+        return;
+    };
+    let row_count = list.rows().len();
+    state.duplicates = Some(list);
+    state.folder_groups = None;
+
+    update_status_text(state);
+    unsafe {
+        SendMessageW(state.listview_hwnd, LVM_SETITEMCOUNT, Some(WPARAM(row_count)), Some(LPARAM(0)));
+        let _ = InvalidateRect(Some(state.listview_hwnd), None, true);
+    }
+    update_selection_status(state);
+    update_preview(state);
+}
+
+/// 表示中の検索結果の取得と、同じサイズのファイルの読み込みをワーカースレッドで行い、その間は進捗ダイアログを表示する
+/// ネットワークドライブなどで読み込みが遅くてもUIを止めず、キャンセルできるようにする。キャンセルされたら None
+fn find_duplicates_with_progress_dialog(window: HWND, state: &AppState, match_name: bool) -> Option<DuplicateList> {
+    let request = state.last_search.clone()?;
+    let mut backend = state.backend;
+    let page_size = state.pages.page_size();
+    let limit = state.display_count() as usize;
+    run_with_progress_dialog(window, w!("重複を検索中"), limit, move |report: ProgressReport<'_>| {
+        let results = fetch_all(&mut backend, &request, page_size, limit, |fetched| report(fetched, limit))?;
+        let candidates = duplicates::group_by_size(results, match_name);
+        let total = candidates.iter().map(Vec::len).sum();
+        let mut compared = 0;
+        let mut cancelled = false;
+        let groups = duplicates::split_by_content(candidates, |result| {
+            // キャンセルされたら残りのファイルは読まない
+            if cancelled { return None; }
+            compared += 1;
+            cancelled = !report(compared, total);
+            duplicates::prefix_fingerprint(Path::new(&result.full_path))
+        });
+        (!cancelled).then(|| DuplicateList::new(groups))
+    })
 }
 
 // --- シェルコンテキストメニュー関連 ---
 
 /// シェルのコンテキストメニューを表示する
-fn show_shell_context_menu(owner: HWND, listview_hwnd: HWND, full_path: &Path, point: POINT) {
+/// パスを解決できないなどでメニューを取得できなかった場合は `Unavailable` を返す
+fn show_shell_context_menu(owner: HWND, listview_hwnd: HWND, full_path: &Path, point: POINT) -> ShellMenuOutcome {
+    let mut outcome = ShellMenuOutcome::Unavailable;
     if let Ok((shell_folder, _pidl_absolute, pidl_relative)) = get_shell_folder_and_pidl(full_path) {
         let context_menu: Result<IContextMenu> = unsafe { shell_folder.GetUIObjectOf(owner, &[pidl_relative], None) };
 
         if let Ok(context_menu) = context_menu {
             if let Ok(hmenu) = unsafe { CreatePopupMenu() } {
                 if unsafe { context_menu.QueryContextMenu(hmenu, 0, 1, 0x7FFF, CMF_NORMAL) }.is_ok() {
+                    outcome = ShellMenuOutcome::Dismissed;
                     let mut pt = point;
                     let _ = unsafe { ClientToScreen(listview_hwnd, &mut pt) };
 
@@ -1053,8 +4908,9 @@ fn show_shell_context_menu(owner: HWND, listview_hwnd: HWND, full_path: &Path, p
                             ..Default::default()
                         };
 
-                        if let Err(e) = unsafe { context_menu.InvokeCommand(&ci) } {
-                            eprintln!("InvokeCommand failed: {:?}", e);
+                        match unsafe { context_menu.InvokeCommand(&ci) } {
+                            Ok(()) => outcome = ShellMenuOutcome::Invoked,
+                            Err(e) => eprintln!("InvokeCommand failed: {:?}", e),
                         }
                     }
                 }
@@ -1062,17 +4918,210 @@ fn show_shell_context_menu(owner: HWND, listview_hwnd: HWND, full_path: &Path, p
             }
         }
     }
+    outcome
 }
 
-/// ファイルパスからIShellFolderと相対PIDLを取得する
-fn get_shell_folder_and_pidl(path: &Path) -> Result<(IShellFolder, OwningPidl, *const ITEMIDLIST)> {
-    let path_wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
-    let mut pidl_absolute = OwningPidl::new();
+/// `show_shell_context_menu` の結果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ShellMenuOutcome {
+    /// パスを解決できずメニューを表示できなかった
+    Unavailable,
+    /// メニューを閉じた（コマンドを選ばなかった、または実行に失敗した）
+    Dismissed,
+    /// コマンドを実行した
+    Invoked,
+}
+
+/// エクスプローラでアイテムの親フォルダを開き、アイテムを選択する
+/// 既存のエクスプローラウィンドウを再利用できるよう SHOpenFolderAndSelectItems を使い、
+/// PIDLを解決できない場合は explorer.exe /select にフォールバックする
+/// ドライブ直下やUNCパスで区切りが重なっているとエクスプローラで選択できないため、どちらにも区切りを正規化したパスを渡す
+/// ネットワークパスの解決に時間がかかることがあるため、UIスレッド以外から呼ぶこと
+fn reveal_in_explorer(full_path: &Path) {
+    let full_path = full_path.to_string_lossy();
+    let normalized = normalize_windows_path(&full_path);
+    unsafe {
+        // シェルAPIを呼ぶスレッドでもCOMの初期化が必要
+        let com_initialized = CoInitializeEx(None, COINIT_APARTMENTTHREADED).is_ok();
+
+        let revealed = parse_display_name(Path::new(&normalized))
+            .and_then(|pidl| SHOpenFolderAndSelectItems(pidl.as_ptr(), None, 0))
+            .is_ok();
+        if !revealed {
+            let params = explorer_select_parameter(&full_path);
+            let params_w = str_to_wide(&params);
+            ShellExecuteW(None, w!("open"), w!("explorer.exe"), PCWSTR(params_w.as_ptr()), None, SW_SHOW);
+        }
+
+        if com_initialized { CoUninitialize(); }
+    }
+}
+
+/// 「フォルダを開く」を選択中の項目（選択がなければ右クリックした行）に対して行う
+/// 1件なら親フォルダを開いて選択する。複数ならフォルダはそのフォルダ自体を開き、ファイルは親フォルダで選択する
+fn open_selected_folders(window: HWND, state: &mut AppState, clicked_index: usize) {
+    let mut indices = selected_indices(state);
+    if indices.is_empty() { indices.push(clicked_index); }
+    let items: Vec<(PathBuf, bool)> = indices
+        .into_iter()
+        .filter_map(|index| state.result_at(index))
+        .map(|result| (PathBuf::from(&result.full_path), result.is_folder))
+        .collect();
+    // エクスプローラを開く処理はどれもワーカースレッドで行い、応答の遅いネットワークパスでUIを止めない
+    match items.as_slice() {
+        [] => return,
+        [(full_path, _)] => {
+            let full_path = full_path.clone();
+            thread::spawn(move || reveal_in_explorer(&full_path));
+            return;
+        }
+        _ => {}
+    }
+
+    if items.len() >= OPEN_FOLDERS_CONFIRM_COUNT {
+        let text_w = str_to_wide(&format!("{} 個のフォルダを開きます。よろしいですか？", items.len()));
+        let answer = unsafe { MessageBoxW(Some(window), PCWSTR(text_w.as_ptr()), w!("確認"), MB_OKCANCEL | MB_ICONQUESTION) };
+        if answer != IDOK { return; }
+    }
+    for (full_path, is_folder) in items {
+        if is_folder {
+            let path_w = str_to_wide(&normalize_windows_path(&full_path.to_string_lossy()));
+            thread::spawn(move || unsafe {
+                ShellExecuteW(None, w!("open"), PCWSTR(path_w.as_ptr()), None, None, SW_SHOW);
+            });
+        } else {
+            thread::spawn(move || reveal_in_explorer(&full_path));
+        }
+    }
+}
+
+/// 「送る」のコマンドを、選択した行（選択がなければクリックした行）のアイテムで実行する
+/// アイテムごとに実行するコマンドは、実行する回数が多ければ先に確認する
+fn run_send_to(window: HWND, state: &mut AppState, command_index: usize, clicked_index: usize) {
+    let Some(command) = state.send_to_commands.get(command_index).cloned() else { return };
+    let mut indices = selected_indices(state);
+    if indices.is_empty() { indices.push(clicked_index); }
+    let paths: Vec<PathBuf> = indices
+        .into_iter()
+        .filter_map(|index| state.result_at(index))
+        .map(|result| PathBuf::from(&result.full_path))
+        .collect();
+    let lines = command.command_lines(&paths.iter().map(PathBuf::as_path).collect::<Vec<_>>());
+    if lines.is_empty() { return; }
+
+    if lines.len() >= SEND_TO_CONFIRM_COUNT {
+        let text_w = str_to_wide(&format!("「{}」を {} 回実行します。よろしいですか？", command.name, lines.len()));
+        let answer = unsafe { MessageBoxW(Some(window), PCWSTR(text_w.as_ptr()), w!("確認"), MB_OKCANCEL | MB_ICONQUESTION) };
+        if answer != IDOK { return; }
+    }
+    // 作業フォルダは先頭のアイテムのあるフォルダにする
+    let directory = paths.first().and_then(|path| path.parent()).map(Path::to_path_buf).unwrap_or_default();
+    thread::spawn(move || {
+        for line in lines {
+            run_command_line(&line, &directory);
+        }
+    });
+}
+
+/// コマンドラインを ShellExecuteW で実行する
+/// PATH やアプリの登録から探されるため、`code` のようにプログラム名だけでも起動できる
+/// 起動できなければ ShellExecuteW がエラーのダイアログを表示する
+fn run_command_line(line: &str, directory: &Path) {
+    let (program, parameters) = split_command_line(line);
+    let program_w = str_to_wide(&program);
+    let parameters_w = str_to_wide(&parameters);
+    let directory_w = str_to_wide(&directory.to_string_lossy());
+    unsafe {
+        ShellExecuteW(
+            None,
+            w!("open"),
+            PCWSTR(program_w.as_ptr()),
+            if parameters.is_empty() { PCWSTR::null() } else { PCWSTR(parameters_w.as_ptr()) },
+            if directory.as_os_str().is_empty() { PCWSTR::null() } else { PCWSTR(directory_w.as_ptr()) },
+            SW_SHOW,
+        );
+    }
+}
+
+/// フォルダを作業ディレクトリにしてターミナルを開く
+/// Windows Terminal (wt.exe) を優先し、起動できなければ cmd.exe を開く
+/// 作業ディレクトリで渡すため、パスの引用符やエスケープを気にしなくてよい
+fn open_terminal(directory: &Path) {
+    let directory_w = str_to_wide(&directory.to_string_lossy());
+    unsafe {
+        let instance = ShellExecuteW(None, w!("open"), w!("wt.exe"), w!("-d ."), PCWSTR(directory_w.as_ptr()), SW_SHOW);
+        // ShellExecuteW は失敗すると32以下の値を返す
+        if instance.0 as isize <= 32 {
+            ShellExecuteW(None, w!("open"), w!("cmd.exe"), None, PCWSTR(directory_w.as_ptr()), SW_SHOW);
+        }
+    }
+}
+
+/// Everything.exe の場所を探す
+/// インストーラが記録したインストール先（`HKLM\SOFTWARE\voidtools\Everything`）を優先し、
+/// 次に既定のインストール先（Program Files）、最後に PATH の各フォルダを探す
+fn find_everything_exe() -> Option<PathBuf> {
+    let install_dirs = ["ProgramFiles", "ProgramFiles(x86)", "ProgramW6432"]
+        .into_iter()
+        .filter_map(|name| std::env::var_os(name))
+        .map(|dir| PathBuf::from(dir).join("Everything"));
+    let path_dirs = std::env::var_os("PATH").map(|paths| std::env::split_paths(&paths).collect::<Vec<_>>()).unwrap_or_default();
+    everything_install_location()
+        .into_iter()
+        .chain(install_dirs)
+        .chain(path_dirs)
+        .map(|dir| dir.join("Everything.exe"))
+        .find(|exe| exe.is_file())
+}
+
+/// レジストリに記録された Everything のインストール先（`InstallLocation`）
+fn everything_install_location() -> Option<PathBuf> {
+    let mut buffer = [0u16; MAX_PATH as usize];
+    let mut size = std::mem::size_of_val(&buffer) as u32;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            w!("SOFTWARE\\voidtools\\Everything"),
+            w!("InstallLocation"),
+            RRF_RT_REG_SZ,
+            None,
+            Some(buffer.as_mut_ptr() as *mut c_void),
+            Some(&mut size),
+        )
+    };
+    if status != ERROR_SUCCESS {
+        return None;
+    }
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    (len > 0).then(|| PathBuf::from(String::from_utf16_lossy(&buffer[..len])))
+}
 
+/// Everything本体のウィンドウで同じクエリを検索する（`Everything.exe -s <クエリ>`）
+/// 正規表現を使うかも `-regex` / `-noregex` で合わせる（引数は `search::everything_arguments`）。Everything.exe が見つからなければ false
+fn open_in_everything(query: &ComposedQuery) -> bool {
+    let Some(exe) = find_everything_exe() else { return false };
+    let params = search::everything_arguments(query);
+    let exe_w = str_to_wide(&exe.to_string_lossy());
+    let params_w = str_to_wide(&params);
+    let instance = unsafe { ShellExecuteW(None, w!("open"), PCWSTR(exe_w.as_ptr()), PCWSTR(params_w.as_ptr()), None, SW_SHOW) };
+    // ShellExecuteW は失敗すると32以下の値を返す
+    instance.0 as isize > 32
+}
+
+/// ファイルパスを絶対PIDLに変換する
+fn parse_display_name(path: &Path) -> Result<OwningPidl> {
+    let path_wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut pidl = OwningPidl::new();
     unsafe {
         let sfgao: u32 = SFGAO_FILESYSTEM.0;
-        SHParseDisplayName(PCWSTR(path_wide.as_ptr()), None, pidl_absolute.as_mut_ptr(), sfgao, None)?;
+        SHParseDisplayName(PCWSTR(path_wide.as_ptr()), None, pidl.as_mut_ptr(), sfgao, None)?;
     }
+    Ok(pidl)
+}
+
+/// ファイルパスからIShellFolderと相対PIDLを取得する
+fn get_shell_folder_and_pidl(path: &Path) -> Result<(IShellFolder, OwningPidl, *const ITEMIDLIST)> {
+    let pidl_absolute = parse_display_name(path)?;
 
     let mut pidl_relative_ptr: *mut ITEMIDLIST = std::ptr::null_mut();
     let shell_folder: IShellFolder = unsafe { SHBindToParent(pidl_absolute.as_ptr(), Some(&mut pidl_relative_ptr))? };
@@ -1111,81 +5160,39 @@ fn str_to_wide(s: &str) -> Vec<u16> {
     s.encode_utf16().chain(std::iter::once(0)).collect()
 }
 
-fn parse_highlight_text(highlighted_text: &str) -> (String, Vec<(usize, usize)>) {
-    let mut plain_text = String::new();
-    let mut ranges = Vec::new();
-    let mut highlight_start = 0;
-    let mut in_highlight = false;
-
-    let chars: Vec<char> = highlighted_text.chars().collect();
-    let mut i = 0;
-    while i < chars.len() {
-        if chars[i] == '*' {
-            if in_highlight {
-                ranges.push((highlight_start, plain_text.chars().count()));
-                in_highlight = false;
-            } else {
-                highlight_start = plain_text.chars().count();
-                in_highlight = true;
-            }
-            i += 1;
-        } else {
-            plain_text.push(chars[i]);
-            i += 1;
-        }
+/// 検索結果をGitHub形式のMarkdownの表に整形する
+fn format_markdown_table(results: &[FileResult], binary_units: bool) -> String {
+    let mut table = String::from("| 名前 | フォルダ | サイズ | 更新日時 |\n| --- | --- | ---: | --- |\n");
+    for result in results {
+        table.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            escape_markdown_cell(&result.name),
+            escape_markdown_cell(&result.path),
+            format_size(result.size, result.is_folder, binary_units),
+            format_date(result.modified_date),
+        ));
     }
-    (plain_text, ranges)
+    table
 }
 
-/// ファイル/フォルダのアイコンインデックスを取得する
-fn get_icon_index(name: &str, is_folder: bool, himagelist: HIMAGELIST) -> i32 {
-    let mut shfi: SHFILEINFOW = unsafe { std::mem::zeroed() };
-    let file_name_w = str_to_wide(name);
-    let mut flags = SHGFI_USEFILEATTRIBUTES;
-    if himagelist.0 != 0 { flags |= SHGFI_SYSICONINDEX; }
-    let attr = if is_folder { FILE_ATTRIBUTE_DIRECTORY } else { FILE_ATTRIBUTE_NORMAL };
-
+/// クリップボードの文字列を読み取る（文字列がなければNone）
+fn read_clipboard_text(window: HWND) -> Option<String> {
     unsafe {
-        SHGetFileInfoW(PCWSTR(file_name_w.as_ptr()), attr, Some(&mut shfi as *mut _), std::mem::size_of::<SHFILEINFOW>() as u32, flags);
-    }
-    shfi.iIcon
-}
-
-fn format_with_commas(n: u64) -> String {
-    let s = n.to_string();
-    let bytes = s.as_bytes();
-    let mut result = Vec::new();
-    let len = bytes.len();
-    let first = len % 3;
-
-    if first > 0 {
-        result.extend_from_slice(&bytes[..first]);
-        if len > first { result.push(b','); }
-    }
-
-    for (i, chunk) in bytes[first..].chunks(3).enumerate() {
-        result.extend_from_slice(chunk);
-        if i < (len - first) / 3 - 1 { result.push(b','); }
-    }
-    String::from_utf8(result).unwrap_or_default()
-}
-
-/// ファイルサイズをKB単位の文字列にフォーマットする
-fn format_size(bytes: u64) -> String {
-    if bytes == 0 { return "".to_string(); }
-    let kb = (bytes + 1023) / 1024;
-    format!("{} KB", format_with_commas(kb))
-}
-
-/// FILETIME(u64)を"YYYY-MM-DD HH:MM"形式の文字列に変換する
-fn format_date(filetime: u64) -> String {
-    if filetime == 0 { return String::new(); }
-    let ft = FILETIME { dwLowDateTime: (filetime & 0xFFFFFFFF) as u32, dwHighDateTime: (filetime >> 32) as u32 };
-    let mut st = SYSTEMTIME::default();
-    if unsafe { FileTimeToSystemTime(&ft, &mut st).is_ok() } {
-        format!("{:04}-{:02}-{:02} {:02}:{:02}", st.wYear, st.wMonth, st.wDay, st.wHour, st.wMinute)
-    } else {
-        String::new()
+        if IsClipboardFormatAvailable(CF_UNICODETEXT.0 as u32).is_err() { return None; }
+        // 他のアプリがまだ開いている場合は失敗する。次の変更通知を待てばよいので読み直さない
+        OpenClipboard(Some(window)).ok()?;
+        let mut text = None;
+        if let Ok(handle) = GetClipboardData(CF_UNICODETEXT.0 as u32) {
+            let h_mem = HGLOBAL(handle.0);
+            let p_mem = GlobalLock(h_mem) as *const u16;
+            if !p_mem.is_null() {
+                let len = (0..).take_while(|&i| *p_mem.add(i) != 0).count();
+                text = Some(String::from_utf16_lossy(std::slice::from_raw_parts(p_mem, len)));
+                let _ = GlobalUnlock(h_mem);
+            }
+        }
+        let _ = CloseClipboard();
+        text
     }
 }
 
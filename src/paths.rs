@@ -0,0 +1,174 @@
+// --- 設定ファイルと辞書を置くフォルダの決定 ---
+//
+// 通常は `%APPDATA%\migemo-everything` を使い、環境変数 `MIGEMO_EVERYTHING_PORTABLE` が
+// 設定されている場合（ポータブル利用）は実行ファイルと同じフォルダを使う。
+// シェルに渡すパスの区切りの正規化と、explorer.exe に渡す引数の組み立てもここに置く。
+
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+/// ポータブル利用を指定する環境変数（空・`0` 以外の値で有効）
+pub const PORTABLE_ENV_VAR: &str = "MIGEMO_EVERYTHING_PORTABLE";
+/// `%APPDATA%` の下に作るフォルダ名
+pub const APP_DATA_FOLDER_NAME: &str = "migemo-everything";
+/// 拡張長パス形式にしなくても扱えるパスの長さの上限（UTF-16の文字数、終端のNULを含む）
+const MAX_PATH: usize = 260;
+
+/// 設定ファイルと辞書を置くフォルダ
+/// フォルダが存在するとは限らないため、書き込む側で作成すること
+pub fn resolve_data_dir() -> Option<PathBuf> {
+    data_dir_from(env::var_os(PORTABLE_ENV_VAR), env::var_os("APPDATA"), exe_dir())
+}
+
+/// 実行ファイルのあるフォルダ
+pub fn exe_dir() -> Option<PathBuf> {
+    env::current_exe().ok().and_then(|p| p.parent().map(|d| d.to_path_buf()))
+}
+
+/// Windowsのパスの区切りを正規化する（エクスプローラでアイテムを選択する前などに使う）
+/// `/` を `\` にそろえ、重なった区切りを1つにまとめ、末尾の区切りを取り除く
+/// UNCパス（`\\server\share`）と `\\?\` の接頭辞は残し、ドライブ直下は `C:\` の形にする（`C:` だけではカレントディレクトリを指すため）
+pub fn normalize_windows_path(path: &str) -> String {
+    let path = path.replace('/', "\\");
+    let (prefix, rest) = if let Some(rest) = path.strip_prefix(r"\\?\") {
+        (r"\\?\", rest)
+    } else if let Some(rest) = path.strip_prefix(r"\\") {
+        (r"\\", rest)
+    } else if let Some(rest) = path.strip_prefix('\\') {
+        (r"\", rest)
+    } else {
+        ("", path.as_str())
+    };
+    let mut normalized = prefix.to_string();
+    normalized.push_str(&rest.split('\\').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("\\"));
+    if normalized.ends_with(':') {
+        normalized.push('\\');
+    }
+    normalized
+}
+
+/// `explorer.exe /select,<パス>` に渡す引数を作る
+/// パスは区切りを正規化し、MAX_PATHを超えるものは拡張長パス形式にしてから、CommandLineToArgvW の規則で引用符で囲む
+pub fn explorer_select_parameter(path: &str) -> String {
+    format!("/select,{}", quote_command_line_argument(&extended_length_path(&normalize_windows_path(path))))
+}
+
+/// MAX_PATHを超えるパスを拡張長パス形式（`\\?\` / `\\?\UNC\`）に変換する
+fn extended_length_path(path: &str) -> String {
+    if path.encode_utf16().count() < MAX_PATH || path.starts_with(r"\\?\") {
+        return path.to_string();
+    }
+    match path.strip_prefix(r"\\") {
+        Some(unc) => format!(r"\\?\UNC\{}", unc),
+        None => format!(r"\\?\{}", path),
+    }
+}
+
+/// CommandLineToArgvW の規則に従って引数を引用符で囲む
+/// 引用符の直前のバックスラッシュは倍にし、引用符自体は `\"` にエスケープする
+fn quote_command_line_argument(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        if c == '\\' {
+            backslashes += 1;
+            continue;
+        }
+        if c == '"' {
+            quoted.push_str(&"\\".repeat(backslashes * 2 + 1));
+        } else {
+            quoted.push_str(&"\\".repeat(backslashes));
+        }
+        backslashes = 0;
+        quoted.push(c);
+    }
+    // 閉じ引用符の直前のバックスラッシュも倍にする
+    quoted.push_str(&"\\".repeat(backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// 環境変数の値からデータフォルダを決める
+/// `%APPDATA%` が取得できない場合も実行ファイルと同じフォルダにする
+fn data_dir_from(portable: Option<OsString>, app_data: Option<OsString>, exe_dir: Option<PathBuf>) -> Option<PathBuf> {
+    let portable = portable.is_some_and(|value| !value.is_empty() && value != "0");
+    match app_data.filter(|dir| !portable && !dir.is_empty()) {
+        Some(app_data) => Some(PathBuf::from(app_data).join(APP_DATA_FOLDER_NAME)),
+        None => exe_dir,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exe() -> Option<PathBuf> {
+        Some(PathBuf::from(r"C:\tools\migemo-everything"))
+    }
+
+    #[test]
+    fn installed_mode_uses_app_data() {
+        let dir = data_dir_from(None, Some(r"C:\Users\me\AppData\Roaming".into()), exe());
+        assert_eq!(dir, Some(PathBuf::from(r"C:\Users\me\AppData\Roaming").join(APP_DATA_FOLDER_NAME)));
+    }
+
+    #[test]
+    fn portable_mode_uses_exe_dir() {
+        assert_eq!(data_dir_from(Some("1".into()), Some(r"C:\Users\me\AppData\Roaming".into()), exe()), exe());
+    }
+
+    #[test]
+    fn empty_or_zero_does_not_enable_portable_mode() {
+        let app_data = Some(OsString::from(r"C:\Users\me\AppData\Roaming"));
+        let expected = Some(PathBuf::from(r"C:\Users\me\AppData\Roaming").join(APP_DATA_FOLDER_NAME));
+        assert_eq!(data_dir_from(Some("".into()), app_data.clone(), exe()), expected);
+        assert_eq!(data_dir_from(Some("0".into()), app_data, exe()), expected);
+    }
+
+    #[test]
+    fn missing_app_data_falls_back_to_exe_dir() {
+        assert_eq!(data_dir_from(None, None, exe()), exe());
+        assert_eq!(data_dir_from(None, Some("".into()), exe()), exe());
+    }
+
+    #[test]
+    fn drive_root_paths_keep_a_single_separator() {
+        assert_eq!(normalize_windows_path(r"C:\\foo.txt"), r"C:\foo.txt");
+        assert_eq!(normalize_windows_path(r"C:\"), r"C:\");
+        assert_eq!(normalize_windows_path("C:"), r"C:\");
+        assert_eq!(normalize_windows_path("C:/work//a.txt/"), r"C:\work\a.txt");
+        assert_eq!(normalize_windows_path(r"\work\a.txt"), r"\work\a.txt");
+    }
+
+    #[test]
+    fn unc_paths_keep_the_leading_separators() {
+        assert_eq!(normalize_windows_path(r"\\server\share\file.txt"), r"\\server\share\file.txt");
+        assert_eq!(normalize_windows_path(r"\\server\share\\dir\"), r"\\server\share\dir");
+        assert_eq!(normalize_windows_path("//server/share/file.txt"), r"\\server\share\file.txt");
+        assert_eq!(normalize_windows_path(r"\\?\C:\a\\b"), r"\\?\C:\a\b");
+        assert_eq!(normalize_windows_path(r"\\?\UNC\server\share\a"), r"\\?\UNC\server\share\a");
+    }
+    #[test]
+    fn explorer_select_parameter_for_drive_roots_and_unc_paths() {
+        assert_eq!(explorer_select_parameter(r"C:\\a b.txt"), r#"/select,"C:\a b.txt""#);
+        // ドライブ直下は閉じる `"` の前の `\` を重ねる
+        assert_eq!(explorer_select_parameter("C:"), r#"/select,"C:\\""#);
+        assert_eq!(explorer_select_parameter(r"C:\"), r#"/select,"C:\\""#);
+        assert_eq!(explorer_select_parameter(r"\\server\share\\file.txt"), r#"/select,"\\server\share\file.txt""#);
+        assert_eq!(explorer_select_parameter(r"\\server\share\dir\"), r#"/select,"\\server\share\dir""#);
+    }
+
+    #[test]
+    fn explorer_select_parameter_uses_extended_length_paths() {
+        let long_name = "a".repeat(MAX_PATH);
+        assert_eq!(
+            explorer_select_parameter(&format!(r"C:\work\{}", long_name)),
+            format!(r#"/select,"\\?\C:\work\{}""#, long_name)
+        );
+        assert_eq!(
+            explorer_select_parameter(&format!(r"\\server\share\{}", long_name)),
+            format!(r#"/select,"\\?\UNC\server\share\{}""#, long_name)
+        );
+    }
+}
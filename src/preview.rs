@@ -0,0 +1,106 @@
+// --- プレビュー欄に表示するファイル内容 ---
+//
+// 読み込むかどうかの判定と、先頭部分のバイト列を文字列にする処理だけを扱う。
+// Shift_JIS の変換はWindowsのAPIに任せるため、ここではUTF-8/UTF-16として読めるかだけを判定する。
+
+/// 先頭から読み込むバイト数
+pub const PREVIEW_BYTES: usize = 8 * 1024;
+
+/// 内容を読み込むファイルサイズの上限（これより大きいファイルはログなどの巨大ファイルとみなして読まない）
+pub const MAX_PREVIEW_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// テキストとして内容を表示する拡張子（小文字）
+const TEXT_EXTENSIONS: &[&str] = &[
+    "txt", "md", "markdown", "log", "csv", "tsv", "json", "jsonc", "xml", "yaml", "yml", "toml", "ini", "cfg", "conf",
+    "rs", "c", "h", "cpp", "hpp", "cc", "cs", "java", "kt", "go", "py", "rb", "php", "js", "mjs", "ts", "jsx", "tsx",
+    "html", "htm", "css", "scss", "vue", "svelte", "sh", "bat", "cmd", "ps1", "sql", "lua", "swift", "vb", "tex", "org",
+    "gitignore", "editorconfig",
+];
+
+/// ファイル名の拡張子からテキストファイルとみなせるか（大文字・小文字は区別しない）
+/// `.gitignore` のように拡張子だけの名前も拡張子として扱う
+pub fn is_text_file_name(name: &str) -> bool {
+    let Some((_, extension)) = name.rsplit_once('.') else { return false };
+    TEXT_EXTENSIONS.iter().any(|text| text.eq_ignore_ascii_case(extension))
+}
+
+/// 先頭部分をUTF-8またはUTF-16（BOM付き）として文字列にする
+/// NULを含むものはバイナリとみなして `Binary` を、UTF-8として読めないものは Shift_JIS などで読み直すよう `Other` を返す
+/// 途中で切った末尾の不完全な文字は捨てる
+pub fn decode_text(bytes: &[u8]) -> DecodedText {
+    if let Some(body) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        let units: Vec<u16> = body.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect();
+        return DecodedText::Text(String::from_utf16_lossy(&units).trim_end_matches('\u{FFFD}').to_string());
+    }
+    if bytes.contains(&0) {
+        return DecodedText::Binary;
+    }
+    let body = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes);
+    match std::str::from_utf8(body) {
+        Ok(text) => DecodedText::Text(text.to_string()),
+        // 読み込んだ範囲の末尾で文字が切れているだけならその手前までを使う
+        Err(e) if e.error_len().is_none() => DecodedText::Text(String::from_utf8_lossy(&body[..e.valid_up_to()]).into_owned()),
+        Err(_) => DecodedText::Other,
+    }
+}
+
+/// `decode_text` の結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodedText {
+    /// 文字列として読めた
+    Text(String),
+    /// NULを含むためバイナリとみなした
+    Binary,
+    /// UTF-8ではない（Shift_JIS などで読み直す）
+    Other,
+}
+
+/// 改行を CRLF にそろえる（複数行エディットは LF だけでは改行しない）
+pub fn to_crlf(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\r', "\n").replace('\n', "\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_extensions_are_case_insensitive() {
+        assert!(is_text_file_name("README.md"));
+        assert!(is_text_file_name("main.RS"));
+        assert!(is_text_file_name(".gitignore"));
+        assert!(!is_text_file_name("photo.jpg"));
+        assert!(!is_text_file_name("Makefile"));
+    }
+
+    #[test]
+    fn utf8_with_or_without_bom() {
+        assert_eq!(decode_text("日本語".as_bytes()), DecodedText::Text("日本語".to_string()));
+        assert_eq!(decode_text(b"\xEF\xBB\xBFabc"), DecodedText::Text("abc".to_string()));
+    }
+
+    #[test]
+    fn truncated_last_character_is_dropped() {
+        let bytes = "あい".as_bytes();
+        assert_eq!(decode_text(&bytes[..4]), DecodedText::Text("あ".to_string()));
+    }
+
+    #[test]
+    fn utf16_with_bom() {
+        let mut bytes = vec![0xFF, 0xFE];
+        bytes.extend("aあ".encode_utf16().flat_map(|unit| unit.to_le_bytes()));
+        assert_eq!(decode_text(&bytes), DecodedText::Text("aあ".to_string()));
+    }
+
+    #[test]
+    fn nul_bytes_mean_binary_and_invalid_utf8_falls_back() {
+        assert_eq!(decode_text(b"MZ\x90\x00\x03"), DecodedText::Binary);
+        // Shift_JIS の「あ」
+        assert_eq!(decode_text(b"\x82\xA0abc"), DecodedText::Other);
+    }
+
+    #[test]
+    fn newlines_become_crlf() {
+        assert_eq!(to_crlf("a\nb\r\nc\rd"), "a\r\nb\r\nc\r\nd");
+    }
+}
@@ -0,0 +1,773 @@
+// --- Migemoの検索語生成とEverythingのクエリ組み立て ---
+//
+// ウィンドウに依存しない検索ロジックをまとめる。
+// GUI以外（CLIなど）からも同じ規則でクエリを組み立てられるよう、状態ではなく値を受け取る。
+
+use crate::paths;
+use everything_sdk::ergo::SortType;
+use rustmigemo::migemo::{
+    compact_dictionary::CompactDictionary, query::query, regex_generator::RegexOperator,
+};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Migemo辞書のファイル名
+pub const DICTIONARY_FILE_NAME: &str = "migemo-compact-dict";
+
+/// クリップボードから検索する語の最大文字数（これより長いものはファイル名ではなく文章とみなす）
+pub const CLIPBOARD_TERM_MAX_CHARS: usize = 260;
+
+/// Everythingに渡す最終的な検索条件
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComposedQuery {
+    /// searcher.set_search に渡す文字列
+    pub text: String,
+    /// searcher.set_regex に渡す値
+    pub regex: bool,
+}
+
+/// 並べ替えのキー（列として表示していない項目も含む）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    Path,
+    Size,
+    Extension,
+    DateModified,
+    DateCreated,
+    DateAccessed,
+    RunCount,
+    Attributes,
+}
+
+impl SortKey {
+    /// メニューに並べる順のすべてのキー
+    pub const ALL: [SortKey; 9] = [
+        SortKey::Name,
+        SortKey::Path,
+        SortKey::Size,
+        SortKey::Extension,
+        SortKey::DateModified,
+        SortKey::DateCreated,
+        SortKey::DateAccessed,
+        SortKey::RunCount,
+        SortKey::Attributes,
+    ];
+
+    /// メニューとステータスバーに表示する名前
+    pub fn label(self) -> &'static str {
+        match self {
+            SortKey::Name => "名前",
+            SortKey::Path => "フォルダ",
+            SortKey::Size => "サイズ",
+            SortKey::Extension => "拡張子",
+            SortKey::DateModified => "更新日時",
+            SortKey::DateCreated => "作成日時",
+            SortKey::DateAccessed => "アクセス日時",
+            SortKey::RunCount => "実行回数",
+            SortKey::Attributes => "属性",
+        }
+    }
+
+    /// 同順位の並べ替えに使える2番目のキー（結果に値を持つもの）
+    pub const SECONDARY: [SortKey; 6] = [
+        SortKey::Name,
+        SortKey::Path,
+        SortKey::Size,
+        SortKey::Extension,
+        SortKey::DateModified,
+        SortKey::DateCreated,
+    ];
+
+    /// 設定ファイルに保存する値
+    pub fn config_value(self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Path => "path",
+            SortKey::Size => "size",
+            SortKey::Extension => "extension",
+            SortKey::DateModified => "date_modified",
+            SortKey::DateCreated => "date_created",
+            SortKey::DateAccessed => "date_accessed",
+            SortKey::RunCount => "run_count",
+            SortKey::Attributes => "attributes",
+        }
+    }
+
+    /// 設定ファイルの値からキーを得る
+    pub fn from_config_value(value: &str) -> Option<Self> {
+        SortKey::ALL.into_iter().find(|key| key.config_value() == value)
+    }
+
+    /// 対応するEverythingの並べ替え指定
+    pub fn sort_type(self, descending: bool) -> SortType {
+        match (self, descending) {
+            (SortKey::Name, false) => SortType::EVERYTHING_SORT_NAME_ASCENDING,
+            (SortKey::Name, true) => SortType::EVERYTHING_SORT_NAME_DESCENDING,
+            (SortKey::Path, false) => SortType::EVERYTHING_SORT_PATH_ASCENDING,
+            (SortKey::Path, true) => SortType::EVERYTHING_SORT_PATH_DESCENDING,
+            (SortKey::Size, false) => SortType::EVERYTHING_SORT_SIZE_ASCENDING,
+            (SortKey::Size, true) => SortType::EVERYTHING_SORT_SIZE_DESCENDING,
+            (SortKey::Extension, false) => SortType::EVERYTHING_SORT_EXTENSION_ASCENDING,
+            (SortKey::Extension, true) => SortType::EVERYTHING_SORT_EXTENSION_DESCENDING,
+            (SortKey::DateModified, false) => SortType::EVERYTHING_SORT_DATE_MODIFIED_ASCENDING,
+            (SortKey::DateModified, true) => SortType::EVERYTHING_SORT_DATE_MODIFIED_DESCENDING,
+            (SortKey::DateCreated, false) => SortType::EVERYTHING_SORT_DATE_CREATED_ASCENDING,
+            (SortKey::DateCreated, true) => SortType::EVERYTHING_SORT_DATE_CREATED_DESCENDING,
+            (SortKey::DateAccessed, false) => SortType::EVERYTHING_SORT_DATE_ACCESSED_ASCENDING,
+            (SortKey::DateAccessed, true) => SortType::EVERYTHING_SORT_DATE_ACCESSED_DESCENDING,
+            (SortKey::RunCount, false) => SortType::EVERYTHING_SORT_RUN_COUNT_ASCENDING,
+            (SortKey::RunCount, true) => SortType::EVERYTHING_SORT_RUN_COUNT_DESCENDING,
+            (SortKey::Attributes, false) => SortType::EVERYTHING_SORT_ATTRIBUTES_ASCENDING,
+            (SortKey::Attributes, true) => SortType::EVERYTHING_SORT_ATTRIBUTES_DESCENDING,
+        }
+    }
+}
+
+/// 結果に含めるアイテムの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ItemKind {
+    #[default]
+    All,
+    Files,
+    Folders,
+}
+
+impl ItemKind {
+    /// メニューに並べる順のすべての種類
+    pub const ALL: [ItemKind; 3] = [ItemKind::All, ItemKind::Files, ItemKind::Folders];
+
+    /// 切り替えボタンで順に切り替えたときの次の種類
+    pub fn next(self) -> Self {
+        match self {
+            ItemKind::All => ItemKind::Files,
+            ItemKind::Files => ItemKind::Folders,
+            ItemKind::Folders => ItemKind::All,
+        }
+    }
+
+    /// 切り替えボタンに表示する名前
+    pub fn button_text(self) -> &'static str {
+        match self {
+            ItemKind::All => "All",
+            ItemKind::Files => "Files",
+            ItemKind::Folders => "Folders",
+        }
+    }
+
+    /// メニューとステータスバーに表示する名前
+    pub fn label(self) -> &'static str {
+        match self {
+            ItemKind::All => "すべて",
+            ItemKind::Files => "ファイルのみ",
+            ItemKind::Folders => "フォルダのみ",
+        }
+    }
+
+    /// 設定ファイルに保存する値
+    pub fn config_value(self) -> &'static str {
+        match self {
+            ItemKind::All => "all",
+            ItemKind::Files => "files",
+            ItemKind::Folders => "folders",
+        }
+    }
+
+    /// 設定ファイルの値から種類を得る
+    pub fn from_config_value(value: &str) -> Option<Self> {
+        ItemKind::ALL.into_iter().find(|kind| kind.config_value() == value)
+    }
+
+    /// 絞り込みに使うEverythingの検索関数
+    fn clause(self) -> Option<&'static str> {
+        match self {
+            ItemKind::All => None,
+            ItemKind::Files => Some("file:"),
+            ItemKind::Folders => Some("folder:"),
+        }
+    }
+}
+
+/// 検索語の中で、列に対応する絞り込み関数（`size:` / `dm:` / `dc:`）が使われているか
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ColumnFilters {
+    pub size: bool,
+    pub date_modified: bool,
+    pub date_created: bool,
+}
+
+/// 正規表現でない検索語から、列に対応する絞り込み関数を探す
+/// `!size:` のような否定や、`<` `|` で組み合わせた語の中も対象にする
+pub fn column_filters(term: &str) -> ColumnFilters {
+    let mut filters = ColumnFilters::default();
+    for token in term.split(|c: char| c.is_whitespace() || c == '|') {
+        let token = token.trim_start_matches(['!', '<']).to_lowercase();
+        let has = |names: &[&str]| names.iter().any(|name| token.starts_with(name));
+        filters.size |= has(&["size:"]);
+        filters.date_modified |= has(&["dm:", "datemodified:"]);
+        filters.date_created |= has(&["dc:", "datecreated:"]);
+    }
+    filters
+}
+
+/// 1回の検索結果を左右する条件一式
+/// 同じ条件なら同じ結果になるため、直前の検索との比較にも使う
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchRequest {
+    pub query: ComposedQuery,
+    pub sort_key: SortKey,
+    pub sort_descending: bool,
+    /// `sort_key` が同じ値の結果を並べる2番目のキー（常に昇順）
+    pub secondary_sort: Option<SortKey>,
+}
+
+/// Migemo辞書を読み込む（データフォルダ、実行ファイルのフォルダ、カレントフォルダの順に探す）
+/// 配布物では辞書が実行ファイルの隣にあるため、データフォルダに置いていなくても見つかる
+pub fn init_migemo_dict() -> Option<CompactDictionary> {
+    let paths = [paths::resolve_data_dir(), paths::exe_dir(), Some(PathBuf::new())];
+    paths.into_iter().flatten().find_map(|dir| load_migemo_dict(&dir.join(DICTIONARY_FILE_NAME)))
+}
+
+/// 指定パスのMigemo辞書を読み込む
+pub fn load_migemo_dict(path: &Path) -> Option<CompactDictionary> {
+    fs::read(path).ok().map(|buf| CompactDictionary::new(&buf))
+}
+
+/// ローマ字などの入力をMigemoで正規表現に展開する
+/// 展開する語がなければ辞書を引かず、入力どおりに一致する正規表現にする（不要に大きな正規表現でEverythingの検索を遅くしない）
+pub fn migemo_query(text: &str, dict: &CompactDictionary) -> String {
+    if !has_expandable_word(text) {
+        return escape_regex(text);
+    }
+    query(text.to_string(), dict, &RegexOperator::Default)
+}
+
+/// Migemoで展開される語（英字の並び）を含むか
+/// 数字や記号だけの入力や、すでに日本語で書いた入力は、辞書を引いても入力どおりの語にしか一致しない
+/// （Migemoが加える全角の数字・記号には一致しなくなる）
+pub fn has_expandable_word(text: &str) -> bool {
+    text.chars().any(|c| c.is_ascii_alphabetic())
+}
+
+/// 正規表現の特殊文字をエスケープして、入力どおりの文字列に一致させる
+pub fn escape_regex(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()[]{}|^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// 入力した正規表現のうち、ローマ字などの英字の並びだけをMigemoで展開する
+/// `^houkoku_\d+` の `\d+` のように、正規表現として書いた部分はそのまま残る
+pub fn migemo_regex_query(pattern: &str, dict: &CompactDictionary) -> String {
+    expand_words_in_regex(pattern, |word| migemo_query(word, dict))
+}
+
+/// 正規表現の中の英字の並びを `expand` の結果に置き換える
+/// エスケープ（`\d` や `\x41` など）・文字クラス `[...]`・量指定子 `{n,m}`・`(?i)` などのグループの指定は展開しない
+/// 直後に量指定子が付く最後の1文字は、置き換えると量指定子の掛かる範囲が変わるため展開しない
+/// 展開した語は `(?:...)` で囲み、前後の正規表現と混ざらないようにする
+pub fn expand_words_in_regex(pattern: &str, mut expand: impl FnMut(&str) -> String) -> String {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut expanded = String::new();
+    let mut in_class = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '\\' {
+            let end = escape_end(&chars, i);
+            expanded.extend(&chars[i..end]);
+            i = end;
+            continue;
+        }
+        if in_class {
+            in_class = c != ']';
+            expanded.push(c);
+            i += 1;
+            continue;
+        }
+        match c {
+            '[' => {
+                in_class = true;
+                expanded.push(c);
+                i += 1;
+                // `[]a]` や `[^]a]` の先頭の `]` はクラスの終わりではなく文字
+                if chars.get(i) == Some(&'^') { expanded.push('^'); i += 1; }
+                if chars.get(i) == Some(&']') { expanded.push(']'); i += 1; }
+            }
+            '{' => {
+                let end = chars[i..].iter().position(|&c| c == '}').map_or(chars.len(), |offset| i + offset + 1);
+                expanded.extend(&chars[i..end]);
+                i = end;
+            }
+            '(' if chars.get(i + 1) == Some(&'?') => {
+                // `(?i)` `(?i:` `(?<name>` の英字はフラグや名前
+                i += 2;
+                expanded.push_str("(?");
+                while i < chars.len() && (chars[i].is_ascii_alphabetic() || chars[i] == '-' || chars[i] == '<') {
+                    expanded.push(chars[i]);
+                    i += 1;
+                }
+                if chars.get(i) == Some(&'>') { expanded.push('>'); i += 1; }
+            }
+            c if c.is_ascii_alphabetic() => {
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_alphabetic() { i += 1; }
+                let mut word_end = i;
+                if matches!(chars.get(i), Some('*' | '+' | '?' | '{')) { word_end -= 1; }
+                if word_end > start {
+                    let word: String = chars[start..word_end].iter().collect();
+                    let pattern = expand(&word);
+                    if pattern == word {
+                        expanded.push_str(&word);
+                    } else {
+                        expanded.push_str(&format!("(?:{})", pattern));
+                    }
+                }
+                expanded.extend(&chars[word_end..i]);
+            }
+            _ => {
+                expanded.push(c);
+                i += 1;
+            }
+        }
+    }
+    expanded
+}
+
+/// `chars[start]` の `\` から始まるエスケープの終わりの位置
+/// `\x41` `\u3042` `\x{...}` `\p{L}` `\k<name>` のように引数を取るものは引数まで含める
+fn escape_end(chars: &[char], start: usize) -> usize {
+    let Some(&kind) = chars.get(start + 1) else { return chars.len() };
+    let mut end = start + 2;
+    let closing = match chars.get(end) {
+        Some('{') => Some('}'),
+        Some('<') if kind == 'k' => Some('>'),
+        _ => None,
+    };
+    if let Some(closing) = closing.filter(|_| "xupPNkg".contains(kind)) {
+        return chars[end..].iter().position(|&c| c == closing).map_or(chars.len(), |offset| end + offset + 1);
+    }
+    let max_args = match kind {
+        'x' => 2,
+        'u' => 4,
+        'p' | 'P' => 1,
+        _ => 0,
+    };
+    while end < chars.len() && end - start - 2 < max_args && (kind == 'p' || kind == 'P' || chars[end].is_ascii_hexdigit()) {
+        end += 1;
+    }
+    end
+}
+
+/// ワイルドカード検索用の検索語を作る
+/// EverythingのSDKにはワイルドカード専用の設定がなく、正規表現OFFの検索では `*` `?` がそのまま
+/// ワイルドカードとして解釈される。`wfn:` を付けてファイル名全体との一致にし、`report_*.pdf` が
+/// グロブと同じ意味になるようにする。検索範囲・除外条件はほかのモードと同様に前にAND結合される
+pub fn wildcard_term(term: &str) -> String {
+    term.split_whitespace()
+        .map(|token| if token.contains(['*', '?']) { format!("wfn:\"{}\"", token) } else { token.to_string() })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// クリップボードの文字列を、検索ボックスに入れる1行の検索語にする
+/// 最初の空でない行だけを使い、前後の空白と引用符（エクスプローラの「パスのコピー」で付く）を除く
+/// 長すぎるものはファイル名らしくないため使わない
+pub fn clipboard_search_term(text: &str) -> Option<String> {
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let term: String = line.trim_matches('"').trim().chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+    if term.is_empty() || term.chars().count() > CLIPBOARD_TERM_MAX_CHARS { return None; }
+    Some(term)
+}
+
+/// 貼り付ける文字列の改行を空白にまとめ、1行の入力にする
+/// Everythingのクエリに改行は使えないため、前後の改行は除き、途中の改行（連続する空行も）は1つの空白にする
+pub fn collapse_newlines(text: &str) -> String {
+    text.split(['\r', '\n']).filter(|line| !line.is_empty()).collect::<Vec<_>>().join(" ")
+}
+
+/// 通常検索の入力を、Everythingの演算子として解釈されない1つの語にする
+/// 全体を `"` で囲むと、空白（AND）・`|`（OR）・`!`（NOT）・`<` `>`（グループ）が文字として扱われる
+/// `"` 自体はファイル名に使えない文字なので取り除く
+pub fn literal_term(term: &str) -> String {
+    let text = term.trim().replace('"', "");
+    if text.is_empty() { return text; }
+    format!("\"{}\"", text)
+}
+
+/// 正規表現を `regex:` 修飾子の形にする（他の条件と並べるため）
+/// `"` は修飾子の引用符を閉じてしまうため、同じ文字に一致する `\x22` に置き換える
+pub fn regex_term(term: &str) -> String {
+    format!("regex:\"{}\"", term.replace('"', "\\x22"))
+}
+
+/// アクセント記号（é と e など）を区別する修飾子を検索語に付け、1つの条件にする
+/// 区別しないのがEverythingの既定なので、区別しない場合は修飾子を付けない
+/// 修飾子は直後の1語にしか掛からないため、正規表現は `regex:` 修飾子の形にし、それ以外は `< >` でまとめる
+/// `size:>1mb` などの関数は `>` がまとめの終わりと解釈されるため、まとめずに後ろへ並べる（空白はANDなので順序は問わない）
+pub fn diacritics_term(term: &str, regex: bool, match_diacritics: bool) -> String {
+    if regex {
+        let term = regex_term(term);
+        return if match_diacritics { format!("diacritics:{}", term) } else { term };
+    }
+    if !match_diacritics { return term.to_string(); }
+    let (functions, words): (Vec<&str>, Vec<&str>) = split_words(term).into_iter().partition(|word| is_function(word));
+    let mut clauses = Vec::new();
+    if !words.is_empty() {
+        clauses.push(format!("diacritics:<{}>", words.join(" ")));
+    }
+    clauses.extend(functions.iter().map(|function| function.to_string()));
+    clauses.join(" ")
+}
+
+/// 検索語を空白で語に分ける（`"` で囲んだ中の空白では分けない）
+fn split_words(term: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    for (i, c) in term.char_indices() {
+        if c == '"' { quoted = !quoted; }
+        if c.is_whitespace() && !quoted {
+            if let Some(from) = start.take() { words.push(&term[from..i]); }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(from) = start { words.push(&term[from..]); }
+    words
+}
+
+/// `size:>1mb` や `!dm:today` のような検索関数の語か
+fn is_function(word: &str) -> bool {
+    word.trim_start_matches('!').split_once(':').is_some_and(|(name, _)| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// 検索語をファイル名ではなくパス全体と照合させる
+/// `path:` 修飾子は他の修飾子と重ねられるため、`diacritics_term` の結果の前に付ければよい
+pub fn path_term(term: &str) -> String {
+    format!("path:{}", term)
+}
+
+/// 検索範囲・除外フォルダ・アイテムの種類から、検索語とAND結合するEverythingの検索条件を作る
+/// 種類はページ単位で後から除くと件数やページ位置が狂うため、Everythingの側で絞り込む
+pub fn filter_clauses(scope: Option<&Path>, exclude_paths: &[String], kind: ItemKind) -> Vec<String> {
+    let mut clauses = Vec::new();
+    if let Some(clause) = kind.clause() {
+        clauses.push(clause.to_string());
+    }
+    if let Some(scope) = scope {
+        let mut folder = scope.to_string_lossy().into_owned();
+        if !folder.ends_with('\\') { folder.push('\\'); }
+        clauses.push(format!("path:\"{}\"", folder));
+    }
+    for exclude in exclude_paths {
+        clauses.push(format!("!path:\"{}\"", exclude));
+    }
+    clauses
+}
+
+/// フォルダの直下にあるアイテムだけに一致する条件（サブフォルダの中は含まない）
+/// Everythingの `parent:` は末尾の区切りのないパスと照合するため、`C:\` は `C:` にする
+pub fn children_clause(folder: &Path) -> String {
+    format!("parent:\"{}\"", folder.to_string_lossy().trim_end_matches('\\'))
+}
+
+/// 検索語（Migemo展開済み）と追加条件から、Everythingに渡すクエリを組み立てる
+/// 追加条件がある場合、正規表現は条件に混ぜず `regex:` 修飾子で検索語だけに適用する
+pub fn compose_query(term: &str, regex: bool, clauses: &[String]) -> ComposedQuery {
+    if clauses.is_empty() {
+        return ComposedQuery { text: term.to_string(), regex };
+    }
+    // 検索語なしで条件だけを問い合わせる（フォルダの中の一覧）
+    if term.is_empty() {
+        return ComposedQuery { text: clauses.join(" "), regex: false };
+    }
+
+    let term_clause = if regex { regex_term(term) } else { term.to_string() };
+    ComposedQuery { text: format!("{} {}", clauses.join(" "), term_clause), regex: false }
+}
+
+/// Everything本体に同じ検索をさせるコマンドライン引数（`-regex|-noregex -s "<クエリ>"`）
+/// Everything.exe は `\` をエスケープ文字として扱わないので、`path:"C:\x\"` のように
+/// `"` の直前にある `\` もそのまま渡す。クエリ中の `"` は Everything の規則どおり `"""` にする
+pub fn everything_arguments(query: &ComposedQuery) -> String {
+    let mode = if query.regex { "-regex" } else { "-noregex" };
+    format!("{} -s \"{}\"", mode, query.text.replace('"', "\"\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// リポジトリ直下の辞書を読み込む
+    /// 辞書は配布サイトから別途ダウンロードするものなので、辞書を使うテストは `#[ignore]` にしておき、
+    /// 辞書を置いてから `cargo test -- --ignored` で実行する（省略したことが結果に表れるように）
+    fn test_dict() -> CompactDictionary {
+        load_migemo_dict(&Path::new(env!("CARGO_MANIFEST_DIR")).join(DICTIONARY_FILE_NAME))
+            .unwrap_or_else(|| panic!("{} をリポジトリ直下に置いてください", DICTIONARY_FILE_NAME))
+    }
+
+    /// 展開した箇所が分かるよう大文字にする
+    fn upper(pattern: &str) -> String {
+        expand_words_in_regex(pattern, |word| word.to_uppercase())
+    }
+
+    #[test]
+    fn only_words_in_a_regex_are_expanded() {
+        assert_eq!(upper("^houkoku_\\d+"), "^(?:HOUKOKU)_\\d+");
+        assert_eq!(upper("[a-z]+\\.txt$"), "[a-z]+\\.(?:TXT)$");
+        assert_eq!(upper("[^]ab]cd"), "[^]ab](?:CD)");
+        assert_eq!(upper("kensaku|houkoku"), "(?:KENSAKU)|(?:HOUKOKU)");
+    }
+
+    #[test]
+    fn escapes_and_group_options_are_kept() {
+        assert_eq!(upper("\\bfoo\\x41bc"), "\\b(?:FOO)\\x41(?:BC)");
+        assert_eq!(upper("\\p{L}ab"), "\\p{L}(?:AB)");
+        assert_eq!(upper("(?i)abc"), "(?i)(?:ABC)");
+        assert_eq!(upper("(?<name>abc)"), "(?<name>(?:ABC))");
+        assert_eq!(upper("(?:abc)"), "(?:(?:ABC))");
+    }
+
+    #[test]
+    fn quantified_last_letter_is_not_expanded() {
+        assert_eq!(upper("ab+"), "(?:A)b+");
+        assert_eq!(upper("a*"), "a*");
+        assert_eq!(upper("x{2,3}yz"), "x{2,3}(?:YZ)");
+    }
+
+    #[test]
+    fn unchanged_words_are_not_wrapped() {
+        assert_eq!(expand_words_in_regex("abc\\d", |word| word.to_string()), "abc\\d");
+    }
+
+    #[test]
+    #[ignore = "リポジトリ直下に migemo-compact-dict が必要"]
+    fn migemo_expands_romaji_to_kanji() {
+        let dict = test_dict();
+        let pattern = migemo_query("kensaku", &dict);
+        assert!(pattern.contains("検索"), "{}", pattern);
+        assert!(pattern.contains("けんさく"), "{}", pattern);
+        assert!(pattern.contains("ケンサク"), "{}", pattern);
+    }
+
+    #[test]
+    #[ignore = "リポジトリ直下に migemo-compact-dict が必要"]
+    fn migemo_keeps_the_original_input() {
+        let dict = test_dict();
+        let pattern = migemo_query("toukyou", &dict);
+        assert!(pattern.contains("東京"), "{}", pattern);
+        assert!(pattern.contains("toukyou"), "{}", pattern);
+    }
+
+    #[test]
+    fn input_without_romaji_is_not_expanded() {
+        assert!(!has_expandable_word("報告書"));
+        assert!(!has_expandable_word("2024-03_01"));
+        assert!(!has_expandable_word("ｋｅｎｓａｋｕ"));
+        assert!(has_expandable_word("報告v2"));
+        assert!(has_expandable_word("Kensaku"));
+        assert_eq!(escape_regex("報告書"), "報告書");
+        assert_eq!(escape_regex("(1.2)+[a]|b$"), "\\(1\\.2\\)\\+\\[a\\]\\|b\\$");
+    }
+
+    #[test]
+    #[ignore = "リポジトリ直下に migemo-compact-dict が必要"]
+    fn migemo_returns_kanji_input_verbatim() {
+        let dict = test_dict();
+        assert_eq!(migemo_query("報告書", &dict), "報告書");
+        assert_eq!(migemo_query("2024.03", &dict), "2024\\.03");
+    }
+
+    #[test]
+    fn wildcard_tokens_match_whole_file_name() {
+        assert_eq!(wildcard_term("report_*.pdf 2024"), "wfn:\"report_*.pdf\" 2024");
+        assert_eq!(wildcard_term("  a?c  "), "wfn:\"a?c\"");
+        assert_eq!(wildcard_term(""), "");
+    }
+
+    #[test]
+    fn clipboard_text_becomes_a_single_line_term() {
+        assert_eq!(clipboard_search_term("  report.docx \r\n"), Some("report.docx".to_string()));
+        assert_eq!(clipboard_search_term("\r\n\"C:\\Users\\memo.txt\"\r\nsecond"), Some("C:\\Users\\memo.txt".to_string()));
+        assert_eq!(clipboard_search_term("a\tb"), Some("a b".to_string()));
+        assert_eq!(clipboard_search_term(" \r\n\t"), None);
+        assert_eq!(clipboard_search_term("\"\""), None);
+        assert_eq!(clipboard_search_term(&"x".repeat(CLIPBOARD_TERM_MAX_CHARS + 1)), None);
+    }
+
+    #[test]
+    fn pasted_newlines_collapse_to_spaces() {
+        assert_eq!(collapse_newlines("report\r\n"), "report");
+        assert_eq!(collapse_newlines("a.txt\r\nb.txt\nc.txt"), "a.txt b.txt c.txt");
+        assert_eq!(collapse_newlines("\r\n\r\nkensaku\r\n\r\nhoukoku\r"), "kensaku houkoku");
+        assert_eq!(collapse_newlines(" spaced  words "), " spaced  words ");
+        assert_eq!(collapse_newlines("\r\n"), "");
+    }
+
+    #[test]
+    fn literal_term_quotes_operators() {
+        assert_eq!(literal_term("report | draft.txt"), "\"report | draft.txt\"");
+        assert_eq!(literal_term(" <a> !b "), "\"<a> !b\"");
+        assert_eq!(literal_term("say \"hi\""), "\"say hi\"");
+        assert_eq!(literal_term("  "), "");
+    }
+
+    #[test]
+    fn literal_term_is_not_an_or_expression() {
+        let query = compose_query(&literal_term("report | draft.txt"), false, &[]);
+        assert_eq!(query, ComposedQuery { text: "\"report | draft.txt\"".to_string(), regex: false });
+
+        let clauses = filter_clauses(None, &[], ItemKind::Files);
+        let query = compose_query(&literal_term("report | draft.txt"), false, &clauses);
+        assert_eq!(query.text, "file: \"report | draft.txt\"");
+    }
+
+    #[test]
+    fn diacritics_modifier_covers_the_whole_term() {
+        assert_eq!(diacritics_term("cafe menu", false, false), "cafe menu");
+        assert_eq!(diacritics_term("café \"a b\"", false, true), "diacritics:<café \"a b\">");
+        assert_eq!(diacritics_term("caf(e|é)", true, false), "regex:\"caf(e|é)\"");
+        assert_eq!(diacritics_term("caf(e|é)", true, true), "diacritics:regex:\"caf(e|é)\"");
+    }
+
+    #[test]
+    fn diacritics_modifier_leaves_functions_outside_the_group() {
+        assert_eq!(diacritics_term("café size:>1mb !dm:today", false, true), "diacritics:<café> size:>1mb !dm:today");
+        assert_eq!(diacritics_term("size:>1mb", false, true), "size:>1mb");
+        assert_eq!(diacritics_term("size:>1mb", false, false), "size:>1mb");
+    }
+
+    #[test]
+    fn quotes_in_a_regex_are_escaped() {
+        assert_eq!(regex_term("say \"hi\""), "regex:\"say \\x22hi\\x22\"");
+        let query = compose_query("say \"hi\"", true, &filter_clauses(None, &[], ItemKind::Files));
+        assert_eq!(query, ComposedQuery { text: "file: regex:\"say \\x22hi\\x22\"".to_string(), regex: false });
+        // 条件がなければEverythingの正規表現フラグで検索するため、そのまま渡す
+        assert_eq!(compose_query("say \"hi\"", true, &[]), ComposedQuery { text: "say \"hi\"".to_string(), regex: true });
+    }
+
+    #[test]
+    fn diacritics_term_is_composed_after_filters() {
+        let clauses = filter_clauses(None, &[], ItemKind::Files);
+        let query = compose_query(&diacritics_term("a|b", true, true), false, &clauses);
+        assert_eq!(query, ComposedQuery { text: "file: diacritics:regex:\"a|b\"".to_string(), regex: false });
+    }
+
+    #[test]
+    fn column_filters_detect_size_and_date_functions() {
+        assert_eq!(column_filters("report"), ColumnFilters::default());
+        assert_eq!(
+            column_filters("report size:>1mb DM:today"),
+            ColumnFilters { size: true, date_modified: true, date_created: false }
+        );
+        assert_eq!(
+            column_filters("<a|datecreated:2024> !size:0"),
+            ColumnFilters { size: true, date_modified: false, date_created: true }
+        );
+    }
+
+    #[test]
+    fn column_filters_ignore_words_that_only_contain_the_name() {
+        assert_eq!(column_filters("mysize:1 adm:x"), ColumnFilters::default());
+    }
+
+    #[test]
+    fn filter_clauses_add_trailing_separator_to_scope() {
+        let excludes = vec!["C:\\Windows".to_string()];
+        assert_eq!(
+            filter_clauses(Some(Path::new("C:\\Users")), &excludes, ItemKind::All),
+            vec!["path:\"C:\\Users\\\"".to_string(), "!path:\"C:\\Windows\"".to_string()]
+        );
+        assert_eq!(filter_clauses(Some(Path::new("D:\\")), &[], ItemKind::All), vec!["path:\"D:\\\"".to_string()]);
+        assert!(filter_clauses(None, &[], ItemKind::All).is_empty());
+    }
+
+    #[test]
+    fn item_kind_adds_file_or_folder_function() {
+        assert_eq!(filter_clauses(None, &[], ItemKind::Files), vec!["file:".to_string()]);
+        assert_eq!(
+            filter_clauses(Some(Path::new("C:\\src")), &[], ItemKind::Folders),
+            vec!["folder:".to_string(), "path:\"C:\\src\\\"".to_string()]
+        );
+        let query = compose_query("a|b", true, &filter_clauses(None, &[], ItemKind::Folders));
+        assert_eq!(query, ComposedQuery { text: "folder: regex:\"a|b\"".to_string(), regex: false });
+    }
+
+    #[test]
+    fn item_kind_cycles_and_round_trips_through_config() {
+        let mut kind = ItemKind::default();
+        for _ in 0..3 {
+            assert_eq!(ItemKind::from_config_value(kind.config_value()), Some(kind));
+            kind = kind.next();
+        }
+        assert_eq!(kind, ItemKind::All);
+        assert_eq!(ItemKind::from_config_value("dirs"), None);
+    }
+
+    #[test]
+    fn path_term_applies_only_to_the_term() {
+        let term = path_term(&diacritics_term("houkoku|報告", true, false));
+        assert_eq!(term, "path:regex:\"houkoku|報告\"");
+
+        let clauses = filter_clauses(Some(Path::new("C:\\src")), &[], ItemKind::Files);
+        let query = compose_query(&term, false, &clauses);
+        assert_eq!(query.text, "file: path:\"C:\\src\\\" path:regex:\"houkoku|報告\"");
+        assert!(!query.regex);
+    }
+
+    #[test]
+    fn narrowing_term_is_anded_as_a_clause() {
+        let mut clauses = filter_clauses(None, &[], ItemKind::Files);
+        clauses.push(diacritics_term("2024", false, true));
+        let query = compose_query("a|b", true, &clauses);
+        assert_eq!(query.text, "file: diacritics:<2024> regex:\"a|b\"");
+        assert!(!query.regex);
+    }
+
+    #[test]
+    fn compose_query_without_clauses_passes_regex_through() {
+        let query = compose_query("a|b", true, &[]);
+        assert_eq!(query, ComposedQuery { text: "a|b".to_string(), regex: true });
+    }
+
+    #[test]
+    fn everything_arguments_keep_backslashes_before_quotes() {
+        let query = ComposedQuery { text: r#"path:"C:\x\" houkoku"#.to_string(), regex: false };
+        assert_eq!(everything_arguments(&query), r#"-noregex -s "path:"""C:\x\""" houkoku""#);
+        let query = ComposedQuery { text: r"C:\".to_string(), regex: false };
+        assert_eq!(everything_arguments(&query), r#"-noregex -s "C:\""#);
+    }
+
+    #[test]
+    fn everything_arguments_select_regex_mode() {
+        let query = ComposedQuery { text: "a|b".to_string(), regex: true };
+        assert_eq!(everything_arguments(&query), r#"-regex -s "a|b""#);
+    }
+
+    #[test]
+    fn compose_query_with_clauses_uses_regex_modifier() {
+        let clauses = vec!["path:\"C:\\Users\\\"".to_string()];
+        let query = compose_query("a|b", true, &clauses);
+        assert_eq!(query.text, "path:\"C:\\Users\\\" regex:\"a|b\"");
+        assert!(!query.regex);
+
+        let query = compose_query("memo", false, &clauses);
+        assert_eq!(query.text, "path:\"C:\\Users\\\" memo");
+        assert!(!query.regex);
+    }
+
+    #[test]
+    fn empty_term_lists_the_folder_children() {
+        let clauses = vec![children_clause(Path::new("C:\\Users\\"))];
+        assert_eq!(clauses[0], "parent:\"C:\\Users\"");
+        assert_eq!(compose_query("", false, &clauses).text, "parent:\"C:\\Users\"");
+        assert_eq!(children_clause(Path::new("C:\\")), "parent:\"C:\"");
+    }
+}